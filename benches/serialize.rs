@@ -0,0 +1,49 @@
+//! Times packet serialization on the `samples/*_huge.bin` packets
+//!
+//! cargo bench --bench serialize
+
+use std::{fs, hint::black_box, time::{Duration, Instant}};
+
+use rustdns::parser::{packet::DNSPacketParser, CompressionTable};
+
+const ITERATIONS: u32 = 200_000;
+
+/// Average time `f` takes, after running it for a while to warm up
+fn time(mut f: impl FnMut()) -> Duration {
+    for _ in 0..ITERATIONS / 10 {
+        f();
+    }
+
+    let start = Instant::now();
+
+    for _ in 0..ITERATIONS {
+        f();
+    }
+
+    start.elapsed() / ITERATIONS
+}
+
+fn main() {
+    for sample in ["query_packet_huge.bin", "response_packet_huge.bin"] {
+        let raw = fs::read(format!("./samples/{}", sample))
+            .expect("Should read sample file");
+        let packet = DNSPacketParser::new(&raw).parse().unwrap();
+
+        assert_eq!(packet.serialize().unwrap(), raw);
+
+        let fresh = time(|| {
+            black_box(black_box(&packet).serialize().unwrap());
+        });
+
+        let mut buf = Vec::with_capacity(512);
+        let mut table = CompressionTable::new();
+
+        let reused = time(|| {
+            buf.clear();
+            black_box(&packet).serialize_into(&mut buf, &mut table).unwrap();
+            black_box(&buf);
+        });
+
+        println!("{} ({} bytes): serialize {:?}/iter, serialize_into {:?}/iter", sample, raw.len(), fresh, reused);
+    }
+}
@@ -0,0 +1,89 @@
+const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Encodes to standard base64 with padding
+pub fn encode(data: &[u8]) -> String {
+    let mut text = String::with_capacity(data.len().div_ceil(3) * 4);
+
+    for chunk in data.chunks(3) {
+        let value = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |value, (i, byte)| value | (*byte as u32) << (16 - i * 8));
+
+        for i in 0..4 {
+            if i <= chunk.len() {
+                text.push(ALPHABET[((value >> (18 - i * 6)) & 0b0011_1111) as usize] as char);
+            } else {
+                text.push('=');
+            }
+        }
+    }
+
+    text
+}
+
+/// Decodes standard base64, whitespace is ignored so that multi line
+/// presentation formats can be passed as is. Padding has to be there and
+/// be right, anything else is likely a key or blob cut short.
+pub fn decode(text: &str) -> Result<Vec<u8>, String> {
+    let chars = text.chars().filter(|c| ! c.is_whitespace()).collect::<Vec<_>>();
+
+    if chars.len() % 4 != 0 {
+        return Err(format!("Invalid base64 length {}", chars.len()));
+    }
+
+    let padding = chars.iter().rev().take_while(|c| **c == '=').count();
+
+    if padding > 2 {
+        return Err("Invalid base64 padding".to_owned());
+    }
+
+    let mut data = Vec::with_capacity(chars.len() / 4 * 3);
+    let mut value: u32 = 0;
+    let mut bits = 0;
+
+    for c in &chars[..chars.len() - padding] {
+        let digit = ALPHABET
+            .iter()
+            .position(|x| *x as char == *c)
+            .ok_or_else(|| format!("Invalid base64 character {:?}", c))?;
+
+        value = (value << 6) | digit as u32;
+        bits += 6;
+
+        if bits >= 8 {
+            bits -= 8;
+            data.push((value >> bits) as u8);
+        }
+    }
+
+    // Bits past the last byte are zero when the padding is right
+    if value & ((1 << bits) - 1) != 0 {
+        return Err("Invalid base64 padding".to_owned());
+    }
+
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode, encode};
+
+    #[test]
+    fn encodes() {
+        for (raw, encoded) in [("", ""), ("f", "Zg=="), ("fo", "Zm8="), ("foo", "Zm9v"), ("foobar", "Zm9vYmFy")] {
+            assert_eq!(encode(raw.as_bytes()), encoded);
+        }
+    }
+
+    #[test]
+    fn decodes() {
+        for (raw, encoded) in [("", ""), ("f", "Zg=="), ("fo", "Zm8="), ("foo", "Zm9v"), ("foobar", "Zm9v\nYmFy")] {
+            assert_eq!(decode(encoded), Ok(raw.as_bytes().to_vec()));
+        }
+
+        for invalid in ["Zm9v!", "Zm9", "Zg=", "Z===", "Zm=v", "Zh==", "Zm9vYg"] {
+            assert!(decode(invalid).is_err(), "{}", invalid);
+        }
+    }
+}
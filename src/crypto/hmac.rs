@@ -0,0 +1,53 @@
+use super::sha2::{sha256, sha512};
+
+/// HMAC (RFC 2104) over a hash function with the given block size
+fn hmac(key: &[u8], data: &[u8], block_len: usize, hash: impl Fn(&[u8]) -> Vec<u8>) -> Vec<u8> {
+    let mut key = if key.len() > block_len {
+        hash(key)
+    } else {
+        key.to_vec()
+    };
+
+    key.resize(block_len, 0);
+
+    let mut inner = key.iter().map(|byte| byte ^ 0x36).collect::<Vec<_>>();
+    inner.extend_from_slice(data);
+
+    let mut outer = key.iter().map(|byte| byte ^ 0x5c).collect::<Vec<_>>();
+    outer.extend_from_slice(&hash(&inner));
+
+    hash(&outer)
+}
+
+pub fn hmac_sha256(key: &[u8], data: &[u8]) -> Vec<u8> {
+    hmac(key, data, 64, |data| sha256(data).to_vec())
+}
+
+pub fn hmac_sha512(key: &[u8], data: &[u8]) -> Vec<u8> {
+    hmac(key, data, 128, |data| sha512(data).to_vec())
+}
+
+/// Compares two MACs without bailing out on the first differing byte
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::crypto::hex;
+
+    use super::{hmac_sha256, hmac_sha512};
+
+    // RFC 4231 test case 2
+    #[test]
+    fn computes_known_macs() {
+        assert_eq!(
+            hex(&hmac_sha256(b"Jefe", b"what do ya want for nothing?")),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843",
+        );
+        assert_eq!(
+            hex(&hmac_sha512(b"Jefe", b"what do ya want for nothing?")),
+            "164b7a7bfcf819e2e395fbe73b56e0a387bd64222e831fd610270cd7ea2505549758bf75c05a994a6d034f65f8f0e6fdcaeab1a34d4a6b4b636e070a38bce737",
+        );
+    }
+}
@@ -0,0 +1,8 @@
+pub mod base64;
+pub mod hmac;
+pub mod sha2;
+
+#[cfg(test)]
+pub fn hex(data: &[u8]) -> String {
+    data.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
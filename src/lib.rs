@@ -0,0 +1,23 @@
+//! DNS message parsing and serialization, along with the recursive resolver
+//! and authoritative server built on top of them
+//!
+//! - [`parser`] reads and writes wire format messages ([`parser::packet::DNSPacket`]),
+//!   their records ([`parser::record`]) and zone master files ([`parser::zone_file`])
+//! - [`server`] answers requests ([`server::server::handle_packet`]), resolves
+//!   names from the root ([`server::lookup::lookup_recursively`]), hosts zones
+//!   and handles TSIG and dynamic updates
+//!
+//! ```
+//! use rustdns::parser::{packet::DNSPacketParser, record::DNSRecordData};
+//!
+//! let raw = std::fs::read("samples/response_packet.bin").unwrap();
+//! let packet = DNSPacketParser::new(&raw).parse().unwrap();
+//!
+//! assert_eq!(packet.questions[0].name, "google.com.");
+//! assert!(matches!(packet.answers[0].record, DNSRecordData::A(ref a) if a.ip == [172, 217, 18, 238]));
+//! assert_eq!(packet.serialize().unwrap(), raw);
+//! ```
+
+mod crypto;
+pub mod parser;
+pub mod server;
@@ -1,16 +1,77 @@
-mod parser;
-mod server;
+use std::{env, net::{TcpListener, UdpSocket}, process, sync::Arc, thread};
 
-use std::net::{Ipv4Addr, SocketAddrV4, UdpSocket};
-
-
-use crate::server::server::handle_query;
+use rustdns::server::{config::Config, control::{handle_signals, serve_control}, log, pool::WorkerPool, server::{drain, save_cache, save_cache_periodically, serve_tcp, serve_udp, ServerState, SharedState}};
 
 fn main() {
-    let socket = UdpSocket::bind(("0.0.0.0", 8000))
-        .expect("Should bind server");
+    let args = env::args().skip(1).collect::<Vec<_>>();
 
-    loop {
-        handle_query(&socket).unwrap();
+    if args.iter().any(|arg| arg == "--help" || arg == "-h") {
+        print!("{}", Config::usage());
+        return;
     }
+
+    let config = Config::from_args(&args).unwrap_or_else(|e| {
+        eprintln!("{}\n\n{}", e, Config::usage());
+        process::exit(2);
+    });
+
+    log::set_level(config.log_level);
+
+    let state = ServerState::new(config).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(1);
+    });
+
+    let sockets = state.config.listen_udp
+        .iter()
+        .map(|addr| UdpSocket::bind(addr).map(Arc::new).map_err(|e| format!("Failed to bind UDP {}, {}", addr, e)))
+        .collect::<Result<Vec<_>, _>>();
+    let listeners = state.config.listen_tcp
+        .iter()
+        .map(|addr| TcpListener::bind(addr).map_err(|e| format!("Failed to bind TCP {}, {}", addr, e)))
+        .collect::<Result<Vec<_>, _>>();
+
+    let control = state.config.control
+        .map(|addr| TcpListener::bind(addr).map_err(|e| format!("Failed to bind control channel {}, {}", addr, e)))
+        .transpose();
+
+    let (sockets, listeners, control) = match (sockets, listeners, control) {
+        (Ok(sockets), Ok(listeners), Ok(control)) => (sockets, listeners, control),
+        (Err(e), _, _) | (_, Err(e), _) | (_, _, Err(e)) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        },
+    };
+
+    let pool = WorkerPool::new(state.config.pool);
+    let tcp_options = state.config.tcp;
+
+    // Reloads rebuild the configuration from the same command line
+    let shared = SharedState::new(state, args);
+
+    thread::scope(|scope| {
+        for socket in &sockets {
+            scope.spawn(|| serve_udp(socket, &shared, &pool));
+        }
+
+        for listener in &listeners {
+            scope.spawn(|| serve_tcp(listener, &shared, tcp_options, &pool));
+        }
+
+        if let Some(ref control) = control {
+            scope.spawn(|| serve_control(control, &shared));
+        }
+
+        scope.spawn(|| handle_signals(&shared));
+        scope.spawn(|| save_cache_periodically(&shared));
+    });
+
+    // Everything stopped taking queries, only the ones in flight are left
+    drain(&shared, &pool);
+
+    // After the drain, so that what the last queries learned is kept
+    save_cache(&shared);
+
+    // Workers stuck on an upstream server aren't waited for
+    process::exit(0);
 }
@@ -1,7 +1,3 @@
-use std::collections::{VecDeque, HashMap};
-
-pub type LabelPtrMap = HashMap<String, usize>;
-
 pub type ParseResult<T> = Result<(
     T,     // Parsed object
     usize, // Consumed length
@@ -15,103 +11,216 @@ pub struct DomainNameLabel {}
 
 impl DomainNameLabel {
     // TODO: Look into Punycode for parsing Unicode
+    /// Reads the name at `pos`, following compression pointers. Pointers
+    /// have to point before the name they're part of (RFC 1035 4.1.4), so
+    /// that a packet pointing a name at itself can't send us looping.
     pub fn parse(data: &[u8], pos: usize) -> ParseResult<String> {
+        let unexpected_end = || "DNSPacketParser: Unexpected end of packet in a name".to_owned();
+
         let mut name = String::new();
+        let mut ptr = pos;
+
+        // Where the part of the name being read starts, pointers go before it
+        let mut start = pos;
 
-        type Ptr = usize;
-        type Level = usize;
+        // Bytes the name takes at `pos`, known once the first pointer is met
+        let mut consumed_len = None;
 
-        let mut queue: VecDeque<(Ptr, Level)> = VecDeque::from(vec![(pos, 0)]);
-        let mut consumed_len = 0;
+        for _ in 0..MAX_NAME_LABELS {
+            let len = *data.get(ptr).ok_or_else(unexpected_end)? as usize;
 
-        // Resolve names recursively by using a queue
-        while let Some((ptr, level)) = queue.pop_front() {
-            if data[ptr] == 0 { // null character
-                if level == 0 {
-                    consumed_len += 1;
-                }
+            match len >> 6 {
+                // Null character
+                0b00 if len == 0 => return Ok((name, consumed_len.unwrap_or_else(|| ptr + 1 - pos))),
+                // String segment
+                0b00 => {
+                    let label = data.get((ptr + 1)..(ptr + 1 + len)).ok_or_else(unexpected_end)?;
+                    name.push_str(&String::from_utf8_lossy(label));
+                    name.push('.');
 
-                continue;
+                    ptr += 1 + len;
+                },
+                // Pointer
+                0b11 => {
+                    let low = *data.get(ptr + 1).ok_or_else(unexpected_end)?;
+                    let jumpptr = (u16::from_be_bytes([len as u8, low]) & (! (0b11 << 14))) as usize;
+
+                    if jumpptr >= start {
+                        return Err("DNSPacketParser: Compression pointer doesn't point backwards".to_owned());
+                    }
+
+                    consumed_len.get_or_insert_with(|| ptr + 2 - pos);
+                    start = jumpptr;
+                    ptr = jumpptr;
+                },
+                _ => return Err(String::from("DNSPacketParser: Unhandled qname marker")),
             }
+        }
 
-            // Is a string segment
-            if data[ptr] >> 6 == 0b00 {
-                let len = (data[ptr] & 0b0011_1111) as usize; // Length
-                let slice = &data[(ptr + 1)..];
-                let str_segment = &slice[0..len];
-                name.push_str(&String::from_utf8_lossy(str_segment));
-                name.push('.');
+        Err("DNSPacketParser: Too many labels in a name".to_owned())
+    }
 
-                queue.push_back((1 + ptr + len, level));
+    /// Presentation form of a parsed name, the root is written as "."
+    pub fn presentation(name: &str) -> &str {
+        if name.is_empty() { "." } else { name }
+    }
 
-                if level == 0 {
-                    consumed_len += 1 + len;
-                }
+    /// Writes `name` to `buf`, pointing at a previous occurrence of its
+    /// longest known suffix when a compression table is given
+    pub fn serialize_into(
+        name: &str,
+        buf: &mut Vec<u8>,
+        mut table: Option<&mut CompressionTable>,
+    ) -> Result<(), String> {
+        if name.len() > 255 {
+            return Err(format!("Domain name {} exceeds the maximum length allowed", name));
+        }
+
+        // Label boundaries and the hash of the suffix starting at each label
+        let mut labels = [(0usize, 0usize, 0u64); MAX_NAME_LABELS];
+        let mut count = 0;
+        let mut start = 0;
+
+        while start < name.len() {
+            let end = name[start..]
+                .find('.')
+                .map(|i| start + i)
+                .ok_or_else(|| format!("Invalid domain label {}", name))?;
+
+            let part = &name[start..end];
+
+            if part.is_empty() {
+                return Err(format!("Invalid domain label {}", name));
+            }
 
-                continue;
+            if part.len() > 0b0011_1111 {
+                return Err(format!("Domain label part {} exceeds the maximum length allowed", part));
             }
 
-            // Is a pointer
-            if data[ptr + 0] >> 6 == 0b11 {
-                let jumpptr = u16::from_be_bytes([data[ptr + 0], data[ptr + 1]]) & (! (0b11 << 14));
+            labels[count] = (start, end, 0);
+            count += 1;
+            start = end + 1;
+        }
+
+        let mut hash = ROOT_HASH;
+
+        for label in labels[0..count].iter_mut().rev() {
+            hash = hash_label(hash, &name.as_bytes()[label.0..label.1]);
+            label.2 = hash;
+        }
 
-                queue.push_back((jumpptr as usize, level + 1));
+        for (i, &(start, end, hash)) in labels[0..count].iter().enumerate() {
+            if let Some(offset) = table.as_deref().and_then(|table| table.find(buf, name, &labels[i..count], hash)) {
+                buf.extend_from_slice(&(offset | (0b11 << 14)).to_be_bytes());
 
-                if level == 0 {
-                    consumed_len += 2;
-                }
+                return Ok(());
+            }
 
-                continue;
+            if let Some(table) = table.as_deref_mut() {
+                table.insert(buf.len(), hash);
             }
-            
-            return Err(String::from("DNSPacketParser: Unhandled qname marker"))
+
+            buf.push((end - start) as u8);
+            buf.extend_from_slice(&name.as_bytes()[start..end]);
         }
 
-        Ok((name, consumed_len))
+        // Push null character
+        buf.push(0);
+
+        Ok(())
     }
+}
 
-    pub fn serialize(
-        name: &str,
-        label_ptr_map: Option<&LabelPtrMap>,
-    ) -> Result<(Vec<u8>, LabelPtrMap), String>  {
-        let mut rest = name;
-        let mut data = Vec::new();
-        let mut ptr_map = HashMap::new();
-        let mut ptr = 0;
-        
-        while ! rest.is_empty() {
-            if let Some(ptr) = label_ptr_map.and_then(|map| map.get(rest)) {
-                let jumpbytes = (*ptr as u16) | (0b11 << 14);
-
-                data.extend_from_slice(&jumpbytes.to_be_bytes());
-
-                return Ok((data, ptr_map))
-            }
+/// Enough labels for the longest valid name, "a.a.a.(...)", which also
+/// bounds how many compression pointers a valid name goes through
+pub(crate) const MAX_NAME_LABELS: usize = 128;
 
-            ptr_map.insert(rest.to_owned(), ptr);
-            match rest.split_once('.') {
-                Some((part, remainder)) => {
-                    rest = remainder;
+const ROOT_HASH: u64 = 0xcbf2_9ce4_8422_2325;
 
-                    if part.len() > 0b0011_1111 {
-                        return Err(format!("Domain label part {} exceeds the maximum length allowed", part));
-                    }
+/// FNV-1a over the label and the hash of the suffix that follows it
+fn hash_label(suffix_hash: u64, label: &[u8]) -> u64 {
+    label
+        .iter()
+        .chain([&(label.len() as u8)])
+        .fold(suffix_hash, |hash, byte| (hash ^ *byte as u64).wrapping_mul(0x0100_0000_01b3))
+}
 
-                    data.push(part.len() as u8); // Push the length
-                    data.extend_from_slice(part.as_bytes());
+/// Names already written to a message, for compressing the ones that follow
+///
+/// Entries only hold the offset of each suffix along with its hash, the
+/// suffixes themselves are compared against the bytes already written so
+/// nothing is allocated per name. Clearing the table keeps its capacity, so
+/// one table can be reused across messages.
+#[derive(Debug, Default)]
+pub struct CompressionTable {
+    /// Where the message starts in the buffer, e.g. after a TCP length prefix
+    base: usize,
+
+    /// Suffix hash and offset from the start of the message
+    entries: Vec<(u64, u16)>,
+}
 
+impl CompressionTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
 
-                    ptr += part.len() + 1;
-                },
-                None => return Err(format!("Invalid domain label {}", name)),
-            }
+    /// Starts over for a message that begins at `base` in the buffer
+    pub fn reset(&mut self, base: usize) {
+        self.base = base;
+        self.entries.clear();
+    }
+
+    fn insert(&mut self, position: usize, hash: u64) {
+        // Pointers only have 14 bits for the offset
+        if let Some(offset) = position.checked_sub(self.base).filter(|offset| *offset < 1 << 14) {
+            self.entries.push((hash, offset as u16));
         }
+    }
 
+    /// Offset of a previously written name equal to the given labels of `name`
+    fn find(&self, buf: &[u8], name: &str, labels: &[(usize, usize, u64)], hash: u64) -> Option<u16> {
+        let message = &buf[self.base..];
 
-        // Push null character
-        data.push(0);
+        self.entries
+            .iter()
+            .filter(|(entry_hash, _)| *entry_hash == hash)
+            .map(|(_, offset)| *offset)
+            .find(|offset| {
+                let mut written = DomainNameLabel::labels_at(message, *offset as usize);
 
-        Ok((data, ptr_map))
+                labels.iter().all(|(start, end, _)| written.next() == Some(&name.as_bytes()[*start..*end]))
+                    && written.next().is_none()
+            })
     }
 }
 
+impl DomainNameLabel {
+    /// Labels of a name we wrote ourselves, following pointers
+    fn labels_at(message: &[u8], mut ptr: usize) -> impl Iterator<Item = &[u8]> {
+        std::iter::from_fn(move || loop {
+            let len = *message.get(ptr)? as usize;
+
+            match len >> 6 {
+                0b00 if len == 0 => return None,
+                0b00 => {
+                    let label = message.get((ptr + 1)..(ptr + 1 + len))?;
+                    ptr += 1 + len;
+
+                    return Some(label);
+                },
+                // Pointers written by `serialize_into` always point backwards
+                0b11 => {
+                    let jumpptr = (u16::from_be_bytes([len as u8, *message.get(ptr + 1)?]) & ! (0b11 << 14)) as usize;
+
+                    if jumpptr >= ptr {
+                        return None;
+                    }
+
+                    ptr = jumpptr;
+                },
+                _ => return None,
+            }
+        })
+    }
+}
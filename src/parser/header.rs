@@ -1,17 +1,20 @@
 
+use std::fmt;
+
 use crate::parser::common::{Parse, ParseResult};
 
 #[derive(Debug, PartialEq, Eq, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum DNSHeaderType {
     Query = 0,
     Response = 1,
 }
 
-impl Into<usize> for DNSHeaderType {
-    fn into(self) -> usize {
-        match self {
-            Self::Query => 0,
-            Self::Response => 1,
+impl From<DNSHeaderType> for usize {
+    fn from(value: DNSHeaderType) -> Self {
+        match value {
+            DNSHeaderType::Query => 0,
+            DNSHeaderType::Response => 1,
         }
     }
 }
@@ -27,6 +30,7 @@ impl From<usize> for DNSHeaderType {
 }
 
 #[derive(Debug, PartialEq, Eq, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ResultCode {
     /// No error condition
     NoError = 0,
@@ -63,25 +67,133 @@ pub enum ResultCode {
     /// for particular data
     Refused = 5,
 
+    /// YXDomain - Some name that ought not to
+    /// exist, does exist (RFC 2136)
+    YXDomain = 6,
+
+    /// YXRRSet - Some RRset that ought not to
+    /// exist, does exist (RFC 2136)
+    YXRRSet = 7,
+
+    /// NXRRSet - Some RRset that ought to
+    /// exist, does not exist (RFC 2136)
+    NXRRSet = 8,
+
+    /// NotAuth - The server is not authoritative
+    /// for the zone named in the Zone Section (RFC 2136)
+    NotAuth = 9,
+
+    /// NotZone - A name used in the Prerequisite or
+    /// Update Section is not within the zone denoted
+    /// by the Zone Section (RFC 2136)
+    NotZone = 10,
 
-    /// Codes between 6 and 15 are reserved
-    Unknown = 6,
+
+    /// Codes between 11 and 15 are reserved
+    Unknown = 11,
 }
 
-impl Into<usize> for ResultCode {
-    fn into(self) -> usize {
-        match self {
-            Self::NoError => 0,
-            Self::FormatError => 1,
-            Self::ServerFailure => 2,
-            Self::NameError => 3,
-            Self::NotImplemented => 4,
-            Self::Refused => 5,
+impl From<ResultCode> for usize {
+    fn from(value: ResultCode) -> Self {
+        match value {
+            ResultCode::NoError => 0,
+            ResultCode::FormatError => 1,
+            ResultCode::ServerFailure => 2,
+            ResultCode::NameError => 3,
+            ResultCode::NotImplemented => 4,
+            ResultCode::Refused => 5,
+            ResultCode::YXDomain => 6,
+            ResultCode::YXRRSet => 7,
+            ResultCode::NXRRSet => 8,
+            ResultCode::NotAuth => 9,
+            ResultCode::NotZone => 10,
             _ => 0,
         }
     }
 }
 
+/// Mnemonics from the IANA DNS parameters registry
+impl fmt::Display for ResultCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::NoError => "NOERROR",
+            Self::FormatError => "FORMERR",
+            Self::ServerFailure => "SERVFAIL",
+            Self::NameError => "NXDOMAIN",
+            Self::NotImplemented => "NOTIMP",
+            Self::Refused => "REFUSED",
+            Self::YXDomain => "YXDOMAIN",
+            Self::YXRRSet => "YXRRSET",
+            Self::NXRRSet => "NXRRSET",
+            Self::NotAuth => "NOTAUTH",
+            Self::NotZone => "NOTZONE",
+            Self::Unknown => "RESERVED",
+        })
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(from = "u8", into = "u8"))]
+pub enum Opcode {
+    /// A standard query (QUERY)
+    Query,
+
+    /// An inverse query (IQUERY), obsoleted by RFC 3425
+    IQuery,
+
+    /// A server status request (STATUS)
+    Status,
+
+    /// Zone change notification (NOTIFY, RFC 1996)
+    Notify,
+
+    /// Dynamic update (UPDATE, RFC 2136)
+    Update,
+
+    /// Unassigned opcodes, the raw value is kept so
+    /// that the packet can be serialized back as is
+    Unknown(u8),
+}
+
+impl From<Opcode> for u8 {
+    fn from(value: Opcode) -> Self {
+        match value {
+            Opcode::Query => 0,
+            Opcode::IQuery => 1,
+            Opcode::Status => 2,
+            Opcode::Notify => 4,
+            Opcode::Update => 5,
+            Opcode::Unknown(value) => value & 0b0000_1111,
+        }
+    }
+}
+
+impl From<u8> for Opcode {
+    fn from(value: u8) -> Self {
+        match value {
+            0 => Self::Query,
+            1 => Self::IQuery,
+            2 => Self::Status,
+            4 => Self::Notify,
+            5 => Self::Update,
+            _ => Self::Unknown(value),
+        }
+    }
+}
+
+impl fmt::Display for Opcode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Query => f.write_str("QUERY"),
+            Self::IQuery => f.write_str("IQUERY"),
+            Self::Status => f.write_str("STATUS"),
+            Self::Notify => f.write_str("NOTIFY"),
+            Self::Update => f.write_str("UPDATE"),
+            Self::Unknown(value) => write!(f, "RESERVED{}", value),
+        }
+    }
+}
+
 impl From<usize> for ResultCode {
     fn from(value: usize) -> Self {
         match value {
@@ -91,12 +203,18 @@ impl From<usize> for ResultCode {
             3 => Self::NameError,
             4 => Self::NotImplemented,
             5 => Self::Refused,
+            6 => Self::YXDomain,
+            7 => Self::YXRRSet,
+            8 => Self::NXRRSet,
+            9 => Self::NotAuth,
+            10 => Self::NotZone,
             _ => Self::Unknown,
         }
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct DNSHeader {
     /// Packet Identifier (16 bits)
     pub id: u16,
@@ -105,7 +223,7 @@ pub struct DNSHeader {
     pub qr: DNSHeaderType,
 
     /// Operation Code (4 bits)
-    pub opcode: u8,
+    pub opcode: Opcode,
     
     /// Authoritative answer (1 bit)
     pub aa: bool,
@@ -119,9 +237,15 @@ pub struct DNSHeader {
     /// Recursion available (1 bit)
     pub ra: bool,
 
-    /// Reserved (3 bit)
+    /// Reserved, must be zero (1 bit)
     pub z: u8,
 
+    /// Authentic data (1 bit, RFC 4035)
+    pub ad: bool,
+
+    /// Checking disabled (1 bit, RFC 4035)
+    pub cd: bool,
+
     /// Response Code (4 bit)
     pub rcode: ResultCode,
 
@@ -138,10 +262,55 @@ pub struct DNSHeader {
     pub arcount: u16,
 }
 
+/// The two header lines printed by dig, the counts are the ones from the wire
+/// ```text
+/// ;; ->>HEADER<<- opcode: QUERY, status: NOERROR, id: 1234
+/// ;; flags: qr rd ra; QUERY: 1, ANSWER: 1, AUTHORITY: 0, ADDITIONAL: 0
+/// ```
+impl fmt::Display for DNSHeader {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, ";; ->>HEADER<<- opcode: {}, status: {}, id: {}", self.opcode, self.rcode, self.id)?;
+
+        let flags = [
+            (self.qr == DNSHeaderType::Response, "qr"),
+            (self.aa, "aa"),
+            (self.tc, "tc"),
+            (self.rd, "rd"),
+            (self.ra, "ra"),
+            (self.z != 0, "z"),
+            (self.ad, "ad"),
+            (self.cd, "cd"),
+        ];
+
+        f.write_str(";; flags:")?;
+
+        for (_, flag) in flags.iter().filter(|(set, _)| *set) {
+            write!(f, " {}", flag)?;
+        }
+
+        // Dynamic updates rename the sections (RFC 2136 section 2)
+        let sections = match self.opcode {
+            Opcode::Update => ["ZONE", "PREREQ", "UPDATE", "ADDITIONAL"],
+            _ => ["QUERY", "ANSWER", "AUTHORITY", "ADDITIONAL"],
+        };
+
+        write!(
+            f,
+            "; {}: {}, {}: {}, {}: {}, {}: {}",
+            sections[0], self.qdcount,
+            sections[1], self.ancount,
+            sections[2], self.nscount,
+            sections[3], self.arcount,
+        )
+    }
+}
+
 impl Parse for DNSHeader {
+    // The `>> 0` shifts line the fields up with the RFC 1035 4.1.1 diagram
+    #[allow(clippy::identity_op)]
     fn parse(data: &[u8]) -> ParseResult<Self> {
         if data.len() < 12 {
-            return Err(String::from(format!("DNSHeader parser: Expected data length to be at least {}", 12)));
+            return Err(format!("DNSHeader parser: Expected data length to be at least {}", 12));
         }
 
         // 2 bytes
@@ -162,8 +331,12 @@ impl Parse for DNSHeader {
         // 1 byte
             // 1 bit
         let ra    = (data[3] & 0b1000_0000) >> 7;
-            // 3 bits
-        let z     = (data[3] & 0b0111_0000) >> 4;
+            // 1 bit
+        let z     = (data[3] & 0b0100_0000) >> 6;
+            // 1 bit
+        let ad    = (data[3] & 0b0010_0000) >> 5;
+            // 1 bit
+        let cd    = (data[3] & 0b0001_0000) >> 4;
             // 4 bits
         let rcode = (data[3] & 0b0000_1111) >> 0;
 
@@ -183,12 +356,14 @@ impl Parse for DNSHeader {
         let header = DNSHeader {
             id,
             qr: if qr == 0 { DNSHeaderType::Query } else { DNSHeaderType::Response },
-            opcode,
+            opcode: opcode.into(),
             aa: aa == 1,
             tc: tc == 1,
             rd: rd == 1,
             ra: ra == 1,
             z,
+            ad: ad == 1,
+            cd: cd == 1,
             rcode: (rcode as usize).into(),
             qdcount,
             ancount,
@@ -201,14 +376,14 @@ impl Parse for DNSHeader {
 }
 
 impl DNSHeader {
-    pub fn serialize(&self) -> Vec<u8> {
-        let mut data = Vec::with_capacity(12);
-
+    // Same as for parsing, `<< 0` keeps the fields lined up
+    #[allow(clippy::identity_op)]
+    pub fn serialize_into(&self, data: &mut Vec<u8>) {
         data.extend_from_slice(&self.id.to_be_bytes());
 
         data.push(
             ((Into::<usize>::into(self.qr) as u8) << 7) |
-            (self.opcode                          << 3) |
+            (Into::<u8>::into(self.opcode)        << 3) |
             (Into::<u8>::into(self.aa)            << 2) |
             (Into::<u8>::into(self.tc)            << 1) |
             (Into::<u8>::into(self.rd)            << 0)
@@ -216,7 +391,9 @@ impl DNSHeader {
 
         data.push(
             Into::<u8>::into(self.ra)                << 7  |
-            (self.z                                  << 4) |
+            ((self.z & 0b1)                          << 6) |
+            (Into::<u8>::into(self.ad)               << 5) |
+            (Into::<u8>::into(self.cd)               << 4) |
             ((Into::<usize>::into(self.rcode) as u8) << 0)
         );
 
@@ -224,7 +401,46 @@ impl DNSHeader {
         data.extend_from_slice(&self.ancount.to_be_bytes());
         data.extend_from_slice(&self.nscount.to_be_bytes());
         data.extend_from_slice(&self.arcount.to_be_bytes());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::common::Parse;
+
+    use super::{DNSHeader, DNSHeaderType, Opcode, ResultCode};
+
+    #[test]
+    fn parses_and_serializes_ad_and_cd_flags() {
+        let raw = [0x12, 0x34, 0x01, 0b0011_0000, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00];
+
+        let (header, len) = DNSHeader::parse(&raw).unwrap();
+
+        assert_eq!(len, 12);
+        assert_eq!(
+            header,
+            DNSHeader {
+                id: 0x1234,
+                qr: DNSHeaderType::Query,
+                opcode: Opcode::Query,
+                aa: false,
+                tc: false,
+                rd: true,
+                ra: false,
+                z: 0,
+                ad: true,
+                cd: true,
+                rcode: ResultCode::NoError,
+                qdcount: 1,
+                ancount: 0,
+                nscount: 0,
+                arcount: 0,
+            },
+        );
+
+        let mut data = vec![];
+        header.serialize_into(&mut data);
 
-        data
+        assert_eq!(data, raw.to_vec());
     }
 }
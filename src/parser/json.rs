@@ -0,0 +1,362 @@
+use serde_json::{json, Map, Value};
+
+use super::{header::{DNSHeader, DNSHeaderType, Opcode}, packet::{DNSPacket, DNSPacketParser}, question::DNSQuestion, record::{DNSRecord, DNSRecordData, DNSRecordsParser}, types::{RecordClass, RecordType}, zone_file::{normalize_origin, ZoneFileParser}, DomainNameLabel};
+
+type Object = Map<String, Value>;
+
+fn encode_hex(data: &[u8]) -> String {
+    data.iter().map(|byte| format!("{:02X}", byte)).collect()
+}
+
+fn decode_hex(text: &str) -> Result<Vec<u8>, String> {
+    if ! text.is_ascii() || ! text.len().is_multiple_of(2) {
+        return Err(format!("Invalid hex data {}", text));
+    }
+
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&text[i..(i + 2)], 16).map_err(|_| format!("Invalid hex data {}", text)))
+        .collect()
+}
+
+fn get_u64(object: &Object, key: &str) -> Result<Option<u64>, String> {
+    object
+        .get(key)
+        .map(|value| value.as_u64().ok_or_else(|| format!("{} must be an unsigned integer", key)))
+        .transpose()
+}
+
+fn get_u16(object: &Object, key: &str) -> Result<Option<u16>, String> {
+    get_u64(object, key)?
+        .map(|value| u16::try_from(value).map_err(|_| format!("{} is out of range", key)))
+        .transpose()
+}
+
+/// Booleans are accepted as 0 and 1 as well, which is how the RFC's examples write them
+fn get_bool(object: &Object, key: &str) -> Result<bool, String> {
+    match object.get(key) {
+        None => Ok(false),
+        Some(Value::Bool(value)) => Ok(*value),
+        Some(value) => match value.as_u64() {
+            Some(0) => Ok(false),
+            Some(1) => Ok(true),
+            _ => Err(format!("{} must be a boolean", key)),
+        },
+    }
+}
+
+fn get_str<'a>(object: &'a Object, key: &str) -> Result<Option<&'a str>, String> {
+    object
+        .get(key)
+        .map(|value| value.as_str().ok_or_else(|| format!("{} must be a string", key)))
+        .transpose()
+}
+
+/// Reads `<prefix>`, falling back on `<prefix>name`
+fn get_type(object: &Object, prefix: &str) -> Result<Option<RecordType>, String> {
+    match get_u16(object, prefix)? {
+        Some(value) => Ok(Some(value.into())),
+        None => get_str(object, &format!("{}name", prefix))?.map(str::parse).transpose(),
+    }
+}
+
+fn get_class(object: &Object, prefix: &str) -> Result<RecordClass, String> {
+    match get_u16(object, prefix)? {
+        Some(value) => Ok(value.into()),
+        None => Ok(get_str(object, &format!("{}name", prefix))?.map(str::parse).transpose()?.unwrap_or(RecordClass::IN)),
+    }
+}
+
+/// `update` tells whether the records belong to a dynamic update, where
+/// empty record data is allowed
+fn get_records(object: &Object, key: &str, update: bool) -> Result<Vec<DNSRecord>, String> {
+    match object.get(key) {
+        None => Ok(vec![]),
+        Some(Value::Array(values)) => values.iter().map(|value| record_from_json(value, update)).collect(),
+        Some(_) => Err(format!("{} must be an array", key)),
+    }
+}
+
+fn as_object<'a>(value: &'a Value, what: &str) -> Result<&'a Object, String> {
+    value.as_object().ok_or_else(|| format!("{} must be a JSON object", what))
+}
+
+fn question_to_json(question: &DNSQuestion) -> Value {
+    let mut object = json!({
+        "NAME": DomainNameLabel::presentation(&question.name),
+        "TYPE": u16::from(question.rtype),
+        "CLASS": u16::from(question.class),
+    });
+
+    if let Some(name) = question.rtype.mnemonic() {
+        object["TYPEname"] = name.into();
+    }
+
+    if let Some(name) = question.class.mnemonic() {
+        object["CLASSname"] = name.into();
+    }
+
+    object
+}
+
+fn question_from_json(value: &Value) -> Result<DNSQuestion, String> {
+    let object = as_object(value, "Question")?;
+
+    Ok(DNSQuestion {
+        name: normalize_origin(get_str(object, "NAME")?.ok_or("Question is missing its NAME")?),
+        rtype: get_type(object, "TYPE")?.ok_or("Question is missing its TYPE")?,
+        class: get_class(object, "CLASS")?,
+    })
+}
+
+fn record_to_json(record: &DNSRecord) -> Result<Value, String> {
+    let data = record.record.to_bytes()?;
+
+    let mut object = json!({
+        "NAME": DomainNameLabel::presentation(&record.name),
+        "TYPE": u16::from(record.rtype),
+        "CLASS": u16::from(record.class),
+        "TTL": record.ttl,
+        "RDLENGTH": data.len(),
+        "RDATAHEX": encode_hex(&data),
+    });
+
+    if let Some(name) = record.rtype.mnemonic() {
+        object["TYPEname"] = name.into();
+
+        // Record data without a type of its own only has the hex form, so do
+        // TSIG records, they have no presentation form that could be read back
+        if ! matches!(record.record, DNSRecordData::Unknown(_) | DNSRecordData::Empty | DNSRecordData::TSIG(_)) {
+            object[format!("rdata{}", name)] = record.record.to_string().into();
+        }
+    }
+
+    if let Some(name) = record.class.mnemonic() {
+        object["CLASSname"] = name.into();
+    }
+
+    Ok(object)
+}
+
+fn record_from_json(value: &Value, update: bool) -> Result<DNSRecord, String> {
+    let object = as_object(value, "Resource record")?;
+
+    let name = normalize_origin(get_str(object, "NAME")?.ok_or("Resource record is missing its NAME")?);
+    let rtype = get_type(object, "TYPE")?.ok_or_else(|| format!("Resource record {} is missing its TYPE", name))?;
+    let class = get_class(object, "CLASS")?;
+    let ttl = get_u64(object, "TTL")?.unwrap_or(0);
+    let ttl = u32::try_from(ttl).map_err(|_| format!("TTL {} is out of range", ttl))?;
+
+    if let Some(hex) = get_str(object, "RDATAHEX")? {
+        let data = decode_hex(hex)?;
+        let (record, _) = DNSRecordsParser::new(&data)
+            .with_empty_rdata(update)
+            .parse_record_data(rtype, data.len(), 0)?;
+
+        return Ok(DNSRecord { name, rtype, class, ttl, len: data.len() as u16, record });
+    }
+
+    // The presentation form goes through the zone file parser, names in it are absolute
+    let rdata = get_str(object, &format!("rdata{}", rtype))?
+        .ok_or_else(|| format!("Resource record {} has neither RDATAHEX nor rdata{}", name, rtype))?;
+    let line = format!("{} {} {} {} {}", DomainNameLabel::presentation(&name), ttl, class, rtype, rdata);
+
+    ZoneFileParser::new("")
+        .parse_str(&line, "JSON")?
+        .pop()
+        .ok_or_else(|| format!("Resource record {} has no record data", name))
+}
+
+/// Conversion to and from the JSON representation of DNS messages (RFC 8427)
+impl DNSPacket {
+    /// Every header field, the question and the records with both their hex
+    /// and presentation record data
+    pub fn to_json(&self) -> Result<Value, String> {
+        let header = &self.header;
+
+        let mut object = json!({
+            "ID": header.id,
+            "QR": header.qr == DNSHeaderType::Response,
+            "Opcode": u8::from(header.opcode),
+            "AA": header.aa,
+            "TC": header.tc,
+            "RD": header.rd,
+            "RA": header.ra,
+            "AD": header.ad,
+            "CD": header.cd,
+            "RCODE": usize::from(header.rcode),
+            "QDCOUNT": header.qdcount,
+            "ANCOUNT": header.ancount,
+            "NSCOUNT": header.nscount,
+            "ARCOUNT": header.arcount,
+        });
+
+        if let Some(question) = self.questions.first() {
+            object["QNAME"] = DomainNameLabel::presentation(&question.name).into();
+            object["QTYPE"] = u16::from(question.rtype).into();
+            object["QCLASS"] = u16::from(question.class).into();
+
+            if let Some(name) = question.rtype.mnemonic() {
+                object["QTYPEname"] = name.into();
+            }
+
+            if let Some(name) = question.class.mnemonic() {
+                object["QCLASSname"] = name.into();
+            }
+
+            object["questionRRs"] = self.questions.iter().map(question_to_json).collect();
+        }
+
+        for (key, records) in [("answerRRs", &self.answers), ("authorityRRs", &self.authority), ("additionalRRs", &self.additional)] {
+            if ! records.is_empty() {
+                object[key] = records.iter().map(record_to_json).collect::<Result<_, String>>()?;
+            }
+        }
+
+        Ok(object)
+    }
+
+    /// The message as a single hex string of its wire format
+    pub fn to_json_octets(&self) -> Result<Value, String> {
+        Ok(json!({ "messageOctetsHEX": encode_hex(&self.serialize()?) }))
+    }
+
+    /// Reads either form, `messageOctetsHEX` wins when present. Missing
+    /// header fields are zero and missing counts match the sections.
+    pub fn from_json(value: &Value) -> Result<Self, String> {
+        let object = as_object(value, "DNS message")?;
+
+        if let Some(hex) = get_str(object, "messageOctetsHEX")? {
+            return DNSPacketParser::new(&decode_hex(hex)?).parse();
+        }
+
+        let questions = match object.get("questionRRs") {
+            Some(Value::Array(values)) => values.iter().map(question_from_json).collect::<Result<Vec<_>, _>>()?,
+            Some(_) => return Err("questionRRs must be an array".to_owned()),
+            None => match get_str(object, "QNAME")? {
+                Some(name) => vec![DNSQuestion {
+                    name: normalize_origin(name),
+                    rtype: get_type(object, "QTYPE")?.ok_or("Question is missing its QTYPE")?,
+                    class: get_class(object, "QCLASS")?,
+                }],
+                None => vec![],
+            },
+        };
+
+        let count = |key: &str, len: usize| Ok::<_, String>(get_u16(object, key)?.unwrap_or(len as u16));
+        let opcode = get_u64(object, "Opcode")?.unwrap_or(0);
+        let rcode = get_u64(object, "RCODE")?.unwrap_or(0);
+
+        if opcode > 15 || rcode > 15 {
+            return Err("Opcode and RCODE must fit in 4 bits".to_owned());
+        }
+
+        let update = Opcode::from(opcode as u8) == Opcode::Update;
+        let answers = get_records(object, "answerRRs", update)?;
+        let authority = get_records(object, "authorityRRs", update)?;
+        let additional = get_records(object, "additionalRRs", update)?;
+
+        let header = DNSHeader {
+            id: get_u16(object, "ID")?.unwrap_or(0),
+            qr: if get_bool(object, "QR")? { DNSHeaderType::Response } else { DNSHeaderType::Query },
+            opcode: (opcode as u8).into(),
+            aa: get_bool(object, "AA")?,
+            tc: get_bool(object, "TC")?,
+            rd: get_bool(object, "RD")?,
+            ra: get_bool(object, "RA")?,
+            z: 0,
+            ad: get_bool(object, "AD")?,
+            cd: get_bool(object, "CD")?,
+            rcode: (rcode as usize).into(),
+            qdcount: count("QDCOUNT", questions.len())?,
+            ancount: count("ANCOUNT", answers.len())?,
+            nscount: count("NSCOUNT", authority.len())?,
+            arcount: count("ARCOUNT", additional.len())?,
+        };
+
+        Ok(Self { header, questions, answers, authority, additional })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use serde_json::json;
+
+    use crate::parser::{packet::{DNSPacket, DNSPacketParser}, record::{DNSRecord, DNSRecordData, DNSTSIGRecord, TSIGError}, types::{RecordClass, RecordType}};
+
+    #[test]
+    fn converts_packets_to_json_and_back() {
+        let raw = fs::read("./samples/response_packet.bin").expect("Should read response_packet sample file");
+        let packet = DNSPacketParser::new(&raw).parse().unwrap();
+
+        let value = packet.to_json().unwrap();
+
+        assert_eq!(value["ID"], 34534);
+        assert_eq!(value["QR"], true);
+        assert_eq!(value["QNAME"], "google.com.");
+        assert_eq!(value["QTYPEname"], "A");
+        assert_eq!(value["answerRRs"][0]["RDATAHEX"], "ACD912EE");
+        assert_eq!(value["answerRRs"][0]["rdataA"], "172.217.18.238");
+        assert_eq!(DNSPacket::from_json(&value), Ok(packet.clone()));
+
+        let octets = packet.to_json_octets().unwrap();
+        assert_eq!(DNSPacket::from_json(&octets), Ok(packet));
+    }
+
+    #[test]
+    fn reads_rfc_8427_examples() {
+        // Section 7.1, with an answer using the presentation form only
+        let value = json!({
+            "ID": 19678, "QR": 0, "Opcode": 0,
+            "AA": 0, "TC": 0, "RD": 0, "RA": 0, "AD": 0, "CD": 0, "RCODE": 0,
+            "QDCOUNT": 1, "ANCOUNT": 1, "NSCOUNT": 0, "ARCOUNT": 0,
+            "QNAME": "example.com", "QTYPE": 1, "QCLASS": 1,
+            "answerRRs": [{ "NAME": "example.com", "TYPEname": "MX", "CLASS": 1, "TTL": 3600, "rdataMX": "10 mail.example.com." }],
+        });
+
+        let packet = DNSPacket::from_json(&value).unwrap();
+
+        assert_eq!(packet.header.id, 19678);
+        assert_eq!(packet.questions[0].name, "example.com.");
+        assert_eq!(packet.questions[0].rtype, RecordType::A);
+        assert_eq!(packet.answers[0].rtype, RecordType::MX);
+        assert!(matches!(&packet.answers[0].record, DNSRecordData::MX(mx) if mx.exchange == "mail.example.com."));
+
+        assert!(DNSPacket::from_json(&json!({ "messageOctetsHEX": "ABC" })).is_err());
+        assert!(DNSPacket::from_json(&json!({ "QR": 2 })).is_err());
+    }
+
+    #[test]
+    fn writes_tsig_records_in_hex_only() {
+        let raw = fs::read("./samples/query_packet.bin").expect("Should read query_packet sample file");
+        let mut packet = DNSPacketParser::new(&raw).parse().unwrap();
+
+        packet.additional.push(DNSRecord {
+            name: "update-key.".to_owned(),
+            rtype: RecordType::TSIG,
+            class: RecordClass::ANY,
+            ttl: 0,
+            len: 0,
+            record: DNSRecordData::TSIG(DNSTSIGRecord {
+                algorithm: "hmac-sha256.".to_owned(),
+                time_signed: 1_700_000_000,
+                fudge: 300,
+                mac: vec![7; 32],
+                original_id: packet.header.id,
+                error: TSIGError::NoError,
+                other_data: vec![],
+            }),
+        });
+        packet.header.arcount += 1;
+        let packet = DNSPacketParser::new(&packet.serialize().unwrap()).parse().unwrap();
+
+        let value = packet.to_json().unwrap();
+        let tsig = &value["additionalRRs"][0];
+
+        assert_eq!(tsig["TYPEname"], "TSIG");
+        assert!(tsig.get("rdataTSIG").is_none());
+        assert_eq!(DNSPacket::from_json(&value), Ok(packet));
+    }
+}
@@ -2,7 +2,15 @@ pub mod header;
 pub mod question;
 pub mod record;
 pub mod packet;
+pub mod types;
+pub mod update;
+
+pub mod view;
+pub mod zone_file;
+
+#[cfg(feature = "serde")]
+pub mod json;
 
 mod common;
 
-pub use common::{Parse, LabelPtrMap};
+pub use common::{CompressionTable, DomainNameLabel};
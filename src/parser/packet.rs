@@ -1,9 +1,10 @@
-use std::collections::HashMap;
+use std::fmt;
 
-use super::{header::DNSHeader, question::{DNSQuestion, DNSQuestionParser, DNSQuestionSerializer}, record::{DNSRecord, DNSRecordsParser, DNSRecordSerializer}, common::Parse, LabelPtrMap};
+use super::{header::{DNSHeader, Opcode}, question::{DNSQuestion, DNSQuestionParser, DNSQuestionSerializer}, record::{DNSRecord, DNSRecordsParser, DNSRecordSerializer}, common::Parse, CompressionTable};
 
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct DNSPacket {
     pub header: DNSHeader,
     pub questions: Vec<DNSQuestion>,
@@ -15,53 +16,61 @@ pub struct DNSPacket {
 impl DNSPacket {
     pub fn serialize(&self) -> Result<Vec<u8>, String> {
         let mut data = Vec::new();
-        let mut ptr = 0;
 
-        // Serialize Header
-        let serialized_header = self.header.serialize();
-        let mut label_ptr_map: LabelPtrMap = HashMap::new();
+        self.serialize_into(&mut data, &mut CompressionTable::new())?;
 
-        data.extend_from_slice(&serialized_header);
-        ptr += serialized_header.len();
+        Ok(data)
+    }
 
+    /// Appends the packet to `buf`, which may already hold other data (like
+    /// the TCP length prefix). Reusing the buffer and the table between
+    /// packets avoids allocating once they've grown large enough.
+    pub fn serialize_into(&self, buf: &mut Vec<u8>, table: &mut CompressionTable) -> Result<(), String> {
+        // Compression pointers are relative to the start of the message
+        table.reset(buf.len());
 
-        // Serialize Questions
-        let questions_data = DNSQuestionSerializer::new(
-            &self.questions,
-            &mut label_ptr_map,
-            ptr,
-        ).serialize()?;
+        self.header.serialize_into(buf);
 
-        data.extend_from_slice(&questions_data);
-        ptr += questions_data.len();
-        
-        let answers_data = DNSRecordSerializer::new(
-            &self.answers,
-            &mut label_ptr_map,
-            ptr,
-        ).serialize()?;
+        DNSQuestionSerializer::new(&self.questions, table).serialize_into(buf)?;
+        DNSRecordSerializer::new(&self.answers, table).serialize_into(buf)?;
+        DNSRecordSerializer::new(&self.authority, table).serialize_into(buf)?;
+        DNSRecordSerializer::new(&self.additional, table).serialize_into(buf)?;
 
-        data.extend_from_slice(&answers_data);
-        ptr += answers_data.len();
+        Ok(())
+    }
+}
 
-        let authority_data = DNSRecordSerializer::new(
-            &self.authority,
-            &mut label_ptr_map,
-            ptr
-        ).serialize()?;
+/// dig style layout, the header followed by every non empty section
+impl fmt::Display for DNSPacket {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}", self.header)?;
 
-        data.extend_from_slice(&authority_data);
-        ptr += authority_data.len();
+        let sections = match self.header.opcode {
+            Opcode::Update => ["ZONE", "PREREQUISITE", "UPDATE", "ADDITIONAL"],
+            _ => ["QUESTION", "ANSWER", "AUTHORITY", "ADDITIONAL"],
+        };
 
-        let additional_data = DNSRecordSerializer::new(
-            &self.additional,
-            &mut label_ptr_map,
-            ptr
-        ).serialize()?;
+        if ! self.questions.is_empty() {
+            write!(f, "\n;; {} SECTION:\n", sections[0])?;
 
-        data.extend_from_slice(&additional_data);
+            for question in &self.questions {
+                writeln!(f, ";{}", question)?;
+            }
+        }
 
-        Ok(data)
+        for (section, records) in sections[1..].iter().zip([&self.answers, &self.authority, &self.additional]) {
+            if records.is_empty() {
+                continue;
+            }
+
+            write!(f, "\n;; {} SECTION:\n", section)?;
+
+            for record in records {
+                writeln!(f, "{}", record)?;
+            }
+        }
+
+        Ok(())
     }
 }
 
@@ -77,12 +86,13 @@ impl<'data> DNSPacketParser<'data> {
         Self { packet: data, ptr: 0 }
     }
 
-    fn parse_records(&mut self, count: usize) -> Result<Vec<DNSRecord>, String> {
+    fn parse_records(&mut self, count: usize, opcode: Opcode) -> Result<Vec<DNSRecord>, String> {
         if count == 0 {
             return Ok(Vec::new());
         }
 
         let (records, records_size) = DNSRecordsParser::new(self.packet)
+            .with_empty_rdata(opcode == Opcode::Update)
             .parse(count, self.ptr)?;
 
         self.ptr += records_size;
@@ -104,7 +114,8 @@ impl<'data> DNSPacketParser<'data> {
     }
 
     fn parse_header(&mut self) -> Result<DNSHeader, String> {
-        let (header, header_size) = DNSHeader::parse(&self.packet[0..12])?;
+        let header = self.packet.get(0..12).ok_or("DNSPacketParser: Packet is shorter than a header")?;
+        let (header, header_size) = DNSHeader::parse(header)?;
         self.ptr += header_size;
 
         Ok(header)
@@ -114,9 +125,9 @@ impl<'data> DNSPacketParser<'data> {
         let header = self.parse_header()?;
 
         let questions = self.parse_questions(header.qdcount as usize)?;
-        let answers = self.parse_records(header.ancount as usize)?;
-        let authority = self.parse_records(header.nscount as usize)?;
-        let additional = self.parse_records(header.arcount as usize)?;
+        let answers = self.parse_records(header.ancount as usize, header.opcode)?;
+        let authority = self.parse_records(header.nscount as usize, header.opcode)?;
+        let additional = self.parse_records(header.arcount as usize, header.opcode)?;
 
         Ok(DNSPacket {
             header,
@@ -132,7 +143,7 @@ impl<'data> DNSPacketParser<'data> {
 mod tests {
     use std::fs;
 
-    use crate::parser::{packet::DNSPacket, header::{DNSHeader, DNSHeaderType, ResultCode}, question::DNSQuestion};
+    use crate::parser::{CompressionTable, packet::DNSPacket, header::{DNSHeader, DNSHeaderType, Opcode, ResultCode}, question::DNSQuestion, record::DNSRecordData, types::{RecordClass, RecordType}};
 
     use super::DNSPacketParser;
 
@@ -149,12 +160,14 @@ mod tests {
                 header: DNSHeader {
                     id: 34534,
                     qr: DNSHeaderType::Query,
-                    opcode: 0,
+                    opcode: Opcode::Query,
                     aa: false,
                     tc: false,
                     rd: true,
                     ra: false,
-                    z: 2,
+                    z: 0,
+                    ad: true,
+                    cd: false,
                     rcode: ResultCode::NoError,
                     qdcount: 1,
                     ancount: 0,
@@ -164,8 +177,8 @@ mod tests {
                 questions: vec![
                     DNSQuestion {
                         name: "google.com.".to_owned(),
-                        rtype: 1,
-                        class: 1,
+                        rtype: RecordType::A,
+                        class: RecordClass::IN,
                     },
                 ],
                 answers: vec![],
@@ -189,19 +202,20 @@ mod tests {
         let parsed_packet = DNSPacketParser::new(&response_packet_raw)
             .parse();
 
-        println!("Parsed: {:#?}", parsed_packet);
         /*
         assert_eq!(
             Ok(DNSPacket {
                 header: DNSHeader {
                     id: 34534,
                     qr: DNSHeaderType::Query,
-                    opcode: 0,
+                    opcode: Opcode::Query,
                     aa: false,
                     tc: false,
                     rd: true,
                     ra: false,
-                    z: 2,
+                    z: 0,
+                    ad: true,
+                    cd: false,
                     rcode: ResultCode::NoError,
                     qdcount: 1,
                     ancount: 0,
@@ -211,8 +225,8 @@ mod tests {
                 questions: vec![
                     DNSQuestion {
                         name: "google.com.".to_owned(),
-                        rtype: 1,
-                        class: 1,
+                        rtype: RecordType::A,
+                        class: RecordClass::IN,
                     },
                 ],
                 answers: vec![],
@@ -222,5 +236,107 @@ mod tests {
             parsed_packet,
         );
         */
+
+        assert_eq!(
+            Ok(response_packet_raw),
+            parsed_packet.and_then(|p| p.serialize()),
+        );
+    }
+
+    #[test]
+    fn serializes_into_reused_buffers() {
+        let mut buf = vec![];
+        let mut table = CompressionTable::new();
+
+        for sample in ["response_packet_huge.bin", "query_packet_huge.bin", "response_packet_big.bin"] {
+            let raw = fs::read(format!("./samples/{}", sample))
+                .expect("Should read sample file");
+            let packet = DNSPacketParser::new(&raw).parse().unwrap();
+
+            // Pointers have to stay relative to the message, not the buffer
+            buf.clear();
+            buf.extend_from_slice(&(raw.len() as u16).to_be_bytes());
+            packet.serialize_into(&mut buf, &mut table).unwrap();
+
+            assert_eq!(&buf[..2], &(raw.len() as u16).to_be_bytes());
+            assert_eq!(&buf[2..], &raw[..]);
+        }
+    }
+
+    #[test]
+    fn rejects_looping_and_stray_name_pointers() {
+        let mut raw = vec![
+            0x04, 0xd2, 0x01, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01,
+            0x01, b'a', 0x00, 0x00, 0x01, 0x00, 0x01,
+            0x00, 0x00, 0x05, 0x00, 0x01, 0x00, 0x00, 0x0e, 0x10, 0x00, 0x02, 0xc0, 0x1e,
+        ];
+
+        // The CNAME target pointing at itself, past the packet, and at a pointer
+        // that points forward
+        for pointer in [0x1e, 0xff, 0x20] {
+            raw[31] = pointer;
+            assert!(DNSPacketParser::new(&raw).parse().is_err());
+        }
+
+        // Cut short in the middle of a pointer
+        assert!(DNSPacketParser::new(&raw[..31]).parse().is_err());
+
+        raw[31] = 0x0c;
+        assert!(DNSPacketParser::new(&raw).parse().is_ok());
+    }
+
+    #[test]
+    fn only_takes_empty_record_data_in_updates() {
+        // A response with an A record for the root that has no record data
+        let mut raw = vec![
+            0x00, 0x01, 0x80, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x01, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        ];
+
+        assert!(DNSPacketParser::new(&raw).parse().is_err());
+
+        // The same as an update, where it deletes the RRset
+        raw[2] = 0x28;
+        let packet = DNSPacketParser::new(&raw).parse().unwrap();
+
+        assert_eq!(packet.header.opcode, Opcode::Update);
+        assert_eq!(packet.answers[0].record, DNSRecordData::Empty);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serializes_packets_with_serde() {
+        let response_packet_raw = fs::read("./samples/response_packet_huge.bin")
+            .expect("Should read response_packet_huge sample file");
+
+        let packet = DNSPacketParser::new(&response_packet_raw).parse().unwrap();
+        let text = serde_json::to_string(&packet).unwrap();
+
+        assert!(text.contains(r#""opcode":0"#));
+        assert!(text.contains(r#""rtype":1,"class":1"#));
+        assert_eq!(serde_json::from_str::<DNSPacket>(&text).unwrap(), packet);
+    }
+
+    #[test]
+    fn displays_packets_like_dig() {
+        let response_packet_raw = fs::read("./samples/response_packet.bin")
+            .expect("Should read response_packet sample file");
+
+        let packet = DNSPacketParser::new(&response_packet_raw).parse().unwrap();
+
+        assert_eq!(
+            packet.to_string(),
+            [
+                ";; ->>HEADER<<- opcode: QUERY, status: NOERROR, id: 34534",
+                ";; flags: qr rd ra; QUERY: 1, ANSWER: 1, AUTHORITY: 0, ADDITIONAL: 0",
+                "",
+                ";; QUESTION SECTION:",
+                ";google.com.\t\tIN\tA",
+                "",
+                ";; ANSWER SECTION:",
+                "google.com.\t300\tIN\tA\t172.217.18.238",
+                "",
+            ].join("\n"),
+        );
     }
 }
@@ -1,18 +1,25 @@
-use std::{collections::HashMap};
+use std::fmt;
 
-use super::{common::{ParseResult, DomainNameLabel}, LabelPtrMap};
+use super::{common::{ParseResult, DomainNameLabel}, types::{RecordClass, RecordType}, CompressionTable};
 
 
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct DNSQuestion {
     /// Domain name
     pub name: String,
 
     /// Record type (16 bit)
-    pub rtype: u16,
+    pub rtype: RecordType,
 
     /// Class (16 bit)
-    pub class: u16,
+    pub class: RecordClass,
+}
+
+impl fmt::Display for DNSQuestion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}\t\t{}\t{}", DomainNameLabel::presentation(&self.name), self.class, self.rtype)
+    }
 }
 
 pub struct DNSQuestionParser<'data> {
@@ -43,48 +50,42 @@ impl<'data> DNSQuestionParser<'data> {
         let (name, consumed_len) = DomainNameLabel::parse(self.packet, ptr)?;
         let end = ptr + consumed_len;
 
+        let fields = self.packet
+            .get(end..(end + 4))
+            .ok_or("DNSPacketParser: Unexpected end of packet in a question")?;
+
         Ok((
             DNSQuestion {
                 name,
-                rtype: u16::from_be_bytes([self.packet[end + 0], self.packet[end + 1]]),
-                class: u16::from_be_bytes([self.packet[end + 2], self.packet[end + 3]]),
+                rtype: u16::from_be_bytes([fields[0], fields[1]]).into(),
+                class: u16::from_be_bytes([fields[2], fields[3]]).into(),
             },
             consumed_len + 2 + 2,
         ))
     }
 }
 
-pub struct DNSQuestionSerializer<'data, 'lmap> {
+pub struct DNSQuestionSerializer<'data, 'table> {
     dns_questions: &'data [DNSQuestion],
-    label_ptr_map: &'lmap mut LabelPtrMap,
-    ptr: usize,
+    table: &'table mut CompressionTable,
 }
 
-impl<'data, 'lmap> DNSQuestionSerializer<'data, 'lmap> {
+impl<'data, 'table> DNSQuestionSerializer<'data, 'table> {
     pub fn new(
         dns_questions: &'data [DNSQuestion],
-        label_ptr_map: &'lmap mut LabelPtrMap,
-        ptr: usize,
+        table: &'table mut CompressionTable,
     ) -> Self {
-        Self { dns_questions, label_ptr_map, ptr }
+        Self { dns_questions, table }
     }
 
-    pub fn serialize(&mut self) -> Result<Vec<u8>, String> {
-        let mut ptr = 0;
-        let mut data = Vec::new();
-
+    pub fn serialize_into(&mut self, buf: &mut Vec<u8>) -> Result<(), String> {
         for question in self.dns_questions {
-            let (serialized_name, mut name_ptr_map) = DomainNameLabel::serialize(&question.name, Some(&self.label_ptr_map))?;
-            name_ptr_map.iter_mut().for_each(|(_, x)| *x += ptr + self.ptr);
-            self.label_ptr_map.extend(name_ptr_map);
-
-            data.extend_from_slice(&serialized_name);
-            data.extend_from_slice(&question.rtype.to_be_bytes());
-            data.extend_from_slice(&question.class.to_be_bytes());
+            DomainNameLabel::serialize_into(&question.name, buf, Some(self.table))?;
 
-            ptr += serialized_name.len() + 2 + 2;
+            buf.extend_from_slice(&u16::from(question.rtype).to_be_bytes());
+            buf.extend_from_slice(&u16::from(question.class).to_be_bytes());
         }
 
-        Ok(data)
+        Ok(())
     }
 }
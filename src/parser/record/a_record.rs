@@ -1,33 +1,41 @@
-use crate::parser::LabelPtrMap;
+use std::{fmt, net::Ipv4Addr};
 
-use super::DNSRecordPack;
+use crate::parser::CompressionTable;
 
-#[derive(Debug, PartialEq, Eq)]
+use super::{DNSRecordPack, RecordType};
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct DNSARecord { pub ip: [u8; 4] }
 
+impl fmt::Display for DNSARecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", Ipv4Addr::from(self.ip))
+    }
+}
+
 impl DNSRecordPack for DNSARecord {
-    const RTYPE: u16 = 1;
+    const RTYPE: RecordType = RecordType::A;
 
     fn parse(
         data: &[u8],
         startptr: usize,
-        _: usize,
+        len: usize,
     ) -> Result<Self, String> where Self: Sized {
-        let ip = [
-            data[startptr + 0],
-            data[startptr + 1],
-            data[startptr + 2],
-            data[startptr + 3],
-        ];
-
-        return Ok(Self { ip })
+        let ip = data.get(startptr..(startptr + len))
+            .and_then(|ip| ip.try_into().ok())
+            .ok_or_else(|| format!("Failed to parse A record, expected 4 bytes, got {}", len))?;
+
+        Ok(Self { ip })
     }
 
-    fn serialize(
+    fn serialize_into(
         &self,
-        _label_ptr_map: &mut LabelPtrMap,
-        _ptr: usize,
-    ) -> Result<Vec<u8>, String> {
-        Ok(self.ip.to_vec())
+        buf: &mut Vec<u8>,
+        _table: Option<&mut CompressionTable>,
+    ) -> Result<(), String> {
+        buf.extend_from_slice(&self.ip);
+
+        Ok(())
     }
 }
@@ -1,35 +1,45 @@
-use crate::parser::LabelPtrMap;
+use std::{fmt, net::Ipv6Addr};
 
-use super::DNSRecordPack;
+use crate::parser::CompressionTable;
 
+use super::{DNSRecordPack, RecordType};
 
-#[derive(Debug, PartialEq, Eq)]
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct DNSAAAARecord {
     // TODO: I'm not sure what's the most efficient way to store ipv6 addresses
-    ip: [u8; 16],
+    pub ip: [u8; 16],
+}
+
+impl fmt::Display for DNSAAAARecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", Ipv6Addr::from(self.ip))
+    }
 }
 
 impl DNSRecordPack for DNSAAAARecord {
-    const RTYPE: u16 = 28;
+    const RTYPE: RecordType = RecordType::AAAA;
 
     fn parse(
         data: &[u8],
         startptr: usize,
-        _: usize,
+        len: usize,
     ) -> Result<Self, String> where Self: Sized {
-        
-        Ok(Self {
-            ip: data[startptr..(startptr + 16)]
-                .try_into()
-                .map_err(|_| "Failed to parse AAAA record".to_owned())?,
-        })
+        let ip = data.get(startptr..(startptr + len))
+            .and_then(|ip| ip.try_into().ok())
+            .ok_or_else(|| format!("Failed to parse AAAA record, expected 16 bytes, got {}", len))?;
+
+        Ok(Self { ip })
     }
 
-    fn serialize(
+    fn serialize_into(
         &self,
-        _label_ptr_map: &mut LabelPtrMap,
-        _ptr: usize,
-    ) -> Result<Vec<u8>, String> {
-        Ok(self.ip.to_vec())
+        buf: &mut Vec<u8>,
+        _table: Option<&mut CompressionTable>,
+    ) -> Result<(), String> {
+        buf.extend_from_slice(&self.ip);
+
+        Ok(())
     }
 }
@@ -1,15 +1,24 @@
-use crate::parser::common::DomainNameLabel;
+use std::fmt;
 
-use super::DNSRecordPack;
+use crate::parser::{common::DomainNameLabel, CompressionTable};
 
+use super::{DNSRecordPack, RecordType};
 
-#[derive(Debug, PartialEq, Eq)]
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct DNSCNameRecord {
-    cname: String,
+    pub cname: String,
+}
+
+impl fmt::Display for DNSCNameRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(DomainNameLabel::presentation(&self.cname))
+    }
 }
 
 impl DNSRecordPack for DNSCNameRecord {
-    const RTYPE: u16 = 5;
+    const RTYPE: RecordType = RecordType::CNAME;
 
     fn parse(
         data: &[u8],
@@ -21,18 +30,11 @@ impl DNSRecordPack for DNSCNameRecord {
         Ok(Self { cname })
     }
 
-    fn serialize(
+    fn serialize_into(
         &self,
-        label_ptr_map: &mut crate::parser::LabelPtrMap,
-        ptr: usize,
-    ) -> Result<Vec<u8>, String> {
-        let (cname_bytes, mut temp_label_ptr_map) = DomainNameLabel::serialize(
-            &self.cname,
-            Some(&label_ptr_map),
-        )?;
-        temp_label_ptr_map.iter_mut().for_each(|(_, x)| *x += ptr);
-        label_ptr_map.extend(temp_label_ptr_map);
-
-        Ok(cname_bytes)
+        buf: &mut Vec<u8>,
+        table: Option<&mut CompressionTable>,
+    ) -> Result<(), String> {
+        DomainNameLabel::serialize_into(&self.cname, buf, table)
     }
 }
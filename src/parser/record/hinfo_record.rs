@@ -0,0 +1,87 @@
+use std::fmt;
+
+use crate::parser::CompressionTable;
+
+use super::{DNSRecordPack, RecordType};
+
+
+/// Host information (RFC 1035 section 3.3.2)
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DNSHINFORecord {
+    pub cpu: String,
+    pub os: String,
+}
+
+/// Reads a <character-string>, returning it along with the consumed length
+pub(super) fn parse_character_string(data: &[u8], ptr: usize, end: usize) -> Result<(String, usize), String> {
+    let len = *data.get(ptr).ok_or("Failed to parse character string, unexpected end of data")? as usize;
+
+    if ptr + 1 + len > end {
+        return Err("Failed to parse character string, length exceeds record data".to_owned());
+    }
+
+    let text = String::from_utf8_lossy(&data[(ptr + 1)..(ptr + 1 + len)]).to_string();
+
+    Ok((text, 1 + len))
+}
+
+/// Writes a <character-string> in its quoted presentation form, escaping quotes,
+/// backslashes and non printable bytes (RFC 1035 section 5.1)
+pub(super) fn format_character_string(f: &mut fmt::Formatter<'_>, text: &[u8]) -> fmt::Result {
+    f.write_str("\"")?;
+
+    for byte in text {
+        match byte {
+            b'"' | b'\\' => write!(f, "\\{}", *byte as char)?,
+            0x20..=0x7e => write!(f, "{}", *byte as char)?,
+            _ => write!(f, "\\{:03}", byte)?,
+        }
+    }
+
+    f.write_str("\"")
+}
+
+pub(super) fn serialize_character_string(buf: &mut Vec<u8>, text: &str) -> Result<(), String> {
+    if text.len() > 255 {
+        return Err(format!("Character string {} exceeds the maximum length allowed", text));
+    }
+
+    buf.push(text.len() as u8);
+    buf.extend_from_slice(text.as_bytes());
+
+    Ok(())
+}
+
+impl fmt::Display for DNSHINFORecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        format_character_string(f, self.cpu.as_bytes())?;
+        f.write_str(" ")?;
+        format_character_string(f, self.os.as_bytes())
+    }
+}
+
+impl DNSRecordPack for DNSHINFORecord {
+    const RTYPE: RecordType = RecordType::HINFO;
+
+    fn parse(
+        data: &[u8],
+        startptr: usize,
+        len: usize,
+    ) -> Result<Self, String> where Self: Sized {
+        let end = startptr + len;
+        let (cpu, consumed_len) = parse_character_string(data, startptr, end)?;
+        let (os, _) = parse_character_string(data, startptr + consumed_len, end)?;
+
+        Ok(Self { cpu, os })
+    }
+
+    fn serialize_into(
+        &self,
+        buf: &mut Vec<u8>,
+        _table: Option<&mut CompressionTable>,
+    ) -> Result<(), String> {
+        serialize_character_string(buf, &self.cpu)?;
+        serialize_character_string(buf, &self.os)
+    }
+}
@@ -0,0 +1,358 @@
+use std::{fmt, str::FromStr};
+
+use crate::parser::CompressionTable;
+
+use super::{DNSRecordPack, RecordType};
+
+
+/// Latitude and longitude of the equator and prime meridian
+const COORDINATE_ORIGIN: u32 = 1 << 31;
+
+/// Altitude is stored in centimeters above a base of 100,000m below the WGS 84 ellipsoid
+const ALTITUDE_BASE: i64 = 10_000_000;
+
+/// Location information (RFC 1876), all the fields hold their wire encoding
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DNSLOCRecord {
+    /// Always 0
+    pub version: u8,
+
+    /// Diameter of the sphere enclosing the entity, see `decode_precision`
+    pub size: u8,
+
+    /// Horizontal precision, encoded like `size`
+    pub horiz_pre: u8,
+
+    /// Vertical precision, encoded like `size`
+    pub vert_pre: u8,
+
+    /// Thousandths of a second of arc, 2^31 being the equator
+    pub latitude: u32,
+
+    /// Thousandths of a second of arc, 2^31 being the prime meridian
+    pub longitude: u32,
+
+    /// Centimeters from a base 100,000m below the WGS 84 ellipsoid
+    pub altitude: u32,
+}
+
+/// Size and precisions are encoded as a base in the high nibble and a
+/// power of ten in the low nibble, in centimeters
+pub fn decode_precision(value: u8) -> u64 {
+    let base = (value >> 4) as u64;
+    let exponent = (value & 0b0000_1111) as u32;
+
+    base * 10u64.pow(exponent.min(9))
+}
+
+pub fn encode_precision(centimeters: u64) -> u8 {
+    let mut exponent = 0;
+
+    while exponent < 9 && centimeters >= 10u64.pow(exponent + 1) {
+        exponent += 1;
+    }
+
+    let base = (centimeters / 10u64.pow(exponent)).min(9);
+
+    ((base as u8) << 4) | exponent as u8
+}
+
+/// Parses a "12.34m" style value into centimeters
+fn parse_meters(value: &str) -> Result<i64, String> {
+    let number = value.strip_suffix(['m', 'M']).unwrap_or(value);
+    let (whole, fraction) = number.split_once('.').unwrap_or((number, ""));
+
+    if fraction.len() > 2 || (whole.is_empty() && fraction.is_empty()) {
+        return Err(format!("Invalid LOC distance {}", value));
+    }
+
+    let negative = whole.starts_with('-');
+    let whole = whole.trim_start_matches('-');
+
+    let parse = |digits: &str| -> Result<i64, String> {
+        if digits.is_empty() {
+            return Ok(0);
+        }
+
+        digits.parse::<u32>()
+            .map(|x| x as i64)
+            .map_err(|_| format!("Invalid LOC distance {}", value))
+    };
+
+    let centimeters = parse(whole)? * 100 + parse(&format!("{:0<2}", fraction))?;
+
+    Ok(if negative { -centimeters } else { centimeters })
+}
+
+fn format_meters(f: &mut fmt::Formatter<'_>, centimeters: i64) -> fmt::Result {
+    let sign = if centimeters < 0 { "-" } else { "" };
+
+    write!(f, "{}{}.{:02}m", sign, centimeters.abs() / 100, centimeters.abs() % 100)
+}
+
+fn format_coordinate(f: &mut fmt::Formatter<'_>, value: u32, positive: char, negative: char) -> fmt::Result {
+    let offset = value as i64 - COORDINATE_ORIGIN as i64;
+    let hemisphere = if offset < 0 { negative } else { positive };
+    let thousandths = offset.abs();
+
+    write!(
+        f,
+        "{} {} {}.{:03} {}",
+        thousandths / 3_600_000,
+        (thousandths / 60_000) % 60,
+        (thousandths / 1_000) % 60,
+        thousandths % 1_000,
+        hemisphere,
+    )
+}
+
+/// Reads "d [m [s]] H" from the tokens, returning the wire encoded coordinate
+fn parse_coordinate<'a>(
+    tokens: &mut impl Iterator<Item = &'a str>,
+    positive: &str,
+    negative: &str,
+    max_degrees: u64,
+) -> Result<u32, String> {
+    let mut parts = vec![];
+
+    let hemisphere = loop {
+        let token = tokens.next().ok_or("Unexpected end of LOC record")?;
+
+        if token.eq_ignore_ascii_case(positive) || token.eq_ignore_ascii_case(negative) {
+            break token;
+        }
+
+        if parts.len() == 3 {
+            return Err(format!("Expected {} or {} in LOC record, got {}", positive, negative, token));
+        }
+
+        parts.push(token);
+    };
+
+    let degrees = parts.first()
+        .ok_or("Missing degrees in LOC record")?
+        .parse::<u64>()
+        .map_err(|_| "Invalid degrees in LOC record")?;
+    let minutes = parts.get(1)
+        .map(|x| x.parse::<u64>())
+        .unwrap_or(Ok(0))
+        .map_err(|_| "Invalid minutes in LOC record")?;
+    let milliseconds = match parts.get(2) {
+        Some(seconds) => {
+            let (whole, fraction) = seconds.split_once('.').unwrap_or((seconds, ""));
+
+            if fraction.len() > 3 {
+                return Err(format!("Invalid seconds in LOC record {}", seconds));
+            }
+
+            let whole = whole.parse::<u64>().map_err(|_| "Invalid seconds in LOC record")?;
+            let fraction = if fraction.is_empty() {
+                0
+            } else {
+                format!("{:0<3}", fraction).parse::<u64>().map_err(|_| "Invalid seconds in LOC record")?
+            };
+
+            whole * 1_000 + fraction
+        },
+        None => 0,
+    };
+
+    if degrees > max_degrees || minutes > 59 || milliseconds > 59_999 {
+        return Err(format!("Coordinate out of range in LOC record {:?}", parts));
+    }
+
+    let offset = degrees * 3_600_000 + minutes * 60_000 + milliseconds;
+
+    if offset > max_degrees * 3_600_000 {
+        return Err(format!("Coordinate out of range in LOC record {:?}", parts));
+    }
+
+    let offset = offset as i64;
+    let offset = if hemisphere.eq_ignore_ascii_case(negative) { -offset } else { offset };
+
+    Ok((COORDINATE_ORIGIN as i64 + offset) as u32)
+}
+
+impl DNSLOCRecord {
+    /// Altitude in centimeters relative to the WGS 84 ellipsoid
+    pub fn altitude_cm(&self) -> i64 {
+        self.altitude as i64 - ALTITUDE_BASE
+    }
+}
+
+/// Presentation format from RFC 1876 section 3
+/// ```text
+/// d1 [m1 [s1]] {"N"|"S"} d2 [m2 [s2]] {"E"|"W"} alt["m"] [siz["m"] [hp["m"] [vp["m"]]]]
+/// ```
+impl fmt::Display for DNSLOCRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        format_coordinate(f, self.latitude, 'N', 'S')?;
+        f.write_str(" ")?;
+        format_coordinate(f, self.longitude, 'E', 'W')?;
+        f.write_str(" ")?;
+        format_meters(f, self.altitude_cm())?;
+
+        for precision in [self.size, self.horiz_pre, self.vert_pre] {
+            f.write_str(" ")?;
+            format_meters(f, decode_precision(precision) as i64)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl FromStr for DNSLOCRecord {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut tokens = s.split_whitespace();
+
+        let latitude = parse_coordinate(&mut tokens, "N", "S", 90)?;
+        let longitude = parse_coordinate(&mut tokens, "E", "W", 180)?;
+
+        let altitude = parse_meters(tokens.next().ok_or("Missing altitude in LOC record")?)? + ALTITUDE_BASE;
+
+        if ! (0..=u32::MAX as i64).contains(&altitude) {
+            return Err("Altitude out of range in LOC record".to_owned());
+        }
+
+        // Defaults from RFC 1876 section 3: 1m size, 10km horizontal and 10m vertical precision
+        let mut precisions = [100, 1_000_000, 1_000];
+
+        for precision in precisions.iter_mut() {
+            match tokens.next() {
+                Some(token) => {
+                    let centimeters = parse_meters(token)?;
+
+                    if ! (0..=90_000_000_000).contains(&centimeters) {
+                        return Err(format!("Precision out of range in LOC record {}", token));
+                    }
+
+                    *precision = centimeters as u64;
+                },
+                None => break,
+            }
+        }
+
+        if let Some(token) = tokens.next() {
+            return Err(format!("Unexpected {} at the end of LOC record", token));
+        }
+
+        Ok(Self {
+            version: 0,
+            size: encode_precision(precisions[0]),
+            horiz_pre: encode_precision(precisions[1]),
+            vert_pre: encode_precision(precisions[2]),
+            latitude,
+            longitude,
+            altitude: altitude as u32,
+        })
+    }
+}
+
+impl DNSRecordPack for DNSLOCRecord {
+    const RTYPE: RecordType = RecordType::LOC;
+
+    // Offsets are written out as `+ 0` to match the RFC 1876 2 layout
+    #[allow(clippy::identity_op)]
+    fn parse(
+        data: &[u8],
+        startptr: usize,
+        len: usize,
+    ) -> Result<Self, String> where Self: Sized {
+        if len != 16 {
+            return Err(format!("Failed to parse LOC record, expected 16 bytes, got {}", len));
+        }
+
+        // Other versions may lay the record out differently (RFC 1876 2)
+        if data[startptr + 0] != 0 {
+            return Err(format!("Unsupported LOC record version {}", data[startptr + 0]));
+        }
+
+        let read_u32 = |ptr: usize| u32::from_be_bytes([
+            data[startptr + ptr + 0],
+            data[startptr + ptr + 1],
+            data[startptr + ptr + 2],
+            data[startptr + ptr + 3],
+        ]);
+
+        Ok(Self {
+            version: data[startptr + 0],
+            size: data[startptr + 1],
+            horiz_pre: data[startptr + 2],
+            vert_pre: data[startptr + 3],
+            latitude: read_u32(4),
+            longitude: read_u32(8),
+            altitude: read_u32(12),
+        })
+    }
+
+    fn serialize_into(
+        &self,
+        buf: &mut Vec<u8>,
+        _table: Option<&mut CompressionTable>,
+    ) -> Result<(), String> {
+        buf.extend_from_slice(&[self.version, self.size, self.horiz_pre, self.vert_pre]);
+        buf.extend_from_slice(&self.latitude.to_be_bytes());
+        buf.extend_from_slice(&self.longitude.to_be_bytes());
+        buf.extend_from_slice(&self.altitude.to_be_bytes());
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::record::DNSRecordPack;
+
+    use super::{decode_precision, encode_precision, DNSLOCRecord};
+
+    #[test]
+    fn converts_precisions() {
+        assert_eq!(encode_precision(100), 0x12);
+        assert_eq!(encode_precision(1_000_000), 0x16);
+        assert_eq!(encode_precision(1_000), 0x13);
+        assert_eq!(encode_precision(0), 0x00);
+        assert_eq!(decode_precision(0x12), 100);
+        assert_eq!(decode_precision(0x35), 300_000);
+    }
+
+    #[test]
+    fn converts_presentation_format() {
+        // Example from RFC 1876 section 4
+        let loc = "42 21 54 N 71 06 18 W -24m 30m".parse::<DNSLOCRecord>().unwrap();
+
+        assert_eq!(loc.latitude, (1u32 << 31) + 152_514_000);
+        assert_eq!(loc.longitude, (1u32 << 31) - 255_978_000);
+        assert_eq!(loc.altitude, 10_000_000 - 2_400);
+        assert_eq!(loc.size, 0x33);
+        assert_eq!(loc.horiz_pre, 0x16);
+        assert_eq!(loc.vert_pre, 0x13);
+        assert_eq!(loc.to_string(), "42 21 54.000 N 71 6 18.000 W -24.00m 30.00m 10000.00m 10.00m");
+
+        let loc = "52 22 23.5 N 4 53 32.125 E -2.5m 0m 10m 2.5m".parse::<DNSLOCRecord>().unwrap();
+        assert_eq!(loc.to_string(), "52 22 23.500 N 4 53 32.125 E -2.50m 0.00m 10.00m 2.00m");
+        assert_eq!(loc.to_string().parse::<DNSLOCRecord>(), Ok(loc));
+
+        assert!("91 N 0 E 0m".parse::<DNSLOCRecord>().is_err());
+        assert!("10 N 0 E".parse::<DNSLOCRecord>().is_err());
+
+        // Negative parts would wrap around to the other hemisphere
+        for loc in ["-10 N 0 E 0m", "10 -30 N 0 E 0m", "10 30 -5 N 0 E 0m", "0 N 0 -1 W 0m"] {
+            assert!(loc.parse::<DNSLOCRecord>().is_err(), "{}", loc);
+        }
+    }
+
+    #[test]
+    fn only_parses_version_zero() {
+        let mut data = vec![];
+        let loc = "42 21 54 N 71 06 18 W -24m 30m".parse::<DNSLOCRecord>().unwrap();
+        loc.serialize_into(&mut data, None).unwrap();
+
+        assert_eq!(DNSLOCRecord::parse(&data, 0, 16), Ok(loc));
+
+        data[0] = 1;
+        assert!(DNSLOCRecord::parse(&data, 0, 16).is_err());
+    }
+}
@@ -1,6 +1,6 @@
-use std::collections::HashMap;
+use std::fmt;
 
-use super::{common::{ParseResult, DomainNameLabel}, LabelPtrMap};
+use super::{common::{ParseResult, DomainNameLabel}, types::{RecordClass, RecordType}, CompressionTable};
 
 mod a_record;
 mod ns_record;
@@ -9,7 +9,12 @@ mod soa_record;
 mod mx_record;
 mod txt_record;
 mod aaaa_record;
+mod tsig_record;
+mod hinfo_record;
+mod rp_record;
+mod loc_record;
 mod unknown_record;
+mod registry;
 
 pub use a_record::DNSARecord;
 pub use ns_record::DNSNSRecord;
@@ -18,10 +23,15 @@ pub use soa_record::DNSSOARecord;
 pub use mx_record::DNSMXRecord;
 pub use txt_record::DNSTXTRecord;
 pub use aaaa_record::DNSAAAARecord;
+pub use tsig_record::{DNSTSIGRecord, TSIGError};
+pub use hinfo_record::DNSHINFORecord;
+pub use rp_record::DNSRPRecord;
+pub use loc_record::DNSLOCRecord;
 pub use unknown_record::DNSUnknownRecord;
+pub use registry::{register_record_type, DNSCustomRecord, DynRecordPack};
 
 pub trait DNSRecordPack {
-    const RTYPE: u16;
+    const RTYPE: RecordType;
 
     fn parse(
         data: &[u8],
@@ -29,24 +39,27 @@ pub trait DNSRecordPack {
         len: usize,
     ) -> Result<Self, String> where Self: Sized;
 
-    fn serialize(
+    /// Appends the record data to `buf`, names may only be compressed
+    /// when a table is given
+    fn serialize_into(
         &self,
-        label_ptr_map: &mut LabelPtrMap,
-        ptr: usize,
-    ) -> Result<Vec<u8>, String>;
+        buf: &mut Vec<u8>,
+        table: Option<&mut CompressionTable>,
+    ) -> Result<(), String>;
 }
 
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct DNSRecord {
     /// Domain name
     pub name: String,
 
     /// Record type (16 bit)
-    pub rtype: u16,
+    pub rtype: RecordType,
 
     /// Class (16 bit)
-    pub class: u16,
+    pub class: RecordClass,
 
     /// TTL (32 bit)
     pub ttl: u32,
@@ -58,13 +71,36 @@ pub struct DNSRecord {
     pub record: DNSRecordData,
 }
 
+/// Zone file line: `<name> <ttl> <class> <type> <rdata>`
+impl fmt::Display for DNSRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}\t{}\t{}\t{}\t{}",
+            DomainNameLabel::presentation(&self.name),
+            self.ttl,
+            self.class,
+            self.rtype,
+            self.record,
+        )
+    }
+}
+
 pub struct DNSRecordsParser<'data> {
     packet: &'data [u8],
+    empty_rdata: bool,
 }
 
 impl<'data> DNSRecordsParser<'data> {
     pub fn new(packet: &'data [u8]) -> Self {
-        Self { packet }
+        Self { packet, empty_rdata: false }
+    }
+
+    /// Takes zero length record data as `DNSRecordData::Empty`, which dynamic
+    /// updates use to refer to whole RRsets (RFC 2136 2.4). Anywhere else it's
+    /// up to the parser of the type, most of them reject it.
+    pub fn with_empty_rdata(self, empty_rdata: bool) -> Self {
+        Self { empty_rdata, ..self }
     }
 
     pub fn parse(&self, num_records: usize, startptr: usize) -> ParseResult<Vec<DNSRecord>> {
@@ -86,19 +122,14 @@ impl<'data> DNSRecordsParser<'data> {
         let (name, consumed_len) = DomainNameLabel::parse(self.packet, ptr)?;
         let end = ptr + consumed_len;
 
-        let rtype = u16::from_be_bytes([self.packet[end + 0], self.packet[end + 1]]);
-        let class = u16::from_be_bytes([self.packet[end + 2], self.packet[end + 3]]);
-        let ttl = u32::from_be_bytes([
-            self.packet[end + 4],
-            self.packet[end + 5],
-            self.packet[end + 6],
-            self.packet[end + 7],
-        ]);
+        let fields = self.packet
+            .get(end..(end + 10))
+            .ok_or("DNSPacketParser: Unexpected end of packet in a record")?;
 
-        let len = u16::from_be_bytes([
-            self.packet[end + 8],
-            self.packet[end + 9],
-        ]);
+        let rtype = u16::from_be_bytes([fields[0], fields[1]]).into();
+        let class = u16::from_be_bytes([fields[2], fields[3]]).into();
+        let ttl = u32::from_be_bytes([fields[4], fields[5], fields[6], fields[7]]);
+        let len = u16::from_be_bytes([fields[8], fields[9]]);
 
         let (record, record_len) = self.parse_record_data(
             rtype,
@@ -119,74 +150,33 @@ impl<'data> DNSRecordsParser<'data> {
         ))
     }
 
-    fn parse_record_data(
+    pub(crate) fn parse_record_data(
         &self,
-        rtype: u16,
+        rtype: RecordType,
         len: usize,
         ptr: usize,
     ) -> ParseResult<DNSRecordData> {
+        if len == 0 && self.empty_rdata {
+            return Ok((DNSRecordData::Empty, 0));
+        }
 
-        // TODO: Find a way to refactor this
-        match rtype {
-            DNSARecord::RTYPE => {
-                let record_data = DNSRecordData::A(
-                    DNSARecord::parse(&self.packet, ptr, len)?
-                );
-                return Ok((record_data, len))
-            },
-            DNSNSRecord::RTYPE => {
-                let record_data = DNSRecordData::NS(
-                    DNSNSRecord::parse(&self.packet, ptr, len)?
-                );
-                return Ok((record_data, len))
-            },
-            DNSCNameRecord::RTYPE => {
-                let record_data = DNSRecordData::CNAME(
-                    DNSCNameRecord::parse(&self.packet, ptr, len)?
-                );
-                return Ok((record_data, len))
-            },
-            DNSSOARecord::RTYPE => {
-                let record_data = DNSRecordData::SOA(
-                    DNSSOARecord::parse(self.packet, ptr, len)?
-                );
-
-                Ok((record_data, len))
-            },
-            DNSMXRecord::RTYPE => {
-                let record_data = DNSRecordData::MX(
-                    DNSMXRecord::parse(self.packet, ptr, len)?
-                );
-
-                Ok((record_data, len))
-            },
-            DNSTXTRecord::RTYPE => {
-                let record_data = DNSRecordData::TXT(
-                    DNSTXTRecord::parse(self.packet, ptr, len)?
-                );
+        // Parsers only get to see the packet up to the end of the record
+        // data, names in it can still point back at earlier parts
+        let packet = self.packet
+            .get(..(ptr + len))
+            .ok_or("DNSPacketParser: Record data exceeds the packet")?;
 
-                Ok((record_data, len))
-            },
-            DNSAAAARecord::RTYPE => {
-                let record_data = DNSRecordData::AAAA(
-                    DNSAAAARecord::parse(self.packet, ptr, len)?
-                );
+        let record_data = match registry::parser_for(rtype) {
+            Some(parse) => parse(packet, ptr, len)?,
+            None => DNSRecordData::Unknown(DNSUnknownRecord::parse(packet, ptr, len)?),
+        };
 
-                Ok((record_data, len))
-            },
-            _ => {
-                Ok((
-                    DNSRecordData::Unknown(
-                        DNSUnknownRecord::parse(&self.packet, ptr, len)?
-                    ),
-                    len
-                ))
-            },
-        }
+        Ok((record_data, len))
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum DNSRecordData {
     A(DNSARecord),
     NS(DNSNSRecord),
@@ -195,65 +185,233 @@ pub enum DNSRecordData {
     MX(DNSMXRecord),
     TXT(DNSTXTRecord),
     AAAA(DNSAAAARecord),
+    HINFO(DNSHINFORecord),
+    RP(DNSRPRecord),
+    LOC(DNSLOCRecord),
+    TSIG(DNSTSIGRecord),
     Unknown(DNSUnknownRecord),
 
+    /// A type registered with `register_record_type`
+    Custom(DNSCustomRecord),
+
+    /// Zero length record data, only valid in dynamic updates
+    Empty,
 
     // Wildcard is 255
 }
 
+macro_rules! record_data_from {
+    ($($variant:ident($record:ty),)*) => {
+        $(
+            impl From<$record> for DNSRecordData {
+                fn from(record: $record) -> Self {
+                    Self::$variant(record)
+                }
+            }
+        )*
+    };
+}
+
+record_data_from! {
+    A(DNSARecord),
+    NS(DNSNSRecord),
+    CNAME(DNSCNameRecord),
+    SOA(DNSSOARecord),
+    MX(DNSMXRecord),
+    TXT(DNSTXTRecord),
+    AAAA(DNSAAAARecord),
+    HINFO(DNSHINFORecord),
+    RP(DNSRPRecord),
+    LOC(DNSLOCRecord),
+    TSIG(DNSTSIGRecord),
+    Unknown(DNSUnknownRecord),
+}
+
 impl DNSRecordData {
-    fn serialize(&self, label_ptr_map: &mut LabelPtrMap, startptr: usize) -> Result<Vec<u8>, String> {
+    pub fn serialize_into(&self, buf: &mut Vec<u8>, table: Option<&mut CompressionTable>) -> Result<(), String> {
         match self {
-            Self::A(record) => record.serialize(label_ptr_map, startptr),
-            Self::NS(record) => record.serialize(label_ptr_map, startptr),
-            Self::CNAME(record) => record.serialize(label_ptr_map, startptr),
-            Self::SOA(record) => record.serialize(label_ptr_map, startptr),
-            Self::MX(record) => record.serialize(label_ptr_map, startptr),
-            Self::TXT(record) => record.serialize(label_ptr_map, startptr),
-            Self::AAAA(record) => record.serialize(label_ptr_map, startptr),
-            Self::Unknown(record) => record.serialize(label_ptr_map, startptr),
+            Self::A(record) => record.serialize_into(buf, table),
+            Self::NS(record) => record.serialize_into(buf, table),
+            Self::CNAME(record) => record.serialize_into(buf, table),
+            Self::SOA(record) => record.serialize_into(buf, table),
+            Self::MX(record) => record.serialize_into(buf, table),
+            Self::TXT(record) => record.serialize_into(buf, table),
+            Self::AAAA(record) => record.serialize_into(buf, table),
+            Self::HINFO(record) => record.serialize_into(buf, table),
+            Self::RP(record) => record.serialize_into(buf, table),
+            Self::LOC(record) => record.serialize_into(buf, table),
+            Self::TSIG(record) => record.serialize_into(buf, table),
+            Self::Unknown(record) => record.serialize_into(buf, table),
+            Self::Custom(record) => record.serialize_into(buf, table),
+            Self::Empty => Ok(()),
         }
     }
+
+    /// The record data without any compression, which is also its canonical form
+    pub fn to_bytes(&self) -> Result<Vec<u8>, String> {
+        let mut data = vec![];
+        self.serialize_into(&mut data, None)?;
+
+        Ok(data)
+    }
 }
 
-pub struct DNSRecordSerializer<'data, 'lmap> {
+impl fmt::Display for DNSRecordData {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::A(record) => record.fmt(f),
+            Self::NS(record) => record.fmt(f),
+            Self::CNAME(record) => record.fmt(f),
+            Self::SOA(record) => record.fmt(f),
+            Self::MX(record) => record.fmt(f),
+            Self::TXT(record) => record.fmt(f),
+            Self::AAAA(record) => record.fmt(f),
+            Self::HINFO(record) => record.fmt(f),
+            Self::RP(record) => record.fmt(f),
+            Self::LOC(record) => record.fmt(f),
+            Self::TSIG(record) => record.fmt(f),
+            Self::Unknown(record) => record.fmt(f),
+            Self::Custom(record) => record.fmt(f),
+            // Generic form of empty record data (RFC 3597 section 5)
+            Self::Empty => f.write_str("\\# 0"),
+        }
+    }
+}
+
+pub struct DNSRecordSerializer<'data, 'table> {
     records: &'data [DNSRecord],
-    label_ptr_map: &'lmap mut LabelPtrMap,
-    ptr: usize,
+    table: &'table mut CompressionTable,
 }
 
-impl<'data, 'lmap> DNSRecordSerializer<'data, 'lmap> {
+impl<'data, 'table> DNSRecordSerializer<'data, 'table> {
     pub fn new(
         records: &'data [DNSRecord],
-        label_ptr_map: &'lmap mut HashMap<String, usize>,
-        ptr: usize,
+        table: &'table mut CompressionTable,
     ) -> Self {
-        Self { records, label_ptr_map, ptr }
+        Self { records, table }
     }
 
-    pub fn serialize(&mut self) -> Result<Vec<u8>, String> {
-        let mut ptr = 0;
-        let mut data = Vec::new();
-
+    pub fn serialize_into(&mut self, buf: &mut Vec<u8>) -> Result<(), String> {
         for record in self.records {
-            let (name, mut label_ptr_map) = DomainNameLabel::serialize(
-                &record.name,
-                Some(self.label_ptr_map),
-            )?;
-
-            label_ptr_map.iter_mut().for_each(|(_, x)| *x += ptr + self.ptr);
-            self.label_ptr_map.extend(label_ptr_map);
-
-            data.extend_from_slice(&name);
-            data.extend_from_slice(&record.rtype.to_be_bytes());
-            data.extend_from_slice(&record.class.to_be_bytes());
-            data.extend_from_slice(&record.ttl.to_be_bytes());
-            data.extend_from_slice(&record.len.to_be_bytes());
-            data.extend_from_slice(&record.record.serialize(self.label_ptr_map, data.len())?);
-
-            ptr += name.len() + 2 + 2 + 4 + 2 + record.len as usize;
+            DomainNameLabel::serialize_into(&record.name, buf, Some(self.table))?;
+
+            buf.extend_from_slice(&u16::from(record.rtype).to_be_bytes());
+            buf.extend_from_slice(&u16::from(record.class).to_be_bytes());
+            buf.extend_from_slice(&record.ttl.to_be_bytes());
+
+            // The length is only known once the record data is written, it
+            // can differ from `record.len` depending on the compression
+            let len_ptr = buf.len();
+            buf.extend_from_slice(&[0, 0]);
+
+            record.record.serialize_into(buf, Some(self.table))?;
+
+            let len = u16::try_from(buf.len() - len_ptr - 2)
+                .map_err(|_| format!("Record data of {} exceeds the maximum length allowed", record.name))?;
+            buf[len_ptr..(len_ptr + 2)].copy_from_slice(&len.to_be_bytes());
         }
 
-        Ok(data)
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::{types::{RecordClass, RecordType}, CompressionTable};
+
+    use super::{DNSAAAARecord, DNSARecord, DNSCNameRecord, DNSHINFORecord, DNSMXRecord, DNSNSRecord, DNSRPRecord, DNSRecord, DNSRecordData, DNSRecordSerializer, DNSRecordsParser, DNSSOARecord, DNSTSIGRecord, DNSTXTRecord, DNSUnknownRecord, TSIGError};
+
+    #[test]
+    fn parses_and_serializes_informational_records() {
+        let records = vec![
+            DNSRecord {
+                name: "host.example.com.".to_owned(),
+                rtype: RecordType::HINFO,
+                class: RecordClass::IN,
+                ttl: 3600,
+                len: 0,
+                record: DNSRecordData::HINFO(DNSHINFORecord { cpu: "INTEL-386".to_owned(), os: "Linux".to_owned() }),
+            },
+            DNSRecord {
+                name: "host.example.com.".to_owned(),
+                rtype: RecordType::RP,
+                class: RecordClass::IN,
+                ttl: 3600,
+                len: 0,
+                record: DNSRecordData::RP(DNSRPRecord { mbox: "admin.example.com.".to_owned(), txt: "".to_owned() }),
+            },
+        ];
+
+        let mut data = vec![];
+
+        DNSRecordSerializer::new(&records, &mut CompressionTable::new())
+            .serialize_into(&mut data)
+            .unwrap();
+
+        let (parsed, len) = DNSRecordsParser::new(&data).parse(2, 0).unwrap();
+
+        assert_eq!(len, data.len());
+        assert_eq!(parsed.len(), 2);
+
+        for (parsed, record) in parsed.iter().zip(&records) {
+            assert_eq!(parsed.record, record.record);
+        }
+    }
+
+    #[test]
+    fn displays_records_in_presentation_format() {
+        let record = |rtype: RecordType, record: DNSRecordData| DNSRecord {
+            name: "example.com.".to_owned(),
+            rtype,
+            class: RecordClass::IN,
+            ttl: 300,
+            len: 0,
+            record,
+        };
+
+        let cases = [
+            (record(RecordType::A, DNSRecordData::A(DNSARecord { ip: [192, 0, 2, 1] })), "192.0.2.1"),
+            (record(RecordType::AAAA, DNSRecordData::AAAA(DNSAAAARecord { ip: [0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1] })), "2001:db8::1"),
+            (record(RecordType::NS, DNSRecordData::NS(DNSNSRecord { nsdname: "ns1.example.com.".to_owned() })), "ns1.example.com."),
+            (record(RecordType::CNAME, DNSRecordData::CNAME(DNSCNameRecord { cname: "".to_owned() })), "."),
+            (record(RecordType::MX, DNSRecordData::MX(DNSMXRecord { preference: 10, exchange: "mail.example.com.".to_owned() })), "10 mail.example.com."),
+            (
+                record(RecordType::SOA, DNSRecordData::SOA(DNSSOARecord {
+                    mname: "ns1.example.com.".to_owned(),
+                    rname: "hostmaster.example.com.".to_owned(),
+                    serial: 2024010101,
+                    refresh: 10800,
+                    retry: 900,
+                    expire: 604800,
+                    minimum: 86400,
+                })),
+                "ns1.example.com. hostmaster.example.com. 2024010101 10800 900 604800 86400",
+            ),
+            (record(RecordType::TXT, DNSRecordData::TXT(DNSTXTRecord { text: "say \"hi\"\\\n".to_owned() })), "\"say \\\"hi\\\"\\\\\\010\""),
+            (record(RecordType::HINFO, DNSRecordData::HINFO(DNSHINFORecord { cpu: "INTEL-386".to_owned(), os: "Linux".to_owned() })), "\"INTEL-386\" \"Linux\""),
+            (record(RecordType::RP, DNSRecordData::RP(DNSRPRecord { mbox: "admin.example.com.".to_owned(), txt: "".to_owned() })), "admin.example.com. ."),
+            (record(RecordType::Unknown(65280), DNSRecordData::Unknown(DNSUnknownRecord { data: vec![0xde, 0xad] })), "\\# 2 dead"),
+            (record(RecordType::A, DNSRecordData::Empty), "\\# 0"),
+            (
+                record(RecordType::TSIG, DNSRecordData::TSIG(DNSTSIGRecord {
+                    algorithm: "hmac-sha256.".to_owned(),
+                    time_signed: 1_700_000_000,
+                    fudge: 300,
+                    mac: b"foo".to_vec(),
+                    original_id: 1234,
+                    error: TSIGError::BadTime,
+                    other_data: vec![0, 0, 0x65, 0x53, 0xf1, 0x00],
+                })),
+                "hmac-sha256. 1700000000 300 3 Zm9v 1234 BADTIME 6 AABlU/EA",
+            ),
+        ];
+
+        for (record, rdata) in cases {
+            assert_eq!(record.to_string(), format!("example.com.\t300\tIN\t{}\t{}", record.rtype, rdata));
+        }
+
+        // Long texts are split in 255 byte strings, the same way they're serialized
+        let text = DNSTXTRecord { text: "a".repeat(300) };
+        assert_eq!(text.to_string(), format!("\"{}\" \"{}\"", "a".repeat(255), "a".repeat(45)));
     }
 }
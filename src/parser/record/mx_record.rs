@@ -1,24 +1,34 @@
-use crate::parser::common::DomainNameLabel;
+use std::fmt;
 
-use super::DNSRecordPack;
+use crate::parser::{common::DomainNameLabel, CompressionTable};
 
+use super::{DNSRecordPack, RecordType};
 
-#[derive(Debug, PartialEq, Eq)]
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct DNSMXRecord {
-    preference: u16,
-    exchange: String
+    pub preference: u16,
+    pub exchange: String,
+}
+
+impl fmt::Display for DNSMXRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.preference, DomainNameLabel::presentation(&self.exchange))
+    }
 }
 
 impl DNSRecordPack for DNSMXRecord {
-    const RTYPE: u16 = 15;
+    const RTYPE: RecordType = RecordType::MX;
 
     fn parse(
         data: &[u8],
         startptr: usize,
         _len: usize,
     ) -> Result<Self, String> where Self: Sized {
-        println!("MXLEN: {:?}", _len);
-        let preference = u16::from_be_bytes([data[startptr + 0], data[startptr + 1]]);
+        let preference = data.get(startptr..(startptr + 2))
+            .map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]))
+            .ok_or("Failed to parse MX record, record data is too short")?;
         let (exchange, _) = DomainNameLabel::parse(data, startptr + 2)?;
 
 
@@ -28,24 +38,13 @@ impl DNSRecordPack for DNSMXRecord {
         })
     }
 
-    fn serialize(
+    fn serialize_into(
         &self,
-        label_ptr_map: &mut crate::parser::LabelPtrMap,
-        ptr: usize,
-    ) -> Result<Vec<u8>, String> {
-        let mut data: Vec<u8> = vec![];
-
-        data.extend_from_slice(&self.preference.to_be_bytes());
-
-        let (exchange_bytes, mut temp_label_ptr_map) = DomainNameLabel::serialize(
-            &self.exchange,
-            Some(label_ptr_map),
-        )?;
-
-        temp_label_ptr_map.iter_mut().for_each(|(_, x)| *x += ptr + data.len());
-        label_ptr_map.extend(temp_label_ptr_map);
-        data.extend_from_slice(&exchange_bytes);
+        buf: &mut Vec<u8>,
+        table: Option<&mut CompressionTable>,
+    ) -> Result<(), String> {
+        buf.extend_from_slice(&self.preference.to_be_bytes());
 
-        Ok(data)
+        DomainNameLabel::serialize_into(&self.exchange, buf, table)
     }
 }
@@ -1,15 +1,24 @@
-use crate::parser::{common::DomainNameLabel, LabelPtrMap};
+use std::fmt;
 
-use super::DNSRecordPack;
+use crate::parser::{common::DomainNameLabel, CompressionTable};
 
+use super::{DNSRecordPack, RecordType};
 
-#[derive(Debug, PartialEq, Eq)]
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct DNSNSRecord {
     pub nsdname: String,
 }
 
+impl fmt::Display for DNSNSRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(DomainNameLabel::presentation(&self.nsdname))
+    }
+}
+
 impl DNSRecordPack for DNSNSRecord {
-    const RTYPE: u16 = 2;
+    const RTYPE: RecordType = RecordType::NS;
 
     fn parse(
         data: &[u8],
@@ -21,19 +30,11 @@ impl DNSRecordPack for DNSNSRecord {
         Ok(Self { nsdname: name })
     }
 
-    fn serialize(
+    fn serialize_into(
         &self,
-        label_ptr_map: &mut LabelPtrMap,
-        ptr: usize,
-    ) -> Result<Vec<u8>, String> {
-        let (bytes, mut nsdname_label_ptr_map) = DomainNameLabel::serialize(
-            &self.nsdname,
-            Some(label_ptr_map)
-        )?;
-
-        nsdname_label_ptr_map.iter_mut().for_each(|(_, x)| *x += ptr);
-        label_ptr_map.extend(nsdname_label_ptr_map);
-        
-        Ok(bytes)
+        buf: &mut Vec<u8>,
+        table: Option<&mut CompressionTable>,
+    ) -> Result<(), String> {
+        DomainNameLabel::serialize_into(&self.nsdname, buf, table)
     }
 }
@@ -0,0 +1,315 @@
+use std::{any::Any, fmt, ops::RangeInclusive, sync::RwLock};
+
+use crate::parser::{types::RecordType, CompressionTable};
+
+use super::{DNSAAAARecord, DNSARecord, DNSCNameRecord, DNSHINFORecord, DNSLOCRecord, DNSMXRecord, DNSNSRecord, DNSRPRecord, DNSRecordData, DNSRecordPack, DNSSOARecord, DNSTSIGRecord, DNSTXTRecord};
+
+/// Parses `len` bytes of record data starting at `startptr`
+pub(super) type ParseFn = fn(&[u8], usize, usize) -> Result<DNSRecordData, String>;
+
+fn parse_as<T: DNSRecordPack + Into<DNSRecordData>>(data: &[u8], startptr: usize, len: usize) -> Result<DNSRecordData, String> {
+    Ok(T::parse(data, startptr, len)?.into())
+}
+
+fn parse_custom<T: DynRecordPack + DNSRecordPack>(data: &[u8], startptr: usize, len: usize) -> Result<DNSRecordData, String> {
+    Ok(DNSRecordData::Custom(DNSCustomRecord::new(T::parse(data, startptr, len)?)))
+}
+
+/// Types with a `DNSRecordData` variant of their own
+const BUILTIN_TYPES: [(RecordType, ParseFn); 11] = [
+    (DNSARecord::RTYPE, parse_as::<DNSARecord>),
+    (DNSNSRecord::RTYPE, parse_as::<DNSNSRecord>),
+    (DNSCNameRecord::RTYPE, parse_as::<DNSCNameRecord>),
+    (DNSSOARecord::RTYPE, parse_as::<DNSSOARecord>),
+    (DNSMXRecord::RTYPE, parse_as::<DNSMXRecord>),
+    (DNSTXTRecord::RTYPE, parse_as::<DNSTXTRecord>),
+    (DNSAAAARecord::RTYPE, parse_as::<DNSAAAARecord>),
+    (DNSHINFORecord::RTYPE, parse_as::<DNSHINFORecord>),
+    (DNSRPRecord::RTYPE, parse_as::<DNSRPRecord>),
+    (DNSLOCRecord::RTYPE, parse_as::<DNSLOCRecord>),
+    (DNSTSIGRecord::RTYPE, parse_as::<DNSTSIGRecord>),
+];
+
+/// Types applications can register parsers for
+const PRIVATE_USE_TYPES: RangeInclusive<u16> = 65280..=65534;
+
+/// Types registered by the application, shared by every parser
+static CUSTOM_TYPES: RwLock<Vec<(RecordType, ParseFn)>> = RwLock::new(Vec::new());
+
+/// The parser for `rtype`, `None` when its data should be kept as unknown
+pub(super) fn parser_for(rtype: RecordType) -> Option<ParseFn> {
+    if let Some((_, parse)) = BUILTIN_TYPES.iter().find(|(builtin, _)| *builtin == rtype) {
+        return Some(*parse);
+    }
+
+    CUSTOM_TYPES
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .iter()
+        .find(|(custom, _)| *custom == rtype)
+        .map(|(_, parse)| *parse)
+}
+
+/// Makes every parser decode `T::RTYPE` records as `DNSRecordData::Custom`
+///
+/// Only types in the private use range (65280-65534, RFC 6895 section 3.1)
+/// can be registered, and only once. Assigned types would change how every
+/// parser in the process reads them, and may get a variant of their own later.
+pub fn register_record_type<T: DynRecordPack + DNSRecordPack>() -> Result<(), String> {
+    if ! PRIVATE_USE_TYPES.contains(&u16::from(T::RTYPE)) {
+        return Err(format!("Record type {} isn't in the private use range and can't be registered", T::RTYPE));
+    }
+
+    let mut types = CUSTOM_TYPES.write().unwrap_or_else(|e| e.into_inner());
+
+    if types.iter().any(|(rtype, _)| *rtype == T::RTYPE) {
+        return Err(format!("Record type {} is already registered", T::RTYPE));
+    }
+
+    types.push((T::RTYPE, parse_custom::<T>));
+
+    Ok(())
+}
+
+/// Object safe side of `DNSRecordPack`, implemented for every record type
+/// that can be cloned, compared and displayed
+pub trait DynRecordPack: fmt::Debug + fmt::Display + Send + Sync + 'static {
+    fn rtype(&self) -> RecordType;
+
+    fn write_into(
+        &self,
+        buf: &mut Vec<u8>,
+        table: Option<&mut CompressionTable>,
+    ) -> Result<(), String>;
+
+    fn clone_box(&self) -> Box<dyn DynRecordPack>;
+
+    fn eq_dyn(&self, other: &dyn DynRecordPack) -> bool;
+
+    fn as_any(&self) -> &dyn Any;
+}
+
+impl<T> DynRecordPack for T
+where
+    T: DNSRecordPack + fmt::Debug + fmt::Display + Clone + PartialEq + Send + Sync + 'static,
+{
+    fn rtype(&self) -> RecordType {
+        T::RTYPE
+    }
+
+    fn write_into(
+        &self,
+        buf: &mut Vec<u8>,
+        table: Option<&mut CompressionTable>,
+    ) -> Result<(), String> {
+        self.serialize_into(buf, table)
+    }
+
+    fn clone_box(&self) -> Box<dyn DynRecordPack> {
+        Box::new(self.clone())
+    }
+
+    fn eq_dyn(&self, other: &dyn DynRecordPack) -> bool {
+        other.as_any().downcast_ref::<T>().is_some_and(|other| other == self)
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// Record data of a type registered with `register_record_type`
+#[derive(Debug)]
+pub struct DNSCustomRecord(Box<dyn DynRecordPack>);
+
+impl DNSCustomRecord {
+    pub fn new<T: DynRecordPack>(record: T) -> Self {
+        Self(Box::new(record))
+    }
+
+    pub fn rtype(&self) -> RecordType {
+        self.0.rtype()
+    }
+
+    pub fn downcast_ref<T: DynRecordPack>(&self) -> Option<&T> {
+        self.0.as_any().downcast_ref::<T>()
+    }
+
+    pub fn serialize_into(
+        &self,
+        buf: &mut Vec<u8>,
+        table: Option<&mut CompressionTable>,
+    ) -> Result<(), String> {
+        self.0.write_into(buf, table)
+    }
+}
+
+impl Clone for DNSCustomRecord {
+    fn clone(&self) -> Self {
+        Self(self.0.clone_box())
+    }
+}
+
+impl PartialEq for DNSCustomRecord {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.eq_dyn(other.0.as_ref())
+    }
+}
+
+impl Eq for DNSCustomRecord {}
+
+impl fmt::Display for DNSCustomRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+/// Custom records go through serde as their type and raw record data, the
+/// same way as unknown ones, and are parsed again by the registered type
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct RawCustomRecord {
+    rtype: u16,
+    data: Vec<u8>,
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for DNSCustomRecord {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut data = vec![];
+        self.serialize_into(&mut data, None).map_err(serde::ser::Error::custom)?;
+
+        RawCustomRecord { rtype: self.rtype().into(), data }.serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for DNSCustomRecord {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let raw = RawCustomRecord::deserialize(deserializer)?;
+        let rtype = RecordType::from(raw.rtype);
+
+        match parser_for(rtype).map(|parse| parse(&raw.data, 0, raw.data.len())) {
+            Some(Ok(DNSRecordData::Custom(record))) => Ok(record),
+            Some(Err(e)) => Err(serde::de::Error::custom(e)),
+            _ => Err(serde::de::Error::custom(format!("Record type {} isn't registered", rtype))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fmt;
+
+    use crate::parser::{packet::{DNSPacket, DNSPacketParser}, header::{DNSHeader, DNSHeaderType, Opcode, ResultCode}, record::{DNSARecord, DNSRecord, DNSRecordData, DNSRecordPack}, types::{RecordClass, RecordType}, CompressionTable, DomainNameLabel};
+
+    use super::{register_record_type, DNSCustomRecord};
+
+    /// Weighted pointer to another name, its target is compressed like NS/MX
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    struct DNSWeightRecord {
+        weight: u16,
+        target: String,
+    }
+
+    impl fmt::Display for DNSWeightRecord {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "{} {}", self.weight, DomainNameLabel::presentation(&self.target))
+        }
+    }
+
+    impl DNSRecordPack for DNSWeightRecord {
+        const RTYPE: RecordType = RecordType::Unknown(65300);
+
+        fn parse(data: &[u8], startptr: usize, len: usize) -> Result<Self, String> {
+            if len < 3 {
+                return Err("WEIGHT record is too short".to_owned());
+            }
+
+            let (target, _) = DomainNameLabel::parse(data, startptr + 2)?;
+
+            Ok(Self { weight: u16::from_be_bytes([data[startptr], data[startptr + 1]]), target })
+        }
+
+        fn serialize_into(&self, buf: &mut Vec<u8>, table: Option<&mut CompressionTable>) -> Result<(), String> {
+            buf.extend_from_slice(&self.weight.to_be_bytes());
+
+            DomainNameLabel::serialize_into(&self.target, buf, table)
+        }
+    }
+
+    #[test]
+    fn parses_registered_record_types() {
+        register_record_type::<DNSWeightRecord>().unwrap();
+
+        let record = |name: &str, rtype: RecordType, record: DNSRecordData| DNSRecord {
+            name: name.to_owned(),
+            rtype,
+            class: RecordClass::IN,
+            ttl: 300,
+            len: 0,
+            record,
+        };
+        let weight = DNSWeightRecord { weight: 10, target: "www.example.com.".to_owned() };
+
+        let packet = DNSPacket {
+            header: DNSHeader {
+                id: 1,
+                qr: DNSHeaderType::Response,
+                opcode: Opcode::Query,
+                aa: true,
+                tc: false,
+                rd: false,
+                ra: false,
+                z: 0,
+                ad: false,
+                cd: false,
+                rcode: ResultCode::NoError,
+                qdcount: 0,
+                ancount: 2,
+                nscount: 0,
+                arcount: 0,
+            },
+            questions: vec![],
+            answers: vec![
+                record("www.example.com.", RecordType::A, DNSRecordData::A(DNSARecord { ip: [192, 0, 2, 1] })),
+                record("example.com.", DNSWeightRecord::RTYPE, DNSRecordData::Custom(DNSCustomRecord::new(weight.clone()))),
+            ],
+            authority: vec![],
+            additional: vec![],
+        };
+
+        let data = packet.serialize().unwrap();
+        let parsed = DNSPacketParser::new(&data).parse().unwrap();
+
+        // The target points back at the owner of the A record
+        assert!(data.ends_with(&[0x00, 0x0a, 0xc0, 0x0c]));
+
+        let DNSRecordData::Custom(ref custom) = parsed.answers[1].record else {
+            panic!("Expected a custom record, got {:?}", parsed.answers[1].record);
+        };
+
+        assert_eq!(custom.rtype(), DNSWeightRecord::RTYPE);
+        assert_eq!(custom.downcast_ref::<DNSWeightRecord>(), Some(&weight));
+        assert_eq!(parsed.answers[1].record, packet.answers[1].record);
+        assert_eq!(parsed.answers[1].to_string(), "example.com.\t300\tIN\tTYPE65300\t10 www.example.com.");
+        assert_eq!(parsed.serialize(), Ok(data));
+
+        assert_eq!(
+            register_record_type::<DNSWeightRecord>(),
+            Err("Record type TYPE65300 is already registered".to_owned()),
+        );
+        assert_eq!(
+            register_record_type::<DNSARecord>(),
+            Err("Record type A isn't in the private use range and can't be registered".to_owned()),
+        );
+
+        #[cfg(feature = "serde")]
+        {
+            let text = serde_json::to_string(&packet).unwrap();
+
+            assert!(text.contains(r#"{"Custom":{"rtype":65300,"data":[0,10,3,119,119,119"#));
+            assert_eq!(serde_json::from_str::<DNSPacket>(&text).unwrap(), packet);
+        }
+    }
+}
@@ -0,0 +1,48 @@
+use std::fmt;
+
+use crate::parser::{common::DomainNameLabel, CompressionTable};
+
+use super::{DNSRecordPack, RecordType};
+
+
+/// Responsible person (RFC 1183 section 2.2)
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DNSRPRecord {
+    /// Mailbox of the responsible person, the first label being the local part
+    pub mbox: String,
+
+    /// Name of the TXT records holding more information, the root if there's none
+    pub txt: String,
+}
+
+impl fmt::Display for DNSRPRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", DomainNameLabel::presentation(&self.mbox), DomainNameLabel::presentation(&self.txt))
+    }
+}
+
+impl DNSRecordPack for DNSRPRecord {
+    const RTYPE: RecordType = RecordType::RP;
+
+    fn parse(
+        data: &[u8],
+        startptr: usize,
+        _len: usize,
+    ) -> Result<Self, String> where Self: Sized {
+        let (mbox, consumed_len) = DomainNameLabel::parse(data, startptr)?;
+        let (txt, _) = DomainNameLabel::parse(data, startptr + consumed_len)?;
+
+        Ok(Self { mbox, txt })
+    }
+
+    fn serialize_into(
+        &self,
+        buf: &mut Vec<u8>,
+        _table: Option<&mut CompressionTable>,
+    ) -> Result<(), String> {
+        // Names in types newer than RFC 1035 must not be compressed (RFC 3597 section 4)
+        DomainNameLabel::serialize_into(&self.mbox, buf, None)?;
+        DomainNameLabel::serialize_into(&self.txt, buf, None)
+    }
+}
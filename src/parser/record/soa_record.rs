@@ -1,21 +1,40 @@
-use crate::parser::{common::DomainNameLabel, LabelPtrMap};
+use std::fmt;
 
-use super::DNSRecordPack;
+use crate::parser::{common::DomainNameLabel, CompressionTable};
 
+use super::{DNSRecordPack, RecordType};
 
-#[derive(Debug, PartialEq, Eq)]
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct DNSSOARecord {
-    mname: String,
-    rname: String,
-    serial: u32,
-    refresh: u32,
-    retry: u32,
-    expire: u32,
-    minimum: u32,
+    pub mname: String,
+    pub rname: String,
+    pub serial: u32,
+    pub refresh: u32,
+    pub retry: u32,
+    pub expire: u32,
+    pub minimum: u32,
+}
+
+impl fmt::Display for DNSSOARecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} {} {} {} {} {}",
+            DomainNameLabel::presentation(&self.mname),
+            DomainNameLabel::presentation(&self.rname),
+            self.serial,
+            self.refresh,
+            self.retry,
+            self.expire,
+            self.minimum,
+        )
+    }
 }
 
 impl DNSRecordPack for DNSSOARecord {
-    const RTYPE: u16 = 6;
+    const RTYPE: RecordType = RecordType::SOA;
 
     fn parse(
         data: &[u8],
@@ -30,19 +49,19 @@ impl DNSRecordPack for DNSSOARecord {
         ptr += consumed_len;
 
         let mut read_u32 = || {
-            let value = u32::from_be_bytes([
-                data[ptr + 0], data[ptr + 1], data[ptr + 2], data[ptr + 3],
-            ]);
+            let value = data.get(ptr..(ptr + 4))
+                .map(|bytes| u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+                .ok_or("Failed to parse SOA record, record data is too short");
             ptr += 4;
 
             value
         };
 
-        let serial = read_u32();
-        let refresh = read_u32();
-        let retry = read_u32();
-        let expire = read_u32();
-        let minimum = read_u32();
+        let serial = read_u32()?;
+        let refresh = read_u32()?;
+        let retry = read_u32()?;
+        let expire = read_u32()?;
+        let minimum = read_u32()?;
 
         Ok(Self {
             mname,
@@ -55,35 +74,20 @@ impl DNSRecordPack for DNSSOARecord {
         })
     }
 
-    fn serialize(
+    fn serialize_into(
         &self,
-        label_ptr_map: &mut LabelPtrMap,
-        ptr: usize,
-    ) -> Result<Vec<u8>, String> {
-        let mut data: Vec<u8> = vec![];
-
-        let (mname_bytes, mut temp_label_ptr_map) = DomainNameLabel::serialize(
-            &self.mname,
-            Some(&label_ptr_map),
-        )?;
-        temp_label_ptr_map.iter_mut().for_each(|(_, x)| *x += ptr + data.len());
-        data.extend_from_slice(&mname_bytes);
-        label_ptr_map.extend(temp_label_ptr_map);
-
-        let (rname_bytes, mut temp_label_ptr_map) = DomainNameLabel::serialize(
-            &self.rname,
-            Some(&label_ptr_map),
-        )?;
-        temp_label_ptr_map.iter_mut().for_each(|(_, x)| *x += ptr + data.len());
-        data.extend_from_slice(&rname_bytes);
-        label_ptr_map.extend(temp_label_ptr_map);
+        buf: &mut Vec<u8>,
+        mut table: Option<&mut CompressionTable>,
+    ) -> Result<(), String> {
+        DomainNameLabel::serialize_into(&self.mname, buf, table.as_deref_mut())?;
+        DomainNameLabel::serialize_into(&self.rname, buf, table)?;
 
-        data.extend_from_slice(&self.serial.to_be_bytes());
-        data.extend_from_slice(&self.refresh.to_be_bytes());
-        data.extend_from_slice(&self.retry.to_be_bytes());
-        data.extend_from_slice(&self.expire.to_be_bytes());
-        data.extend_from_slice(&self.minimum.to_be_bytes());
+        buf.extend_from_slice(&self.serial.to_be_bytes());
+        buf.extend_from_slice(&self.refresh.to_be_bytes());
+        buf.extend_from_slice(&self.retry.to_be_bytes());
+        buf.extend_from_slice(&self.expire.to_be_bytes());
+        buf.extend_from_slice(&self.minimum.to_be_bytes());
 
-        Ok(data)
+        Ok(())
     }
 }
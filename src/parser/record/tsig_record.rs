@@ -0,0 +1,189 @@
+use std::fmt;
+
+use crate::{crypto::base64, parser::{common::DomainNameLabel, CompressionTable}};
+
+use super::{DNSRecordPack, RecordType};
+
+
+/// Extended result codes carried in the error field of TSIG records (RFC 8945)
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(from = "u16", into = "u16"))]
+pub enum TSIGError {
+    /// No error condition
+    NoError,
+
+    /// TSIG signature failure
+    BadSig,
+
+    /// Key not recognized
+    BadKey,
+
+    /// Signature out of time window
+    BadTime,
+
+    /// Bad truncation
+    BadTrunc,
+
+    /// Any other code
+    Unknown(u16),
+}
+
+impl From<TSIGError> for u16 {
+    fn from(value: TSIGError) -> Self {
+        match value {
+            TSIGError::NoError => 0,
+            TSIGError::BadSig => 16,
+            TSIGError::BadKey => 17,
+            TSIGError::BadTime => 18,
+            TSIGError::BadTrunc => 22,
+            TSIGError::Unknown(value) => value,
+        }
+    }
+}
+
+impl From<u16> for TSIGError {
+    fn from(value: u16) -> Self {
+        match value {
+            0 => Self::NoError,
+            16 => Self::BadSig,
+            17 => Self::BadKey,
+            18 => Self::BadTime,
+            22 => Self::BadTrunc,
+            _ => Self::Unknown(value),
+        }
+    }
+}
+
+impl fmt::Display for TSIGError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NoError => f.write_str("NOERROR"),
+            Self::BadSig => f.write_str("BADSIG"),
+            Self::BadKey => f.write_str("BADKEY"),
+            Self::BadTime => f.write_str("BADTIME"),
+            Self::BadTrunc => f.write_str("BADTRUNC"),
+            Self::Unknown(value) => write!(f, "{}", value),
+        }
+    }
+}
+
+/// Transaction signature (RFC 8945 section 4.2)
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DNSTSIGRecord {
+    /// Name of the MAC algorithm (e.g. hmac-sha256.)
+    pub algorithm: String,
+
+    /// Seconds since the epoch (48 bit)
+    pub time_signed: u64,
+
+    /// Seconds of error permitted in time signed
+    pub fudge: u16,
+
+    /// Message authentication code
+    pub mac: Vec<u8>,
+
+    /// Message ID of the message before it was forwarded
+    pub original_id: u16,
+
+    /// Extended result code
+    pub error: TSIGError,
+
+    /// Empty unless error is BADTIME, in which case it holds the server's time
+    pub other_data: Vec<u8>,
+}
+
+/// Same layout as dig's, binary fields are base64 encoded
+/// ```text
+/// <algorithm> <time signed> <fudge> <mac size> <mac> <original id> <error> <other len> [<other data>]
+/// ```
+impl fmt::Display for DNSTSIGRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} {} {} {} {} {} {}",
+            DomainNameLabel::presentation(&self.algorithm),
+            self.time_signed,
+            self.fudge,
+            self.mac.len(),
+            base64::encode(&self.mac),
+            self.original_id,
+            self.error,
+            self.other_data.len(),
+        )?;
+
+        if ! self.other_data.is_empty() {
+            write!(f, " {}", base64::encode(&self.other_data))?;
+        }
+
+        Ok(())
+    }
+}
+
+impl DNSRecordPack for DNSTSIGRecord {
+    const RTYPE: RecordType = RecordType::TSIG;
+
+    fn parse(
+        data: &[u8],
+        startptr: usize,
+        len: usize,
+    ) -> Result<Self, String> where Self: Sized {
+        let end = startptr + len;
+        let (algorithm, consumed_len) = DomainNameLabel::parse(data, startptr)?;
+        let mut ptr = startptr + consumed_len;
+
+        let mut read = |size: usize| -> Result<&[u8], String> {
+            if ptr + size > end {
+                return Err("Failed to parse TSIG record, record data is too short".to_owned());
+            }
+
+            let slice = &data[ptr..(ptr + size)];
+            ptr += size;
+
+            Ok(slice)
+        };
+
+        let time = read(6)?;
+        let time_signed = u64::from_be_bytes([0, 0, time[0], time[1], time[2], time[3], time[4], time[5]]);
+        let fudge = read(2)?;
+        let fudge = u16::from_be_bytes([fudge[0], fudge[1]]);
+        let mac_size = read(2)?;
+        let mac = read(u16::from_be_bytes([mac_size[0], mac_size[1]]) as usize)?.to_vec();
+        let original_id = read(2)?;
+        let original_id = u16::from_be_bytes([original_id[0], original_id[1]]);
+        let error = read(2)?;
+        let error = u16::from_be_bytes([error[0], error[1]]).into();
+        let other_len = read(2)?;
+        let other_data = read(u16::from_be_bytes([other_len[0], other_len[1]]) as usize)?.to_vec();
+
+        Ok(Self {
+            algorithm,
+            time_signed,
+            fudge,
+            mac,
+            original_id,
+            error,
+            other_data,
+        })
+    }
+
+    fn serialize_into(
+        &self,
+        buf: &mut Vec<u8>,
+        _table: Option<&mut CompressionTable>,
+    ) -> Result<(), String> {
+        // The algorithm name must not be compressed
+        DomainNameLabel::serialize_into(&self.algorithm, buf, None)?;
+
+        buf.extend_from_slice(&self.time_signed.to_be_bytes()[2..]);
+        buf.extend_from_slice(&self.fudge.to_be_bytes());
+        buf.extend_from_slice(&(self.mac.len() as u16).to_be_bytes());
+        buf.extend_from_slice(&self.mac);
+        buf.extend_from_slice(&self.original_id.to_be_bytes());
+        buf.extend_from_slice(&u16::from(self.error).to_be_bytes());
+        buf.extend_from_slice(&(self.other_data.len() as u16).to_be_bytes());
+        buf.extend_from_slice(&self.other_data);
+
+        Ok(())
+    }
+}
@@ -1,13 +1,37 @@
-use super::DNSRecordPack;
+use std::fmt;
 
+use crate::parser::CompressionTable;
 
-#[derive(Debug, PartialEq, Eq)]
+use super::{hinfo_record::format_character_string, DNSRecordPack, RecordType};
+
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct DNSTXTRecord {
-    text: String,
+    pub text: String,
+}
+
+/// One quoted string per 255 byte segment, the same way they're serialized
+impl fmt::Display for DNSTXTRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.text.is_empty() {
+            return f.write_str("\"\"");
+        }
+
+        for (i, segment) in self.text.as_bytes().chunks(255).enumerate() {
+            if i > 0 {
+                f.write_str(" ")?;
+            }
+
+            format_character_string(f, segment)?;
+        }
+
+        Ok(())
+    }
 }
 
 impl DNSRecordPack for DNSTXTRecord {
-    const RTYPE: u16 = 16;
+    const RTYPE: RecordType = RecordType::TXT;
 
     fn parse(
         data: &[u8],
@@ -19,9 +43,9 @@ impl DNSRecordPack for DNSTXTRecord {
 
         while ptr < len {
             let seg_len = data[startptr + ptr] as usize;
-            let segment = String::from_utf8_lossy(
-                &data[(startptr + ptr + 1)..(startptr + ptr + 1 + seg_len)]
-            ).to_string();
+            let segment = data.get((startptr + ptr + 1)..(startptr + ptr + 1 + seg_len))
+                .map(|segment| String::from_utf8_lossy(segment).to_string())
+                .ok_or("Failed to parse TXT record, a string runs past the record data")?;
             text.push_str(&segment);
 
             ptr += seg_len + 1;
@@ -30,26 +54,16 @@ impl DNSRecordPack for DNSTXTRecord {
         Ok(Self { text })
     }
 
-    fn serialize(
+    fn serialize_into(
         &self,
-        _label_ptr_map: &mut crate::parser::LabelPtrMap,
-        _ptr: usize,
-    ) -> Result<Vec<u8>, String> {
-        let mut data: Vec<u8> = vec![];
-        let mut str_ptr: usize = 0;
-
-        let str_bytes = self.text.as_bytes();
-        let total_len = self.text.len();
-
-        while str_ptr < total_len {
-            let seg_len = total_len.min(255);
-
-            data.push(seg_len as u8);
-            data.extend_from_slice(&str_bytes[str_ptr..(str_ptr + seg_len)]);
-
-            str_ptr += seg_len;
+        buf: &mut Vec<u8>,
+        _table: Option<&mut CompressionTable>,
+    ) -> Result<(), String> {
+        for segment in self.text.as_bytes().chunks(255) {
+            buf.push(segment.len() as u8);
+            buf.extend_from_slice(segment);
         }
 
-        Ok(data)
+        Ok(())
     }
 }
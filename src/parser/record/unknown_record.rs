@@ -1,30 +1,52 @@
-use crate::parser::LabelPtrMap;
+use std::fmt;
 
-use super::DNSRecordPack;
+use crate::parser::CompressionTable;
 
-#[derive(Debug, PartialEq, Eq)]
+use super::{DNSRecordPack, RecordType};
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct DNSUnknownRecord {
-    data: Vec<u8>,
+    pub data: Vec<u8>,
+}
+
+/// Generic presentation format from RFC 3597 section 5
+impl fmt::Display for DNSUnknownRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "\\# {}", self.data.len())?;
+
+        if ! self.data.is_empty() {
+            f.write_str(" ")?;
+        }
+
+        for byte in &self.data {
+            write!(f, "{:02x}", byte)?;
+        }
+
+        Ok(())
+    }
 }
 
 impl DNSRecordPack for DNSUnknownRecord {
-    const RTYPE: u16 = 0;
+    const RTYPE: RecordType = RecordType::Unknown(0);
 
     fn parse(
         data: &[u8],
         startptr: usize,
         len: usize,
     ) -> Result<Self, String> where Self: Sized {
-        return Ok(Self {
+        Ok(Self {
             data: data[startptr..(startptr + len)].to_vec(),
         })
     }
 
-    fn serialize(
+    fn serialize_into(
         &self,
-        _label_ptr_map: &mut LabelPtrMap,
-        _ptr: usize,
-    ) -> Result<Vec<u8>, String> {
-        Ok(self.data.clone())
+        buf: &mut Vec<u8>,
+        _table: Option<&mut CompressionTable>,
+    ) -> Result<(), String> {
+        buf.extend_from_slice(&self.data);
+
+        Ok(())
     }
 }
@@ -0,0 +1,232 @@
+use std::{fmt, str::FromStr};
+
+/// Generates a `u16` backed enum with a catch-all `Unknown` variant and
+/// the conversions/mnemonics that go along with it
+macro_rules! u16_enum {
+    (
+        $(#[$meta:meta])*
+        pub enum $name:ident ($unknown_prefix:literal) {
+            $($(#[$vmeta:meta])* $variant:ident = $value:literal => $mnemonic:literal,)*
+        }
+    ) => {
+        $(#[$meta])*
+        #[derive(Debug, PartialEq, Eq, Copy, Clone, Hash, PartialOrd, Ord)]
+        #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(from = "u16", into = "u16"))]
+        pub enum $name {
+            $($(#[$vmeta])* $variant,)*
+
+            /// Unassigned or private use value, kept as is so that it can be
+            /// serialized back
+            Unknown(u16),
+        }
+
+        impl From<u16> for $name {
+            fn from(value: u16) -> Self {
+                match value {
+                    $($value => Self::$variant,)*
+                    _ => Self::Unknown(value),
+                }
+            }
+        }
+
+        impl From<$name> for u16 {
+            fn from(value: $name) -> Self {
+                match value {
+                    $($name::$variant => $value,)*
+                    $name::Unknown(value) => value,
+                }
+            }
+        }
+
+        impl $name {
+            /// The mnemonic used in presentation format (e.g. zone files),
+            /// `None` for values without one
+            pub fn mnemonic(&self) -> Option<&'static str> {
+                match self {
+                    $(Self::$variant => Some($mnemonic),)*
+                    Self::Unknown(_) => None,
+                }
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                match self.mnemonic() {
+                    Some(mnemonic) => f.write_str(mnemonic),
+                    // Generic notation from RFC 3597 section 5
+                    None => write!(f, "{}{}", $unknown_prefix, u16::from(*self)),
+                }
+            }
+        }
+
+        impl FromStr for $name {
+            type Err = String;
+
+            fn from_str(s: &str) -> Result<Self, Self::Err> {
+                let upper = s.to_ascii_uppercase();
+
+                match upper.as_str() {
+                    $($mnemonic => return Ok(Self::$variant),)*
+                    _ => {},
+                }
+
+                upper.strip_prefix($unknown_prefix)
+                    .and_then(|value| value.parse::<u16>().ok())
+                    .map(Self::from)
+                    .ok_or_else(|| format!("Unknown {} {}", stringify!($name), s))
+            }
+        }
+    };
+}
+
+u16_enum! {
+    /// Resource record types as registered with IANA
+    /// (<https://www.iana.org/assignments/dns-parameters>)
+    pub enum RecordType ("TYPE") {
+        A = 1 => "A",
+        NS = 2 => "NS",
+        MD = 3 => "MD",
+        MF = 4 => "MF",
+        CNAME = 5 => "CNAME",
+        SOA = 6 => "SOA",
+        MB = 7 => "MB",
+        MG = 8 => "MG",
+        MR = 9 => "MR",
+        NULL = 10 => "NULL",
+        WKS = 11 => "WKS",
+        PTR = 12 => "PTR",
+        HINFO = 13 => "HINFO",
+        MINFO = 14 => "MINFO",
+        MX = 15 => "MX",
+        TXT = 16 => "TXT",
+        RP = 17 => "RP",
+        AFSDB = 18 => "AFSDB",
+        X25 = 19 => "X25",
+        ISDN = 20 => "ISDN",
+        RT = 21 => "RT",
+        NSAP = 22 => "NSAP",
+        NSAPPTR = 23 => "NSAP-PTR",
+        SIG = 24 => "SIG",
+        KEY = 25 => "KEY",
+        PX = 26 => "PX",
+        GPOS = 27 => "GPOS",
+        AAAA = 28 => "AAAA",
+        LOC = 29 => "LOC",
+        NXT = 30 => "NXT",
+        EID = 31 => "EID",
+        NIMLOC = 32 => "NIMLOC",
+        SRV = 33 => "SRV",
+        ATMA = 34 => "ATMA",
+        NAPTR = 35 => "NAPTR",
+        KX = 36 => "KX",
+        CERT = 37 => "CERT",
+        A6 = 38 => "A6",
+        DNAME = 39 => "DNAME",
+        SINK = 40 => "SINK",
+        OPT = 41 => "OPT",
+        APL = 42 => "APL",
+        DS = 43 => "DS",
+        SSHFP = 44 => "SSHFP",
+        IPSECKEY = 45 => "IPSECKEY",
+        RRSIG = 46 => "RRSIG",
+        NSEC = 47 => "NSEC",
+        DNSKEY = 48 => "DNSKEY",
+        DHCID = 49 => "DHCID",
+        NSEC3 = 50 => "NSEC3",
+        NSEC3PARAM = 51 => "NSEC3PARAM",
+        TLSA = 52 => "TLSA",
+        SMIMEA = 53 => "SMIMEA",
+        HIP = 55 => "HIP",
+        NINFO = 56 => "NINFO",
+        RKEY = 57 => "RKEY",
+        TALINK = 58 => "TALINK",
+        CDS = 59 => "CDS",
+        CDNSKEY = 60 => "CDNSKEY",
+        OPENPGPKEY = 61 => "OPENPGPKEY",
+        CSYNC = 62 => "CSYNC",
+        ZONEMD = 63 => "ZONEMD",
+        SVCB = 64 => "SVCB",
+        HTTPS = 65 => "HTTPS",
+        DSYNC = 66 => "DSYNC",
+        HHIT = 67 => "HHIT",
+        BRID = 68 => "BRID",
+        SPF = 99 => "SPF",
+        UINFO = 100 => "UINFO",
+        UID = 101 => "UID",
+        GID = 102 => "GID",
+        UNSPEC = 103 => "UNSPEC",
+        NID = 104 => "NID",
+        L32 = 105 => "L32",
+        L64 = 106 => "L64",
+        LP = 107 => "LP",
+        EUI48 = 108 => "EUI48",
+        EUI64 = 109 => "EUI64",
+        NXNAME = 128 => "NXNAME",
+        TKEY = 249 => "TKEY",
+        TSIG = 250 => "TSIG",
+        IXFR = 251 => "IXFR",
+        AXFR = 252 => "AXFR",
+        MAILB = 253 => "MAILB",
+        MAILA = 254 => "MAILA",
+        /// A request for all records
+        ANY = 255 => "ANY",
+        URI = 256 => "URI",
+        CAA = 257 => "CAA",
+        AVC = 258 => "AVC",
+        DOA = 259 => "DOA",
+        AMTRELAY = 260 => "AMTRELAY",
+        RESINFO = 261 => "RESINFO",
+        WALLET = 262 => "WALLET",
+        CLA = 263 => "CLA",
+        IPN = 264 => "IPN",
+        TA = 32768 => "TA",
+        DLV = 32769 => "DLV",
+    }
+}
+
+u16_enum! {
+    /// Resource record classes as registered with IANA
+    pub enum RecordClass ("CLASS") {
+        /// The Internet
+        IN = 1 => "IN",
+        /// CSNET (obsolete)
+        CS = 2 => "CS",
+        /// Chaos
+        CH = 3 => "CH",
+        /// Hesiod
+        HS = 4 => "HS",
+        /// Used by dynamic updates to delete RRsets (RFC 2136)
+        NONE = 254 => "NONE",
+        /// A request for any class
+        ANY = 255 => "ANY",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{RecordClass, RecordType};
+
+    #[test]
+    fn converts_record_types_to_and_from_mnemonics() {
+        assert_eq!("AAAA".parse::<RecordType>(), Ok(RecordType::AAAA));
+        assert_eq!("nsap-ptr".parse::<RecordType>(), Ok(RecordType::NSAPPTR));
+        assert_eq!("TYPE65280".parse::<RecordType>(), Ok(RecordType::Unknown(65280)));
+        assert_eq!("TYPE1".parse::<RecordType>(), Ok(RecordType::A));
+        assert!("BOGUS".parse::<RecordType>().is_err());
+
+        assert_eq!(RecordType::from(28), RecordType::AAAA);
+        assert_eq!(u16::from(RecordType::CAA), 257);
+        assert_eq!(RecordType::MX.to_string(), "MX");
+        assert_eq!(RecordType::Unknown(65280).to_string(), "TYPE65280");
+    }
+
+    #[test]
+    fn converts_record_classes_to_and_from_mnemonics() {
+        assert_eq!("ch".parse::<RecordClass>(), Ok(RecordClass::CH));
+        assert_eq!("CLASS42".parse::<RecordClass>(), Ok(RecordClass::Unknown(42)));
+        assert_eq!(RecordClass::from(1), RecordClass::IN);
+        assert_eq!(u16::from(RecordClass::NONE), 254);
+        assert_eq!(RecordClass::HS.to_string(), "HS");
+        assert_eq!(RecordClass::Unknown(4096).to_string(), "CLASS4096");
+    }
+}
@@ -0,0 +1,88 @@
+use super::{header::Opcode, packet::DNSPacket, question::DNSQuestion, record::DNSRecord, types::RecordType};
+
+/// Dynamic update message (RFC 2136 section 2)
+///
+/// UPDATE messages share the layout of regular messages, only the meaning
+/// of the sections changes:
+/// ```text
+/// questions  -> Zone section (ZOCOUNT)
+/// answers    -> Prerequisite section (PRCOUNT)
+/// authority  -> Update section (UPCOUNT)
+/// additional -> Additional data section (ADCOUNT)
+/// ```
+#[derive(Debug, PartialEq, Eq)]
+pub struct DNSUpdate<'packet> {
+    /// The zone to be updated, its type is always SOA
+    pub zone: &'packet DNSQuestion,
+
+    /// RRs or RRsets which must (not) preexist
+    pub prerequisites: &'packet [DNSRecord],
+
+    /// RRs or RRsets to be added or deleted
+    pub updates: &'packet [DNSRecord],
+
+    /// Additional data
+    pub additional: &'packet [DNSRecord],
+}
+
+impl<'packet> DNSUpdate<'packet> {
+    pub fn new(packet: &'packet DNSPacket) -> Result<Self, String> {
+        if packet.header.opcode != Opcode::Update {
+            return Err(format!("DNSUpdate: Expected UPDATE opcode, got {:?}", packet.header.opcode));
+        }
+
+        let zone = match packet.questions.as_slice() {
+            [zone] => zone,
+            zones => return Err(format!("DNSUpdate: Expected exactly 1 zone, got {}", zones.len())),
+        };
+
+        if zone.rtype != RecordType::SOA {
+            return Err(format!("DNSUpdate: Expected zone type to be SOA, got {}", zone.rtype));
+        }
+
+        Ok(Self {
+            zone,
+            prerequisites: &packet.answers,
+            updates: &packet.authority,
+            additional: &packet.additional,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::{packet::DNSPacketParser, record::{DNSARecord, DNSRecordData}, types::{RecordClass, RecordType}};
+
+    use super::DNSUpdate;
+
+    // nsupdate: zone example.com / prereq nxrrset host.example.com A / update delete host.example.com A
+    //           / update add host.example.com 300 A 10.0.0.1
+    static UPDATE_SAMPLE: &[u8] = &[
+        0x12, 0x34, 0x28, 0x00, 0x00, 0x01, 0x00, 0x01, 0x00, 0x02, 0x00, 0x00,
+        // Zone: example.com. SOA IN
+        0x07, b'e', b'x', b'a', b'm', b'p', b'l', b'e', 0x03, b'c', b'o', b'm', 0x00, 0x00, 0x06, 0x00, 0x01,
+        // Prerequisite: host.example.com. A NONE 0 (empty)
+        0x04, b'h', b'o', b's', b't', 0xc0, 0x0c, 0x00, 0x01, 0x00, 0xfe, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        // Update: host.example.com. A ANY 0 (empty)
+        0xc0, 0x1d, 0x00, 0x01, 0x00, 0xff, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        // Update: host.example.com. A IN 300 10.0.0.1
+        0xc0, 0x1d, 0x00, 0x01, 0x00, 0x01, 0x00, 0x00, 0x01, 0x2c, 0x00, 0x04, 0x0a, 0x00, 0x00, 0x01,
+    ];
+
+    #[test]
+    fn parses_and_serializes_update_sections() {
+        let packet = DNSPacketParser::new(UPDATE_SAMPLE).parse().unwrap();
+        let update = DNSUpdate::new(&packet).unwrap();
+
+        assert_eq!(update.zone.name, "example.com.");
+        assert_eq!(update.prerequisites.len(), 1);
+        assert_eq!(update.prerequisites[0].class, RecordClass::NONE);
+        assert_eq!(update.prerequisites[0].record, DNSRecordData::Empty);
+        assert_eq!(update.updates.len(), 2);
+        assert_eq!(update.updates[0].class, RecordClass::ANY);
+        assert_eq!(update.updates[1].rtype, RecordType::A);
+        assert_eq!(update.updates[1].record, DNSRecordData::A(DNSARecord { ip: [10, 0, 0, 1] }));
+
+        assert_eq!(packet.serialize(), Ok(UPDATE_SAMPLE.to_vec()));
+    }
+}
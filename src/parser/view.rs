@@ -0,0 +1,557 @@
+use std::fmt;
+
+use super::{common::MAX_NAME_LABELS, header::{DNSHeaderType, Opcode, ResultCode}, packet::{DNSPacket, DNSPacketParser}, record::{DNSRecordData, DNSRecordsParser}, types::{RecordClass, RecordType}};
+
+fn read_u16(data: &[u8], ptr: usize) -> Result<u16, String> {
+    data.get(ptr..(ptr + 2))
+        .map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]))
+        .ok_or_else(|| "DNSPacketView: Unexpected end of packet".to_owned())
+}
+
+fn read_u32(data: &[u8], ptr: usize) -> Result<u32, String> {
+    data.get(ptr..(ptr + 4))
+        .map(|bytes| u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+        .ok_or_else(|| "DNSPacketView: Unexpected end of packet".to_owned())
+}
+
+/// A read only view over a packet in its wire format, nothing is decoded or
+/// copied until it's asked for
+///
+/// Sections are found by skipping over the ones before them, so iterating
+/// the additional section walks the questions, answers and authority first.
+#[derive(Debug, Clone, Copy)]
+pub struct DNSPacketView<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> DNSPacketView<'a> {
+    pub fn new(data: &'a [u8]) -> Result<Self, String> {
+        if data.len() < 12 {
+            return Err(format!("DNSPacketView: Expected data length to be at least {}", 12));
+        }
+
+        Ok(Self { data })
+    }
+
+    pub fn data(&self) -> &'a [u8] {
+        self.data
+    }
+
+    pub fn id(&self) -> u16 {
+        u16::from_be_bytes([self.data[0], self.data[1]])
+    }
+
+    pub fn qr(&self) -> DNSHeaderType {
+        (((self.data[2] & 0b1000_0000) >> 7) as usize).into()
+    }
+
+    pub fn opcode(&self) -> Opcode {
+        ((self.data[2] & 0b0111_1000) >> 3).into()
+    }
+
+    pub fn aa(&self) -> bool {
+        self.data[2] & 0b0000_0100 != 0
+    }
+
+    pub fn tc(&self) -> bool {
+        self.data[2] & 0b0000_0010 != 0
+    }
+
+    pub fn rd(&self) -> bool {
+        self.data[2] & 0b0000_0001 != 0
+    }
+
+    pub fn ra(&self) -> bool {
+        self.data[3] & 0b1000_0000 != 0
+    }
+
+    pub fn ad(&self) -> bool {
+        self.data[3] & 0b0010_0000 != 0
+    }
+
+    pub fn cd(&self) -> bool {
+        self.data[3] & 0b0001_0000 != 0
+    }
+
+    pub fn rcode(&self) -> ResultCode {
+        ((self.data[3] & 0b0000_1111) as usize).into()
+    }
+
+    pub fn qdcount(&self) -> u16 {
+        u16::from_be_bytes([self.data[4], self.data[5]])
+    }
+
+    pub fn ancount(&self) -> u16 {
+        u16::from_be_bytes([self.data[6], self.data[7]])
+    }
+
+    pub fn nscount(&self) -> u16 {
+        u16::from_be_bytes([self.data[8], self.data[9]])
+    }
+
+    pub fn arcount(&self) -> u16 {
+        u16::from_be_bytes([self.data[10], self.data[11]])
+    }
+
+    pub fn questions(&self) -> QuestionIter<'a> {
+        QuestionIter { data: self.data, ptr: 12, remaining: self.qdcount() }
+    }
+
+    pub fn answers(&self) -> Result<RecordIter<'a>, String> {
+        let ptr = self.questions().end()?;
+
+        Ok(RecordIter { data: self.data, ptr, remaining: self.ancount() })
+    }
+
+    pub fn authority(&self) -> Result<RecordIter<'a>, String> {
+        let ptr = self.answers()?.end()?;
+
+        Ok(RecordIter { data: self.data, ptr, remaining: self.nscount() })
+    }
+
+    pub fn additional(&self) -> Result<RecordIter<'a>, String> {
+        let ptr = self.authority()?.end()?;
+
+        Ok(RecordIter { data: self.data, ptr, remaining: self.arcount() })
+    }
+
+    /// Walks every section and owner name, making sure they stay within the
+    /// packet, record data is not looked at
+    pub fn validate(&self) -> Result<(), String> {
+        for question in self.questions() {
+            question?.name.labels().try_for_each(|label| label.map(|_| ()))?;
+        }
+
+        for record in self.answers()?.chain(self.authority()?).chain(self.additional()?) {
+            record?.name.labels().try_for_each(|label| label.map(|_| ()))?;
+        }
+
+        Ok(())
+    }
+
+    /// Checks the packet, then decodes the whole of it
+    pub fn to_packet(self) -> Result<DNSPacket, String> {
+        self.validate()?;
+
+        DNSPacketParser::new(self.data).parse()
+    }
+}
+
+/// A view that can also patch the header in place, e.g. to rewrite the ID
+/// of a forwarded response
+#[derive(Debug)]
+pub struct DNSPacketViewMut<'a> {
+    data: &'a mut [u8],
+}
+
+impl<'a> DNSPacketViewMut<'a> {
+    pub fn new(data: &'a mut [u8]) -> Result<Self, String> {
+        DNSPacketView::new(data)?;
+
+        Ok(Self { data })
+    }
+
+    pub fn view(&self) -> DNSPacketView<'_> {
+        DNSPacketView { data: self.data }
+    }
+
+    fn set_bits(&mut self, byte: usize, mask: u8, value: u8) {
+        self.data[byte] = (self.data[byte] & ! mask) | (value & mask);
+    }
+
+    fn set_flag(&mut self, byte: usize, mask: u8, value: bool) {
+        self.set_bits(byte, mask, if value { mask } else { 0 });
+    }
+
+    pub fn set_id(&mut self, id: u16) {
+        self.data[0..2].copy_from_slice(&id.to_be_bytes());
+    }
+
+    pub fn set_qr(&mut self, qr: DNSHeaderType) {
+        self.set_flag(2, 0b1000_0000, qr == DNSHeaderType::Response);
+    }
+
+    pub fn set_opcode(&mut self, opcode: Opcode) {
+        self.set_bits(2, 0b0111_1000, u8::from(opcode) << 3);
+    }
+
+    pub fn set_aa(&mut self, aa: bool) {
+        self.set_flag(2, 0b0000_0100, aa);
+    }
+
+    pub fn set_tc(&mut self, tc: bool) {
+        self.set_flag(2, 0b0000_0010, tc);
+    }
+
+    pub fn set_rd(&mut self, rd: bool) {
+        self.set_flag(2, 0b0000_0001, rd);
+    }
+
+    pub fn set_ra(&mut self, ra: bool) {
+        self.set_flag(3, 0b1000_0000, ra);
+    }
+
+    pub fn set_ad(&mut self, ad: bool) {
+        self.set_flag(3, 0b0010_0000, ad);
+    }
+
+    pub fn set_cd(&mut self, cd: bool) {
+        self.set_flag(3, 0b0001_0000, cd);
+    }
+
+    pub fn set_rcode(&mut self, rcode: ResultCode) {
+        self.set_bits(3, 0b0000_1111, usize::from(rcode) as u8);
+    }
+
+    /// Only the counts are changed, the sections themselves are left as is
+    pub fn set_counts(&mut self, qdcount: u16, ancount: u16, nscount: u16, arcount: u16) {
+        for (i, count) in [qdcount, ancount, nscount, arcount].into_iter().enumerate() {
+            self.data[(4 + i * 2)..(6 + i * 2)].copy_from_slice(&count.to_be_bytes());
+        }
+    }
+}
+
+/// A possibly compressed name, decoded label by label on demand
+#[derive(Debug, Clone, Copy)]
+pub struct NameView<'a> {
+    data: &'a [u8],
+    ptr: usize,
+}
+
+impl<'a> NameView<'a> {
+    /// The labels of the name, following compression pointers
+    pub fn labels(&self) -> LabelIter<'a> {
+        LabelIter { data: self.data, ptr: self.ptr, count: 0, done: false }
+    }
+
+    /// Compares with a dotted name, case insensitively, without decoding
+    pub fn eq_name(&self, name: &str) -> Result<bool, String> {
+        let mut parts = name.strip_suffix('.').unwrap_or(name).split('.').filter(|part| ! part.is_empty());
+
+        for label in self.labels() {
+            match parts.next() {
+                Some(part) if part.as_bytes().eq_ignore_ascii_case(label?) => {},
+                _ => return Ok(false),
+            }
+        }
+
+        Ok(parts.next().is_none())
+    }
+
+    /// Number of bytes the name takes where it's written, pointers count as 2
+    fn wire_len(data: &[u8], ptr: usize) -> Result<usize, String> {
+        let mut end = ptr;
+
+        loop {
+            let len = *data.get(end).ok_or("DNSPacketView: Unexpected end of packet")?;
+
+            match len >> 6 {
+                0b00 if len == 0 => return Ok(end + 1 - ptr),
+                0b00 => end += 1 + len as usize,
+                0b11 => return Ok(end + 2 - ptr),
+                _ => return Err("DNSPacketView: Unhandled qname marker".to_owned()),
+            }
+        }
+    }
+}
+
+/// Same format as the owned parser, labels joined by dots with a trailing dot
+impl fmt::Display for NameView<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for label in self.labels() {
+            let label = label.map_err(|_| fmt::Error)?;
+
+            write!(f, "{}.", String::from_utf8_lossy(label))?;
+        }
+
+        Ok(())
+    }
+}
+
+pub struct LabelIter<'a> {
+    data: &'a [u8],
+    ptr: usize,
+    count: usize,
+    done: bool,
+}
+
+impl<'a> Iterator for LabelIter<'a> {
+    type Item = Result<&'a [u8], String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        loop {
+            self.count += 1;
+
+            if self.count > MAX_NAME_LABELS {
+                self.done = true;
+                return Some(Err("DNSPacketView: Too many labels or a compression loop".to_owned()));
+            }
+
+            let len = match self.data.get(self.ptr) {
+                Some(len) => *len as usize,
+                None => {
+                    self.done = true;
+                    return Some(Err("DNSPacketView: Unexpected end of packet".to_owned()));
+                },
+            };
+
+            match len >> 6 {
+                0b00 if len == 0 => {
+                    self.done = true;
+                    return None;
+                },
+                0b00 => {
+                    let start = self.ptr + 1;
+                    self.ptr = start + len;
+
+                    return match self.data.get(start..self.ptr) {
+                        Some(label) => Some(Ok(label)),
+                        None => {
+                            self.done = true;
+                            Some(Err("DNSPacketView: Unexpected end of packet".to_owned()))
+                        },
+                    };
+                },
+                0b11 => match read_u16(self.data, self.ptr) {
+                    Ok(pointer) => self.ptr = (pointer & 0b0011_1111_1111_1111) as usize,
+                    Err(e) => {
+                        self.done = true;
+                        return Some(Err(e));
+                    },
+                },
+                _ => {
+                    self.done = true;
+                    return Some(Err("DNSPacketView: Unhandled qname marker".to_owned()));
+                },
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct QuestionView<'a> {
+    pub name: NameView<'a>,
+    pub rtype: RecordType,
+    pub class: RecordClass,
+}
+
+pub struct QuestionIter<'a> {
+    data: &'a [u8],
+    ptr: usize,
+    remaining: u16,
+}
+
+impl QuestionIter<'_> {
+    /// Offset right after the last question
+    fn end(mut self) -> Result<usize, String> {
+        for question in self.by_ref() {
+            question?;
+        }
+
+        Ok(self.ptr)
+    }
+}
+
+impl<'a> Iterator for QuestionIter<'a> {
+    type Item = Result<QuestionView<'a>, String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+
+        self.remaining -= 1;
+
+        let question = NameView::wire_len(self.data, self.ptr).and_then(|name_len| {
+            let end = self.ptr + name_len;
+            let question = QuestionView {
+                name: NameView { data: self.data, ptr: self.ptr },
+                rtype: read_u16(self.data, end)?.into(),
+                class: read_u16(self.data, end + 2)?.into(),
+            };
+
+            self.ptr = end + 4;
+
+            Ok(question)
+        });
+
+        if question.is_err() {
+            self.remaining = 0;
+        }
+
+        Some(question)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct RecordView<'a> {
+    data: &'a [u8],
+    rdata_ptr: usize,
+
+    pub name: NameView<'a>,
+    pub rtype: RecordType,
+    pub class: RecordClass,
+    pub ttl: u32,
+
+    /// Raw record data, names in it may point elsewhere in the packet
+    pub rdata: &'a [u8],
+}
+
+impl RecordView<'_> {
+    /// Decodes the record data
+    pub fn parse_data(&self) -> Result<DNSRecordData, String> {
+        let update = DNSPacketView::new(self.data)?.opcode() == Opcode::Update;
+        let (record, _) = DNSRecordsParser::new(self.data)
+            .with_empty_rdata(update)
+            .parse_record_data(self.rtype, self.rdata.len(), self.rdata_ptr)?;
+
+        Ok(record)
+    }
+}
+
+pub struct RecordIter<'a> {
+    data: &'a [u8],
+    ptr: usize,
+    remaining: u16,
+}
+
+impl RecordIter<'_> {
+    /// Offset right after the last record
+    fn end(mut self) -> Result<usize, String> {
+        for record in self.by_ref() {
+            record?;
+        }
+
+        Ok(self.ptr)
+    }
+}
+
+impl<'a> Iterator for RecordIter<'a> {
+    type Item = Result<RecordView<'a>, String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+
+        self.remaining -= 1;
+
+        let record = NameView::wire_len(self.data, self.ptr).and_then(|name_len| {
+            let end = self.ptr + name_len;
+            let rdata_len = read_u16(self.data, end + 8)? as usize;
+            let rdata_ptr = end + 10;
+            let rdata = self.data
+                .get(rdata_ptr..(rdata_ptr + rdata_len))
+                .ok_or("DNSPacketView: Record data exceeds the packet")?;
+
+            let record = RecordView {
+                data: self.data,
+                rdata_ptr,
+                name: NameView { data: self.data, ptr: self.ptr },
+                rtype: read_u16(self.data, end)?.into(),
+                class: read_u16(self.data, end + 2)?.into(),
+                ttl: read_u32(self.data, end + 4)?,
+                rdata,
+            };
+
+            self.ptr = rdata_ptr + rdata_len;
+
+            Ok(record)
+        });
+
+        if record.is_err() {
+            self.remaining = 0;
+        }
+
+        Some(record)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::parser::{header::{DNSHeaderType, ResultCode}, packet::DNSPacketParser, types::RecordType};
+
+    use super::{DNSPacketView, DNSPacketViewMut};
+
+    #[test]
+    fn reads_packets_without_decoding_them() {
+        let raw = fs::read("./samples/response_packet_huge.bin").expect("Should read response_packet_huge sample file");
+        let packet = DNSPacketParser::new(&raw).parse().unwrap();
+        let view = DNSPacketView::new(&raw).unwrap();
+
+        assert_eq!(view.id(), packet.header.id);
+        assert_eq!(view.qr(), packet.header.qr);
+        assert_eq!((view.rd(), view.ra(), view.ad(), view.cd()), (packet.header.rd, packet.header.ra, packet.header.ad, packet.header.cd));
+        assert_eq!(view.rcode(), packet.header.rcode);
+
+        let questions = view.questions().collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(questions.len(), packet.questions.len());
+        assert_eq!(questions[0].name.to_string(), packet.questions[0].name);
+        assert!(questions[0].name.eq_name(&packet.questions[0].name.to_uppercase()).unwrap());
+        assert!(! questions[0].name.eq_name("example.com.").unwrap());
+
+        for (records, expected) in [
+            (view.answers().unwrap(), &packet.answers),
+            (view.authority().unwrap(), &packet.authority),
+            (view.additional().unwrap(), &packet.additional),
+        ] {
+            let records = records.collect::<Result<Vec<_>, _>>().unwrap();
+
+            assert_eq!(records.len(), expected.len());
+
+            for (record, expected) in records.iter().zip(expected) {
+                assert_eq!(record.name.to_string(), expected.name);
+                assert_eq!((record.rtype, record.class, record.ttl), (expected.rtype, expected.class, expected.ttl));
+                assert_eq!(record.parse_data().unwrap(), expected.record);
+            }
+        }
+
+        assert_eq!(view.to_packet(), Ok(packet));
+    }
+
+    #[test]
+    fn patches_headers_in_place() {
+        let mut raw = fs::read("./samples/query_packet.bin").expect("Should read query_packet sample file");
+
+        let mut view = DNSPacketViewMut::new(&mut raw).unwrap();
+        view.set_id(4321);
+        view.set_qr(DNSHeaderType::Response);
+        view.set_ra(true);
+        view.set_ad(false);
+        view.set_rcode(ResultCode::Refused);
+
+        let packet = DNSPacketParser::new(&raw).parse().unwrap();
+        assert_eq!(packet.header.id, 4321);
+        assert_eq!(packet.header.qr, DNSHeaderType::Response);
+        assert!(packet.header.ra && packet.header.rd && ! packet.header.ad);
+        assert_eq!(packet.header.rcode, ResultCode::Refused);
+        assert_eq!(packet.questions[0].rtype, RecordType::A);
+    }
+
+    #[test]
+    fn rejects_malformed_packets() {
+        assert!(DNSPacketView::new(&[0; 11]).is_err());
+
+        // One question whose name is a pointer to itself
+        let raw = [0, 1, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0b1100_0000, 12, 0, 1, 0, 1];
+        let view = DNSPacketView::new(&raw).unwrap();
+        let question = view.questions().next().unwrap().unwrap();
+
+        assert!(question.name.labels().any(|label| label.is_err()));
+        assert!(question.name.eq_name("example.com.").is_err());
+
+        // One answer, but nothing after the header
+        let raw = [0, 1, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0];
+        assert!(DNSPacketView::new(&raw).unwrap().answers().unwrap().next().unwrap().is_err());
+        assert!(DNSPacketView::new(&raw).unwrap().validate().is_err());
+
+        let raw = fs::read("./samples/response_packet_huge.bin").expect("Should read response_packet_huge sample file");
+        assert_eq!(DNSPacketView::new(&raw).unwrap().validate(), Ok(()));
+    }
+}
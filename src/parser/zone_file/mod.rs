@@ -0,0 +1,408 @@
+use std::{fs, path::{Path, PathBuf}};
+
+use super::{record::{DNSRecord, DNSRecordData}, types::{RecordClass, RecordType}};
+
+mod rdata;
+mod tokenizer;
+mod writer;
+
+pub use rdata::{absolute_name, parse_ttl};
+pub use writer::ZoneFileWriter;
+
+use tokenizer::{tokenize, Line, Token};
+
+/// How deep $INCLUDE directives may nest, guards against include loops
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+/// Parser for zone master files (RFC 1035 section 5)
+///
+/// Supports the $ORIGIN, $TTL (RFC 2308), $INCLUDE and BIND's $GENERATE
+/// directives. Errors are reported as "file:line: message".
+pub struct ZoneFileParser {
+    /// Current origin, fully qualified
+    origin: String,
+
+    /// Set by $TTL
+    default_ttl: Option<u32>,
+
+    /// Values inherited by records that omit them
+    last_owner: Option<String>,
+    last_ttl: Option<u32>,
+    last_class: RecordClass,
+}
+
+impl ZoneFileParser {
+    pub fn new(origin: &str) -> Self {
+        Self {
+            origin: normalize_origin(origin),
+            default_ttl: None,
+            last_owner: None,
+            last_ttl: None,
+            last_class: RecordClass::IN,
+        }
+    }
+
+    pub fn parse_file(&mut self, path: &str) -> Result<Vec<DNSRecord>, String> {
+        let mut records = vec![];
+        self.parse_path(Path::new(path), 0, &mut records)?;
+
+        Ok(records)
+    }
+
+    /// Parses zone file text, `file` is only used in error messages, relative
+    /// $INCLUDE paths are resolved from the working directory
+    pub fn parse_str(&mut self, text: &str, file: &str) -> Result<Vec<DNSRecord>, String> {
+        let mut records = vec![];
+        self.parse_text(text, file, Path::new(""), 0, &mut records)?;
+
+        Ok(records)
+    }
+
+    fn parse_path(&mut self, path: &Path, depth: usize, records: &mut Vec<DNSRecord>) -> Result<(), String> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read zone file {}, {}", path.display(), e))?;
+        let dir = path.parent().unwrap_or(Path::new(""));
+
+        self.parse_text(&text, &path.display().to_string(), dir, depth, records)
+    }
+
+    fn parse_text(
+        &mut self,
+        text: &str,
+        file: &str,
+        dir: &Path,
+        depth: usize,
+        records: &mut Vec<DNSRecord>,
+    ) -> Result<(), String> {
+        let lines = tokenize(text).map_err(|(line, e)| format!("{}:{}: {}", file, line, e))?;
+
+        for line in lines {
+            self.parse_line(&line, dir, depth, records)
+                .map_err(|e| format!("{}:{}: {}", file, line.number, e))?;
+        }
+
+        Ok(())
+    }
+
+    fn parse_line(&mut self, line: &Line, dir: &Path, depth: usize, records: &mut Vec<DNSRecord>) -> Result<(), String> {
+        let first = &line.tokens[0];
+        let args = &line.tokens[1..];
+
+        if line.blank_owner || first.quoted || ! first.text.starts_with('$') {
+            records.push(self.parse_record(line.blank_owner, &line.tokens)?);
+
+            return Ok(());
+        }
+
+        match first.text.to_ascii_uppercase().as_str() {
+            "$ORIGIN" => {
+                let [origin] = args else {
+                    return Err("Expected a single name after $ORIGIN".to_owned());
+                };
+
+                self.origin = absolute_name(&origin.text, &self.origin)?;
+            },
+            "$TTL" => {
+                let [ttl] = args else {
+                    return Err("Expected a single TTL after $TTL".to_owned());
+                };
+
+                self.default_ttl = Some(parse_ttl(&ttl.text)?);
+            },
+            "$INCLUDE" => {
+                let (path, origin) = match args {
+                    [path] => (path, None),
+                    [path, origin] => (path, Some(absolute_name(&origin.text, &self.origin)?)),
+                    _ => return Err("Expected a file name and an optional origin after $INCLUDE".to_owned()),
+                };
+
+                if depth >= MAX_INCLUDE_DEPTH {
+                    return Err(format!("$INCLUDE nested more than {} levels deep", MAX_INCLUDE_DEPTH));
+                }
+
+                let mut include_path = PathBuf::from(&path.text);
+                if include_path.is_relative() {
+                    include_path = dir.join(include_path);
+                }
+
+                // The origin is restored once the included file is done (RFC 1035 section 5.1)
+                let saved_origin = self.origin.clone();
+
+                if let Some(origin) = origin {
+                    self.origin = origin;
+                }
+
+                let result = self.parse_path(&include_path, depth + 1, records);
+                self.origin = saved_origin;
+
+                result?;
+            },
+            "$GENERATE" => self.generate(args, records)?,
+            directive => return Err(format!("Unknown directive {}", directive)),
+        }
+
+        Ok(())
+    }
+
+    /// [<owner>] [<TTL>] [<class>] <type> <RDATA>, TTL and class may come in any order
+    fn parse_record(&mut self, blank_owner: bool, tokens: &[Token]) -> Result<DNSRecord, String> {
+        let mut tokens = tokens.iter().peekable();
+
+        let name = if blank_owner {
+            self.last_owner.clone().ok_or("Record has no owner and there is no previous one to inherit")?
+        } else {
+            let owner = tokens.next().ok_or("Missing owner")?;
+            absolute_name(&owner.text, &self.origin)?
+        };
+
+        let mut ttl = None;
+        let mut class = None;
+
+        while let Some(token) = tokens.next_if(|token| ! token.quoted) {
+            if ttl.is_none() && token.text.starts_with(|c: char| c.is_ascii_digit()) {
+                ttl = Some(parse_ttl(&token.text)?);
+                continue;
+            }
+
+            if class.is_none() {
+                if let Ok(value) = token.text.parse::<RecordClass>() {
+                    class = Some(value);
+                    continue;
+                }
+            }
+
+            let rtype = token.text
+                .parse::<RecordType>()
+                .map_err(|_| format!("Unknown record type or class {}", token.text))?;
+
+            let rdata_tokens = tokens.cloned().collect::<Vec<_>>();
+            let record = rdata::parse_rdata(rtype, &rdata_tokens, &self.origin)?;
+
+            // Without $TTL the last explicit TTL applies, SOAs fall back to their minimum
+            let ttl = match (ttl, self.default_ttl.or(self.last_ttl), &record) {
+                (Some(ttl), _, _) => {
+                    self.last_ttl = Some(ttl);
+                    ttl
+                },
+                (None, Some(ttl), _) => ttl,
+                (None, None, DNSRecordData::SOA(soa)) => soa.minimum,
+                (None, None, _) => return Err("Record has no TTL and there is no $TTL or previous TTL to inherit".to_owned()),
+            };
+
+            let class = class.unwrap_or(self.last_class);
+            let len = record.to_bytes()?.len() as u16;
+
+            self.last_owner = Some(name.clone());
+            self.last_class = class;
+
+            return Ok(DNSRecord { name, rtype, class, ttl, len, record });
+        }
+
+        Err("Missing record type".to_owned())
+    }
+
+    /// $GENERATE <range> <lhs> [<TTL>] [<class>] <type> <rhs>
+    fn generate(&mut self, args: &[Token], records: &mut Vec<DNSRecord>) -> Result<(), String> {
+        let [range, lhs, middle @ .., rhs] = args else {
+            return Err("Expected $GENERATE <range> <lhs> [<ttl>] [<class>] <type> <rhs>".to_owned());
+        };
+
+        if middle.is_empty() {
+            return Err("Missing record type in $GENERATE".to_owned());
+        }
+
+        let (bounds, step) = range.text.split_once('/').unwrap_or((&range.text, "1"));
+        let (start, stop) = bounds.split_once('-').ok_or_else(|| format!("Invalid $GENERATE range {}", range.text))?;
+
+        let parse = |value: &str| value.parse::<i64>().map_err(|_| format!("Invalid $GENERATE range {}", range.text));
+        let (start, stop, step) = (parse(start)?, parse(stop)?, parse(step)?);
+
+        if start < 0 || stop < start || step <= 0 {
+            return Err(format!("Invalid $GENERATE range {}", range.text));
+        }
+
+        for i in (start..=stop).step_by(step as usize) {
+            let mut tokens = vec![Token { text: substitute(&lhs.text, i)?, quoted: false }];
+            tokens.extend_from_slice(middle);
+            tokens.push(Token { text: substitute(&rhs.text, i)?, quoted: rhs.quoted });
+
+            records.push(self.parse_record(false, &tokens)?);
+        }
+
+        Ok(())
+    }
+}
+
+/// Makes `origin` fully qualified, the root being ""
+pub fn normalize_origin(origin: &str) -> String {
+    match origin {
+        "" | "." => "".to_owned(),
+        origin if origin.ends_with('.') => origin.to_owned(),
+        origin => format!("{}.", origin),
+    }
+}
+
+/// Replaces $ and ${offset[,width[,base]]} in $GENERATE templates, \$ is a literal $
+fn substitute(template: &str, iterator: i64) -> Result<String, String> {
+    let mut result = String::new();
+    let mut chars = template.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if chars.peek() == Some(&'$') => result.extend(chars.next()),
+            '$' if chars.peek() == Some(&'{') => {
+                chars.next();
+
+                let modifier = chars.by_ref().take_while(|c| *c != '}').collect::<String>();
+                let mut parts = modifier.split(',');
+
+                let invalid = || format!("Invalid $GENERATE modifier ${{{}}}", modifier);
+
+                let offset = parts.next().filter(|x| ! x.is_empty()).map(|x| x.parse::<i64>()).unwrap_or(Ok(0)).map_err(|_| invalid())?;
+                let width = parts.next().map(|x| x.parse::<usize>()).unwrap_or(Ok(0)).map_err(|_| invalid())?;
+                let base = parts.next().unwrap_or("d");
+
+                let value = iterator + offset;
+
+                if value < 0 {
+                    return Err(invalid());
+                }
+
+                let formatted = match base {
+                    "d" => format!("{:0width$}", value, width = width),
+                    "o" => format!("{:0width$o}", value, width = width),
+                    "x" => format!("{:0width$x}", value, width = width),
+                    "X" => format!("{:0width$X}", value, width = width),
+                    // Nibble mode, reversed hex digits separated by dots as used in ip6.arpa
+                    "n" | "N" => {
+                        let hex = format!("{:0width$x}", value, width = width);
+                        let hex = if base == "N" { hex.to_ascii_uppercase() } else { hex };
+
+                        hex.chars().rev().map(String::from).collect::<Vec<_>>().join(".")
+                    },
+                    _ => return Err(invalid()),
+                };
+
+                result.push_str(&formatted);
+            },
+            '$' => result.push_str(&iterator.to_string()),
+            c => result.push(c),
+        }
+    }
+
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs};
+
+    use crate::parser::{record::{DNSARecord, DNSMXRecord, DNSRecordData, DNSTXTRecord}, types::{RecordClass, RecordType}};
+
+    use super::{substitute, ZoneFileParser};
+
+    static ZONE: &str = r#"
+$ORIGIN example.com.
+$TTL 1h
+@   IN  SOA ns1 hostmaster (
+            2024010101 ; serial
+            3h 15m 1w 1d )
+        NS  ns1
+        MX  10 mail.example.org.
+ns1 300 A   192.0.2.1
+    IN 600 AAAA 2001:db8::1
+txt     TXT "v=spf1 -all" " more"
+$ORIGIN sub
+www     CNAME @
+private TYPE65280 \# 4 deadbeef
+$GENERATE 1-3 host-$ A 10.0.0.${10}
+"#;
+
+    #[test]
+    fn parses_zone_files() {
+        let records = ZoneFileParser::new("example.com").parse_str(ZONE, "example.com.zone").unwrap();
+
+        let summary = records
+            .iter()
+            .map(|record| format!("{} {} {} {}", record.name, record.ttl, record.class, record.rtype))
+            .collect::<Vec<_>>();
+
+        assert_eq!(summary, vec![
+            "example.com. 3600 IN SOA",
+            "example.com. 3600 IN NS",
+            "example.com. 3600 IN MX",
+            "ns1.example.com. 300 IN A",
+            "ns1.example.com. 600 IN AAAA",
+            "txt.example.com. 3600 IN TXT",
+            "www.sub.example.com. 3600 IN CNAME",
+            "private.sub.example.com. 3600 IN TYPE65280",
+            "host-1.sub.example.com. 3600 IN A",
+            "host-2.sub.example.com. 3600 IN A",
+            "host-3.sub.example.com. 3600 IN A",
+        ]);
+
+        match &records[0].record {
+            DNSRecordData::SOA(soa) => {
+                assert_eq!(soa.mname, "ns1.example.com.");
+                assert_eq!(soa.serial, 2024010101);
+                assert_eq!((soa.refresh, soa.retry, soa.expire, soa.minimum), (10800, 900, 604800, 86400));
+            },
+            record => panic!("Expected SOA, got {:?}", record),
+        }
+
+        assert_eq!(records[2].record, DNSRecordData::MX(DNSMXRecord { preference: 10, exchange: "mail.example.org.".to_owned() }));
+        assert_eq!(records[5].record, DNSRecordData::TXT(DNSTXTRecord { text: "v=spf1 -all more".to_owned() }));
+        assert_eq!(records[10].record, DNSRecordData::A(DNSARecord { ip: [10, 0, 0, 13] }));
+        assert_eq!(records[3].len, 4);
+        assert_eq!(records[4].class, RecordClass::IN);
+        assert_eq!(records[7].rtype, RecordType::Unknown(65280));
+    }
+
+    #[test]
+    fn reports_errors_with_file_and_line() {
+        let mut parser = ZoneFileParser::new("example.com.");
+
+        assert_eq!(
+            parser.parse_str("$TTL 60\n@ SOA ns1 host 1 2 3 4 5\nwww A 300.0.0.1\n", "db.example"),
+            Err("db.example:3: Invalid IPv4 address 300.0.0.1".to_owned()),
+        );
+        assert_eq!(
+            ZoneFileParser::new("example.com.").parse_str("www A 192.0.2.1\n", "db.example"),
+            Err("db.example:1: Record has no TTL and there is no $TTL or previous TTL to inherit".to_owned()),
+        );
+        assert_eq!(
+            ZoneFileParser::new("example.com.").parse_str("\n\n  A 192.0.2.1\n", "db.example"),
+            Err("db.example:3: Record has no owner and there is no previous one to inherit".to_owned()),
+        );
+    }
+
+    #[test]
+    fn includes_files_with_their_own_origin() {
+        let dir = env::temp_dir().join(format!("rustdns-zone-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("hosts.zone"), "www 60 A 192.0.2.10\n$ORIGIN elsewhere.\n").unwrap();
+        fs::write(dir.join("main.zone"), "$INCLUDE hosts.zone lab\nmail 60 A 192.0.2.20\n$INCLUDE missing.zone\n").unwrap();
+
+        let result = ZoneFileParser::new("example.com.").parse_file(dir.join("main.zone").to_str().unwrap());
+
+        let error = result.unwrap_err();
+        assert!(error.contains("main.zone:3: Failed to read zone file"), "{}", error);
+
+        fs::write(dir.join("main.zone"), "$INCLUDE hosts.zone lab\nmail 60 A 192.0.2.20\n").unwrap();
+        let records = ZoneFileParser::new("example.com.").parse_file(dir.join("main.zone").to_str().unwrap()).unwrap();
+
+        assert_eq!(records[0].name, "www.lab.example.com.");
+        assert_eq!(records[1].name, "mail.example.com.");
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn substitutes_generate_templates() {
+        assert_eq!(substitute("host-$", 7), Ok("host-7".to_owned()));
+        assert_eq!(substitute("\\$-${-1,3,d}", 7), Ok("$-006".to_owned()));
+        assert_eq!(substitute("${0,4,x}", 26), Ok("001a".to_owned()));
+        assert_eq!(substitute("${0,2,n}", 26), Ok("a.1".to_owned()));
+        assert!(substitute("${0,2,q}", 26).is_err());
+    }
+}
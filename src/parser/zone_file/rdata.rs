@@ -0,0 +1,228 @@
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+use crate::parser::{record::{DNSAAAARecord, DNSARecord, DNSCNameRecord, DNSHINFORecord, DNSLOCRecord, DNSMXRecord, DNSNSRecord, DNSRPRecord, DNSRecordData, DNSRecordsParser, DNSSOARecord, DNSTXTRecord}, types::RecordType};
+
+use super::tokenizer::Token;
+
+/// Turns a name from the zone file into a fully qualified one
+pub fn absolute_name(name: &str, origin: &str) -> Result<String, String> {
+    if name.is_empty() {
+        return Err("Empty domain name".to_owned());
+    }
+
+    let absolute = match name {
+        "@" => origin.to_owned(),
+        "." => "".to_owned(),
+        name if name.ends_with('.') && ! name.ends_with("\\.") => name.to_owned(),
+        name if origin.is_empty() => format!("{}.", name),
+        name => format!("{}.{}", name, origin),
+    };
+
+    if absolute.len() > 255 {
+        return Err(format!("Domain name {} exceeds the maximum length allowed", absolute));
+    }
+
+    if let Some(label) = absolute.split('.').find(|label| label.len() > 63) {
+        return Err(format!("Domain label {} exceeds the maximum length allowed", label));
+    }
+
+    Ok(absolute)
+}
+
+/// Parses a TTL, either in seconds or with BIND's units (e.g. 1h30m)
+pub fn parse_ttl(text: &str) -> Result<u32, String> {
+    let invalid = || format!("Invalid TTL {}", text);
+
+    if text.is_empty() {
+        return Err(invalid());
+    }
+
+    if let Ok(value) = text.parse::<u32>() {
+        return Ok(value);
+    }
+
+    let mut total: u64 = 0;
+    let mut value: Option<u64> = None;
+
+    for c in text.chars() {
+        if let Some(digit) = c.to_digit(10) {
+            value = Some(value.unwrap_or(0) * 10 + digit as u64);
+
+            if value > Some(u32::MAX as u64) {
+                return Err(invalid());
+            }
+
+            continue;
+        }
+
+        let multiplier = match c.to_ascii_lowercase() {
+            's' => 1,
+            'm' => 60,
+            'h' => 60 * 60,
+            'd' => 60 * 60 * 24,
+            'w' => 60 * 60 * 24 * 7,
+            _ => return Err(invalid()),
+        };
+
+        total += value.take().ok_or_else(invalid)? * multiplier;
+    }
+
+    if value.is_some() {
+        return Err(invalid());
+    }
+
+    u32::try_from(total).map_err(|_| invalid())
+}
+
+fn parse_number<T: std::str::FromStr>(token: Option<&Token>, what: &str) -> Result<T, String> {
+    let token = token.ok_or_else(|| format!("Missing {}", what))?;
+
+    token.text.parse::<T>().map_err(|_| format!("Invalid {} {}", what, token.text))
+}
+
+fn parse_name(token: Option<&Token>, origin: &str, what: &str) -> Result<String, String> {
+    let token = token.ok_or_else(|| format!("Missing {}", what))?;
+
+    absolute_name(&token.text, origin)
+}
+
+fn decode_hex(text: &str) -> Result<Vec<u8>, String> {
+    if ! text.len().is_multiple_of(2) {
+        return Err(format!("Invalid hex data {}", text));
+    }
+
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&text[i..(i + 2)], 16).map_err(|_| format!("Invalid hex data {}", text)))
+        .collect()
+}
+
+/// Generic record data from RFC 3597 section 5: \# <length> <hex data>
+fn parse_generic(rtype: RecordType, tokens: &[Token]) -> Result<DNSRecordData, String> {
+    let len = parse_number::<usize>(tokens.get(1), "generic record data length")?;
+    let hex = tokens[2.min(tokens.len())..]
+        .iter()
+        .map(|token| token.text.as_str())
+        .collect::<String>();
+    let data = decode_hex(&hex)?;
+
+    if data.len() != len {
+        return Err(format!("Generic record data length is {}, but {} bytes were given", len, data.len()));
+    }
+
+    if len == 0 {
+        return Ok(DNSRecordData::Empty);
+    }
+
+    // Known types go through the wire parser so that they end up typed
+    let (record, _) = DNSRecordsParser::new(&data).parse_record_data(rtype, len, 0)?;
+
+    Ok(record)
+}
+
+/// Parses the record data of a `rtype` record from its presentation format
+pub fn parse_rdata(rtype: RecordType, tokens: &[Token], origin: &str) -> Result<DNSRecordData, String> {
+    if tokens.first().is_some_and(|token| token.text == "\\#" && ! token.quoted) {
+        return parse_generic(rtype, tokens);
+    }
+
+    let mut tokens = tokens.iter();
+
+    let record = match rtype {
+        RecordType::A => {
+            let ip = parse_number::<Ipv4Addr>(tokens.next(), "IPv4 address")?;
+
+            DNSRecordData::A(DNSARecord { ip: ip.octets() })
+        },
+        RecordType::AAAA => {
+            let ip = parse_number::<Ipv6Addr>(tokens.next(), "IPv6 address")?;
+
+            DNSRecordData::AAAA(DNSAAAARecord { ip: ip.octets() })
+        },
+        RecordType::NS => DNSRecordData::NS(DNSNSRecord {
+            nsdname: parse_name(tokens.next(), origin, "name server")?,
+        }),
+        RecordType::CNAME => DNSRecordData::CNAME(DNSCNameRecord {
+            cname: parse_name(tokens.next(), origin, "canonical name")?,
+        }),
+        RecordType::MX => DNSRecordData::MX(DNSMXRecord {
+            preference: parse_number(tokens.next(), "MX preference")?,
+            exchange: parse_name(tokens.next(), origin, "mail exchange")?,
+        }),
+        RecordType::SOA => {
+            let mname = parse_name(tokens.next(), origin, "primary name server")?;
+            let rname = parse_name(tokens.next(), origin, "responsible mailbox")?;
+            let serial = parse_number(tokens.next(), "serial")?;
+            let mut read_ttl = |what: &str| {
+                let token = tokens.next().ok_or_else(|| format!("Missing {}", what))?;
+                parse_ttl(&token.text)
+            };
+
+            DNSRecordData::SOA(DNSSOARecord {
+                mname,
+                rname,
+                serial,
+                refresh: read_ttl("refresh")?,
+                retry: read_ttl("retry")?,
+                expire: read_ttl("expire")?,
+                minimum: read_ttl("minimum")?,
+            })
+        },
+        RecordType::TXT => {
+            let text = tokens.by_ref().map(|token| token.text.as_str()).collect::<String>();
+
+            DNSRecordData::TXT(DNSTXTRecord { text })
+        },
+        RecordType::HINFO => {
+            let mut read = |what: &str| tokens.next().map(|token| token.text.clone()).ok_or_else(|| format!("Missing {}", what));
+
+            DNSRecordData::HINFO(DNSHINFORecord {
+                cpu: read("HINFO cpu")?,
+                os: read("HINFO os")?,
+            })
+        },
+        RecordType::RP => DNSRecordData::RP(DNSRPRecord {
+            mbox: parse_name(tokens.next(), origin, "RP mailbox")?,
+            txt: parse_name(tokens.next(), origin, "RP TXT name")?,
+        }),
+        RecordType::LOC => {
+            let text = tokens.by_ref().map(|token| token.text.as_str()).collect::<Vec<_>>().join(" ");
+
+            DNSRecordData::LOC(text.parse::<DNSLOCRecord>()?)
+        },
+        RecordType::Unknown(_) => {
+            return Err(format!("Record type {} can only be written in the generic \\# format", rtype));
+        },
+        _ => return Err(format!("Record type {} is not supported, use the generic \\# format instead", rtype)),
+    };
+
+    if let Some(token) = tokens.next() {
+        return Err(format!("Unexpected {} at the end of {} record", token.text, rtype));
+    }
+
+    Ok(record)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{absolute_name, parse_ttl};
+
+    #[test]
+    fn resolves_relative_names() {
+        assert_eq!(absolute_name("@", "example.com."), Ok("example.com.".to_owned()));
+        assert_eq!(absolute_name("www", "example.com."), Ok("www.example.com.".to_owned()));
+        assert_eq!(absolute_name("www.example.org.", "example.com."), Ok("www.example.org.".to_owned()));
+        assert_eq!(absolute_name("com", ""), Ok("com.".to_owned()));
+        assert_eq!(absolute_name(".", "example.com."), Ok("".to_owned()));
+    }
+
+    #[test]
+    fn parses_ttls() {
+        assert_eq!(parse_ttl("3600"), Ok(3600));
+        assert_eq!(parse_ttl("1h30m"), Ok(5400));
+        assert_eq!(parse_ttl("1W2D"), Ok(777_600));
+        assert!(parse_ttl("1h30").is_err());
+        assert!(parse_ttl("h").is_err());
+        assert!(parse_ttl("-1").is_err());
+    }
+}
@@ -0,0 +1,160 @@
+use std::{iter::Peekable, str::Chars};
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Token {
+    pub text: String,
+
+    /// Whether the token was a "quoted string", which is never a keyword
+    pub quoted: bool,
+}
+
+/// A logical line, parenthesised records spanning multiple physical lines are joined
+#[derive(Debug, PartialEq, Eq)]
+pub struct Line {
+    /// Physical line number the entry starts on (1 based)
+    pub number: usize,
+
+    /// Whether the line starts with a blank, meaning the owner is omitted
+    pub blank_owner: bool,
+
+    pub tokens: Vec<Token>,
+}
+
+/// Reads a quoted string up to its closing quote, resolving \X and \DDD escapes
+fn read_quoted(chars: &mut Peekable<Chars>, line: &mut usize) -> Result<String, String> {
+    let mut bytes = vec![];
+
+    loop {
+        match chars.next() {
+            None => return Err("Unterminated quoted string".to_owned()),
+            Some('"') => break,
+            Some('\\') => {
+                let digits = (0..3)
+                    .map_while(|_| chars.next_if(|c| c.is_ascii_digit()))
+                    .collect::<String>();
+
+                if digits.is_empty() {
+                    let c = chars.next().ok_or("Unterminated escape sequence")?;
+                    let mut buf = [0u8; 4];
+                    bytes.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+                } else if digits.len() == 3 {
+                    let value = digits.parse::<u8>().map_err(|_| format!("Invalid escape sequence \\{}", digits))?;
+                    bytes.push(value);
+                } else {
+                    return Err(format!("Invalid escape sequence \\{}", digits));
+                }
+            },
+            Some(c) => {
+                if c == '\n' {
+                    *line += 1;
+                }
+
+                let mut buf = [0u8; 4];
+                bytes.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+            },
+        }
+    }
+
+    Ok(String::from_utf8_lossy(&bytes).to_string())
+}
+
+/// Splits a zone file into logical lines of tokens, dropping comments and
+/// parentheses. Errors carry the physical line they occurred on.
+pub fn tokenize(text: &str) -> Result<Vec<Line>, (usize, String)> {
+    let mut lines = vec![];
+    let mut tokens = vec![];
+    let mut chars = text.chars().peekable();
+
+    let mut depth = 0;
+    let mut line = 1;
+    let mut start_line = 1;
+    let mut blank_owner = false;
+    let mut at_line_start = true;
+
+    while let Some(c) = chars.next() {
+        if at_line_start && depth == 0 {
+            start_line = line;
+            blank_owner = c == ' ' || c == '\t';
+        }
+
+        at_line_start = false;
+
+        match c {
+            '\n' => {
+                if depth == 0 && ! tokens.is_empty() {
+                    lines.push(Line { number: start_line, blank_owner, tokens: std::mem::take(&mut tokens) });
+                }
+
+                line += 1;
+                at_line_start = true;
+            },
+            ';' => while chars.next_if(|c| *c != '\n').is_some() {},
+            '(' => depth += 1,
+            ')' => {
+                if depth == 0 {
+                    return Err((line, "Unbalanced closing parenthesis".to_owned()));
+                }
+
+                depth -= 1;
+            },
+            '"' => {
+                let text = read_quoted(&mut chars, &mut line).map_err(|e| (line, e))?;
+                tokens.push(Token { text, quoted: true });
+            },
+            c if c.is_whitespace() => {},
+            c => {
+                let mut text = c.to_string();
+
+                // Escapes are kept as is, names and numbers resolve them further down
+                if c == '\\' {
+                    text.extend(chars.next());
+                }
+
+                while let Some(c) = chars.next_if(|c| ! c.is_whitespace() && ! "();\"".contains(*c)) {
+                    text.push(c);
+
+                    if c == '\\' {
+                        text.extend(chars.next());
+                    }
+                }
+
+                tokens.push(Token { text, quoted: false });
+            },
+        }
+    }
+
+    if depth != 0 {
+        return Err((start_line, "Unbalanced opening parenthesis".to_owned()));
+    }
+
+    if ! tokens.is_empty() {
+        lines.push(Line { number: start_line, blank_owner, tokens });
+    }
+
+    Ok(lines)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::tokenize;
+
+    #[test]
+    fn joins_parenthesised_lines_and_drops_comments() {
+        let lines = tokenize("@ IN SOA ns1 host ( ; comment\n  1 2 3\n  4 5 )\n\twww \"a \\\"quoted\\\" \\065\"\n").unwrap();
+
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0].number, 1);
+        assert!(! lines[0].blank_owner);
+        assert_eq!(
+            lines[0].tokens.iter().map(|t| t.text.as_str()).collect::<Vec<_>>(),
+            vec!["@", "IN", "SOA", "ns1", "host", "1", "2", "3", "4", "5"],
+        );
+        assert_eq!(lines[1].number, 4);
+        assert!(lines[1].blank_owner);
+        assert_eq!(lines[1].tokens[1].text, "a \"quoted\" A");
+        assert!(lines[1].tokens[1].quoted);
+
+        assert_eq!(tokenize("a ( b\n c").unwrap_err().0, 1);
+        assert_eq!(tokenize("a\nb )").unwrap_err().0, 2);
+    }
+}
@@ -0,0 +1,151 @@
+use std::{cmp::Ordering, fs};
+
+use crate::parser::{record::{DNSRecord, DNSRecordData, DNSUnknownRecord}, types::RecordType, DomainNameLabel};
+
+use super::normalize_origin;
+
+/// Orders names the DNSSEC way (RFC 4034 section 6.1), label by label starting
+/// from the root, case insensitively
+fn canonical_name_cmp(a: &str, b: &str) -> Ordering {
+    let labels = |name: &str| name
+        .trim_end_matches('.')
+        .rsplit('.')
+        .filter(|label| ! label.is_empty())
+        .map(|label| label.to_ascii_lowercase())
+        .collect::<Vec<_>>();
+
+    labels(a).cmp(&labels(b))
+}
+
+/// Writes records back out as a zone master file
+///
+/// The output is canonical: the SOA comes first, the rest is sorted by owner,
+/// type and record data, owners are relative to the origin and only written
+/// once per name. TTLs and classes are always explicit so that parsing the
+/// output with `ZoneFileParser` gives back the same records.
+pub struct ZoneFileWriter {
+    /// Fully qualified origin the owner names are made relative to
+    origin: String,
+}
+
+impl ZoneFileWriter {
+    pub fn new(origin: &str) -> Self {
+        Self { origin: normalize_origin(origin) }
+    }
+
+    pub fn write_file(&self, path: &str, records: &[DNSRecord]) -> Result<(), String> {
+        let text = self.write(records)?;
+
+        fs::write(path, text).map_err(|e| format!("Failed to write zone file {}, {}", path, e))
+    }
+
+    pub fn write(&self, records: &[DNSRecord]) -> Result<String, String> {
+        // Record data is compared in its uncompressed wire format, which is the canonical RR ordering
+        let mut sorted = records
+            .iter()
+            .map(|record| Ok((record, record.record.to_bytes()?)))
+            .collect::<Result<Vec<_>, String>>()?;
+
+        sorted.sort_by(|(a, a_data), (b, b_data)| {
+            (b.rtype == RecordType::SOA).cmp(&(a.rtype == RecordType::SOA))
+                .then_with(|| canonical_name_cmp(&a.name, &b.name))
+                .then_with(|| u16::from(a.rtype).cmp(&u16::from(b.rtype)))
+                .then_with(|| a_data.cmp(b_data))
+        });
+
+        let mut text = format!("$ORIGIN {}\n", DomainNameLabel::presentation(&self.origin));
+        let mut last_owner: Option<&str> = None;
+
+        for (record, data) in sorted {
+            let owner = match last_owner {
+                Some(owner) if owner.eq_ignore_ascii_case(&record.name) => "".to_owned(),
+                _ => self.relativize(&record.name),
+            };
+
+            last_owner = Some(&record.name);
+
+            // Registered types have no presentation format the parser knows about
+            let rdata = match record.record {
+                DNSRecordData::Custom(_) => DNSUnknownRecord { data }.to_string(),
+                _ => record.record.to_string(),
+            };
+
+            text.push_str(&format!("{}\t{}\t{}\t{}\t{}\n", owner, record.ttl, record.class, record.rtype, rdata));
+        }
+
+        Ok(text)
+    }
+
+    /// `name` relative to the origin, or as is when it's outside of it
+    fn relativize(&self, name: &str) -> String {
+        if name.eq_ignore_ascii_case(&self.origin) {
+            return "@".to_owned();
+        }
+
+        if self.origin.is_empty() {
+            return DomainNameLabel::presentation(name).to_owned();
+        }
+
+        let suffix = format!(".{}", self.origin);
+
+        match name.len().checked_sub(suffix.len()) {
+            Some(len) if len > 0 && name[len..].eq_ignore_ascii_case(&suffix) => name[..len].to_owned(),
+            _ => DomainNameLabel::presentation(name).to_owned(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cmp::Ordering;
+
+    use crate::parser::zone_file::ZoneFileParser;
+
+    use super::{canonical_name_cmp, ZoneFileWriter};
+
+    #[test]
+    fn orders_names_canonically() {
+        // Example from RFC 4034 section 6.1
+        let mut names = vec!["z.example.", "example.", "*.z.example.", "yljkjljk.a.example.", "a.example.", "Z.a.example.", "zABC.a.EXAMPLE."];
+        names.sort_by(|a, b| canonical_name_cmp(a, b));
+
+        assert_eq!(names, vec!["example.", "a.example.", "yljkjljk.a.example.", "Z.a.example.", "zABC.a.EXAMPLE.", "z.example.", "*.z.example."]);
+        assert_eq!(canonical_name_cmp("", "com."), Ordering::Less);
+    }
+
+    #[test]
+    fn writes_canonical_zone_files() {
+        let zone = r#"
+$ORIGIN example.com.
+$TTL 3600
+www     A       192.0.2.2
+        A       192.0.2.1
+@       NS      ns1
+@       SOA     ns1 hostmaster 1 10800 900 604800 86400
+ns1     A       192.0.2.53
+txt     TXT     "hello \"world\""
+mail.example.org. 60 MX 10 mail.example.org.
+"#;
+
+        let records = ZoneFileParser::new("example.com.").parse_str(zone, "db.example").unwrap();
+        let text = ZoneFileWriter::new("example.com.").write(&records).unwrap();
+
+        assert_eq!(text, [
+            "$ORIGIN example.com.",
+            "@\t3600\tIN\tSOA\tns1.example.com. hostmaster.example.com. 1 10800 900 604800 86400",
+            "\t3600\tIN\tNS\tns1.example.com.",
+            "ns1\t3600\tIN\tA\t192.0.2.53",
+            "txt\t3600\tIN\tTXT\t\"hello \\\"world\\\"\"",
+            "www\t3600\tIN\tA\t192.0.2.1",
+            "\t3600\tIN\tA\t192.0.2.2",
+            "mail.example.org.\t60\tIN\tMX\t10 mail.example.org.",
+            "",
+        ].join("\n"));
+
+        let reparsed = ZoneFileParser::new("example.com.").parse_str(&text, "export").unwrap();
+
+        assert_eq!(reparsed.len(), records.len());
+        assert!(records.iter().all(|record| reparsed.contains(record)));
+        assert_eq!(ZoneFileWriter::new("example.com.").write(&reparsed), Ok(text));
+    }
+}
@@ -0,0 +1,98 @@
+use std::{collections::HashSet, fs};
+
+use crate::parser::zone_file::normalize_origin;
+
+/// Names that are answered with NXDOMAIN instead of being resolved, along
+/// with everything below them
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Blocklist {
+    /// Lowercased and with a trailing dot
+    names: HashSet<String>,
+}
+
+impl Blocklist {
+    /// Merges all of the lists at `paths`
+    pub fn load(paths: &[String]) -> Result<Self, String> {
+        let mut blocklist = Self::default();
+
+        for path in paths {
+            let text = fs::read_to_string(path)
+                .map_err(|e| format!("Failed to read blocklist {}, {}", path, e))?;
+
+            blocklist.extend(&text).map_err(|e| format!("{}: {}", path, e))?;
+        }
+
+        Ok(blocklist)
+    }
+
+    /// Adds the names of a list, either one name per line or in hosts file
+    /// format (`0.0.0.0 ads.example.com`), `#` starts a comment
+    pub fn extend(&mut self, text: &str) -> Result<(), String> {
+        for (i, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default();
+            let fields = line.split_whitespace().collect::<Vec<_>>();
+
+            let name = match fields.as_slice() {
+                [] => continue,
+                [name] | [_, name] => name,
+                _ => return Err(format!("line {}: Expected a name, got {}", i + 1, line.trim())),
+            };
+
+            if name.contains(|c: char| c.is_whitespace() || c == '/' || c == ':') {
+                return Err(format!("line {}: Invalid name {}", i + 1, name));
+            }
+
+            self.names.insert(normalize_origin(name).to_ascii_lowercase());
+        }
+
+        Ok(())
+    }
+
+    /// Whether `name` or any of its parents is on the list
+    pub fn blocks(&self, name: &str) -> bool {
+        if self.names.is_empty() {
+            return false;
+        }
+
+        let name = name.to_ascii_lowercase();
+        let mut suffix = name.as_str();
+
+        while ! suffix.is_empty() {
+            if self.names.contains(suffix) {
+                return true;
+            }
+
+            suffix = suffix.split_once('.').map_or("", |(_, parent)| parent);
+        }
+
+        false
+    }
+
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Blocklist;
+
+    #[test]
+    fn blocks_names_and_their_subdomains() {
+        let mut blocklist = Blocklist::default();
+        blocklist.extend("# ads\nads.example.com\n0.0.0.0 tracker.example.net.  # hosts format\n\n").unwrap();
+
+        assert_eq!(blocklist.len(), 2);
+        assert!(blocklist.blocks("ads.example.com."));
+        assert!(blocklist.blocks("x.y.ADS.example.com."));
+        assert!(blocklist.blocks("tracker.example.net."));
+        assert!(! blocklist.blocks("example.com."));
+        assert!(! blocklist.blocks("badads.example.com."));
+
+        assert_eq!(blocklist.extend("a b c"), Err("line 1: Expected a name, got a b c".to_owned()));
+    }
+}
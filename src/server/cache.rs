@@ -0,0 +1,1375 @@
+use std::{collections::{BTreeMap, HashMap, HashSet}, fs, io, mem, net::{IpAddr, Ipv4Addr}, sync::{atomic::{AtomicU64, AtomicUsize, Ordering}, Mutex, MutexGuard}, time::{Duration, Instant}};
+
+use crate::parser::{header::{DNSHeader, DNSHeaderType, Opcode, ResultCode}, packet::DNSPacket, record::{DNSRecord, DNSRecordData}, types::{RecordClass, RecordType}, view::DNSPacketView};
+
+use super::zone::{is_subdomain, names_eq};
+
+/// Longest CNAME chain answered from the cache
+const MAX_CNAME_CHAIN: usize = 8;
+
+/// Nothing is kept for longer than a day, whatever its TTL says
+const MAX_TTL: u32 = 86_400;
+
+/// Negative answers are kept for 3 hours at most (RFC 2308 5)
+const MAX_NEGATIVE_TTL: u32 = 10_800;
+
+/// TTL of expired data served because upstream servers couldn't be reached (RFC 8767 4)
+const STALE_ANSWER_TTL: u32 = 30;
+
+/// Times an entry has to be answered from before it's worth prefetching
+const PREFETCH_MIN_HITS: u32 = 3;
+
+/// Entries are prefetched once less than this fraction of their TTL is left
+const PREFETCH_WINDOW: u32 = 10;
+
+/// Identifies cache files, followed by the format version
+const FILE_MAGIC: &[u8; 8] = b"RDNSCACH";
+const FILE_VERSION: u16 = 1;
+
+/// The records of an answer section that make up the CNAME chain from the
+/// question on, as far as it stays inside the zone the server was asked for
+pub struct AnswerChain<'a> {
+    pub records: Vec<&'a DNSRecord>,
+
+    /// Name the chain ends at, the question when there are no CNAMEs
+    pub target: String,
+
+    /// Whether `target` is outside of the zone, the server can't be believed
+    /// about it and it has to be asked for on its own
+    pub left_zone: bool,
+}
+
+impl<'a> AnswerChain<'a> {
+    pub fn new(resp: &'a DNSPacket, zone: &str) -> Self {
+        let Some(question) = resp.questions.first() else {
+            return AnswerChain { records: vec![], target: String::new(), left_zone: false };
+        };
+
+        let mut chain = AnswerChain {
+            records: vec![],
+            target: question.name.clone(),
+            left_zone: false,
+        };
+
+        // CNAMEs are the answer themselves when they're asked for
+        let follow = ! matches!(question.rtype, RecordType::CNAME | RecordType::ANY);
+
+        if ! is_subdomain(&chain.target, zone) {
+            chain.left_zone = true;
+
+            return chain;
+        }
+
+        // Owners the answer section may talk about, the question and whatever it's an alias of
+        let mut owners = vec![chain.target.clone()];
+
+        for record in &resp.answers {
+            if ! owners.iter().any(|owner| names_eq(owner, &record.name)) {
+                continue;
+            }
+
+            if let (DNSRecordData::CNAME(cname), true) = (&record.record, follow) {
+                if chain.left_zone {
+                    continue;
+                }
+
+                chain.target = cname.cname.clone();
+                chain.left_zone = ! is_subdomain(&cname.cname, zone);
+
+                if ! chain.left_zone {
+                    owners.push(cname.cname.clone());
+                }
+            }
+
+            chain.records.push(record);
+        }
+
+        chain
+    }
+}
+
+/// How much the cache holds, how stale data is used (RFC 8767), how
+/// popular data is kept fresh and where the cache is saved
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CacheOptions {
+    /// Approximate memory the cache may take, least recently used entries
+    /// are evicted to stay under it
+    pub max_bytes: usize,
+
+    /// How long past their TTL records are kept around, to be served when
+    /// upstream servers can't be reached. 0 turns serve-stale off.
+    pub max_stale: Duration,
+
+    /// How long a client waits on upstream servers before it's answered
+    /// with stale data, when there is some
+    pub client_timeout: Duration,
+
+    /// Whether records answered from often are refreshed shortly before they expire
+    pub prefetch: bool,
+
+    /// File the cache is saved to on shutdown and loaded from on startup
+    pub file: Option<String>,
+
+    /// How often the cache is saved while running, 0 only saves it on shutdown
+    pub save_interval: Duration,
+}
+
+impl Default for CacheOptions {
+    fn default() -> Self {
+        Self {
+            max_bytes: 32 << 20,
+            max_stale: Duration::from_secs(86_400),
+            client_timeout: Duration::from_millis(1800),
+            prefetch: true,
+            file: None,
+            save_interval: Duration::ZERO,
+        }
+    }
+}
+
+/// How far data can be trusted, from least to most (RFC 2181 5.4.1). Cached
+/// data is only ever replaced by data that's trusted as much or more.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[repr(u8)]
+pub enum Trust {
+    /// Additional section, glue addresses of name servers
+    Additional,
+
+    /// Authority section of a referral
+    Authority,
+
+    /// Answer section of a non authoritative response
+    Answer,
+
+    /// Authority section of an authoritative answer
+    AuthoritativeAuthority,
+
+    /// Answer section of an authoritative answer
+    AuthoritativeAnswer,
+}
+
+impl TryFrom<u8> for Trust {
+    type Error = String;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        [Self::Additional, Self::Authority, Self::Answer, Self::AuthoritativeAuthority, Self::AuthoritativeAnswer]
+            .into_iter()
+            .find(|trust| *trust as u8 == value)
+            .ok_or_else(|| format!("Invalid trust level {}", value))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct CacheKey {
+    /// Lowercased
+    name: String,
+
+    /// NXDOMAIN covers every type, it's kept under ANY which no record has
+    rtype: RecordType,
+    class: RecordClass,
+}
+
+impl CacheKey {
+    fn new(name: &str, rtype: RecordType, class: RecordClass) -> Self {
+        Self { name: name.to_ascii_lowercase(), rtype, class }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum CachedData {
+    Records(Vec<DNSRecord>),
+
+    /// NODATA or NXDOMAIN, along with the SOA of the zone that said so
+    Negative(DNSRecord),
+}
+
+impl CachedData {
+    /// Rough number of bytes taken, the record data is counted at its wire size
+    fn size(&self) -> usize {
+        let record_size = |record: &DNSRecord| mem::size_of::<DNSRecord>() + record.name.len() + record.len as usize;
+
+        match self {
+            Self::Records(records) => records.iter().map(record_size).sum(),
+            Self::Negative(soa) => record_size(soa),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct CacheEntry {
+    data: CachedData,
+    trust: Trust,
+
+    /// TTL it was cached with
+    ttl: u32,
+    expires: Instant,
+
+    /// Times it was answered from
+    hits: u32,
+
+    /// Set once a prefetch was asked for, so that only one query asks
+    prefetching: bool,
+
+    /// Approximate bytes taken, key included
+    size: usize,
+
+    /// Position in the LRU order
+    last_used: u64,
+}
+
+impl CacheEntry {
+    /// What's left of the TTL, stale data gets a short one of its own
+    fn ttl_at(&self, now: Instant) -> u32 {
+        match self.expires > now {
+            true => self.expires.saturating_duration_since(now).as_secs() as u32,
+            false => STALE_ANSWER_TTL,
+        }
+    }
+
+    /// Counts a hit, returns whether the entry should be prefetched now
+    fn hit(&mut self, now: Instant) -> bool {
+        self.hits = self.hits.saturating_add(1);
+
+        let left = self.expires.saturating_duration_since(now).as_secs() as u32;
+        let prefetch = ! self.prefetching && self.hits >= PREFETCH_MIN_HITS && left * PREFETCH_WINDOW < self.ttl;
+
+        self.prefetching |= prefetch;
+
+        prefetch
+    }
+
+    /// The records with their TTLs counted down to what's left of them
+    fn records_at(&self, now: Instant) -> Option<Vec<DNSRecord>> {
+        let ttl = self.ttl_at(now);
+
+        match self.data {
+            CachedData::Records(ref records) => Some(records.iter().map(|record| DNSRecord { ttl, ..record.clone() }).collect()),
+            CachedData::Negative(_) => None,
+        }
+    }
+
+    /// Same as `records_at`, for negative entries as well
+    fn data_at(&self, now: Instant) -> CachedData {
+        let ttl = self.ttl_at(now);
+
+        match self.data {
+            CachedData::Records(ref records) => CachedData::Records(records.iter().map(|record| DNSRecord { ttl, ..record.clone() }).collect()),
+            CachedData::Negative(ref soa) => CachedData::Negative(DNSRecord { ttl, ..soa.clone() }),
+        }
+    }
+}
+
+/// Response built from the cache, negative ones carry the SOA in their
+/// authority section (RFC 2308 6)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CachedAnswer {
+    pub rcode: ResultCode,
+    pub answers: Vec<DNSRecord>,
+    pub authority: Vec<DNSRecord>,
+
+    /// Part of the answer is popular and about to expire, it's worth looking
+    /// up again ahead of time
+    pub prefetch: bool,
+}
+
+/// What the cache holds and how well it's doing since startup
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CacheStats {
+    pub entries: usize,
+    pub bytes: usize,
+    pub max_bytes: usize,
+    pub hits: u64,
+    pub misses: u64,
+    pub evictions: u64,
+}
+
+/// The entries along with their LRU order and the bytes they take
+#[derive(Debug, Default)]
+struct Entries {
+    map: HashMap<CacheKey, CacheEntry>,
+
+    /// Keys by when they were last used, least recently used first
+    lru: BTreeMap<u64, CacheKey>,
+    next_use: u64,
+    bytes: usize,
+}
+
+impl Entries {
+    fn get(&self, key: &CacheKey) -> Option<&CacheEntry> {
+        self.map.get(key)
+    }
+
+    /// The entry at `key` if it's `usable`, it then becomes the most recently used
+    fn touch(&mut self, key: &CacheKey, usable: impl FnOnce(&CacheEntry) -> bool) -> Option<&mut CacheEntry> {
+        let entry = self.map.get_mut(key).filter(|entry| usable(entry))?;
+
+        self.lru.remove(&entry.last_used);
+        self.next_use += 1;
+        entry.last_used = self.next_use;
+        self.lru.insert(entry.last_used, key.clone());
+
+        Some(entry)
+    }
+
+    fn insert(&mut self, key: CacheKey, mut entry: CacheEntry) {
+        self.remove(&key);
+
+        self.next_use += 1;
+        entry.last_used = self.next_use;
+        self.bytes += entry.size;
+        self.lru.insert(entry.last_used, key.clone());
+        self.map.insert(key, entry);
+    }
+
+    fn remove(&mut self, key: &CacheKey) -> Option<CacheEntry> {
+        let entry = self.map.remove(key)?;
+
+        self.lru.remove(&entry.last_used);
+        self.bytes -= entry.size;
+
+        Some(entry)
+    }
+
+    /// Evicts least recently used entries until `bytes` more fit under
+    /// `max_bytes`, returns how many were evicted
+    fn make_room(&mut self, bytes: usize, max_bytes: usize) -> u64 {
+        let mut evicted = 0;
+
+        while self.bytes + bytes > max_bytes {
+            let Some((_, key)) = self.lru.pop_first() else {
+                break;
+            };
+
+            if let Some(entry) = self.map.remove(&key) {
+                self.bytes -= entry.size;
+                evicted += 1;
+            }
+        }
+
+        evicted
+    }
+
+    /// Removes every entry `matches` picks, returns how many were
+    fn remove_where(&mut self, matches: impl Fn(&CacheKey) -> bool) -> usize {
+        let keys = self.map.keys().filter(|key| matches(key)).cloned().collect::<Vec<_>>();
+
+        for key in &keys {
+            self.remove(key);
+        }
+
+        keys.len()
+    }
+}
+
+/// RRsets and negative answers learned from upstream servers, keyed by name,
+/// type and class
+#[derive(Debug, Default)]
+pub struct Cache {
+    entries: Mutex<Entries>,
+
+    /// Approximate memory the entries may take
+    max_bytes: AtomicUsize,
+
+    /// Seconds expired entries are kept for
+    max_stale: AtomicU64,
+
+    hits: AtomicU64,
+    misses: AtomicU64,
+    evictions: AtomicU64,
+}
+
+impl Cache {
+    pub fn new(max_bytes: usize) -> Self {
+        Self { max_bytes: AtomicUsize::new(max_bytes), ..Self::default() }
+    }
+
+    /// Evicts right away whatever no longer fits
+    pub fn set_max_bytes(&self, max_bytes: usize) {
+        self.max_bytes.store(max_bytes, Ordering::SeqCst);
+
+        let evicted = self.lock().make_room(0, max_bytes);
+        self.evictions.fetch_add(evicted, Ordering::SeqCst);
+    }
+
+    pub fn set_max_stale(&self, max_stale: Duration) {
+        self.max_stale.store(max_stale.as_secs(), Ordering::SeqCst);
+    }
+
+    fn max_stale(&self) -> Duration {
+        Duration::from_secs(self.max_stale.load(Ordering::SeqCst))
+    }
+
+    /// Number of RRsets and negative answers held, expired ones included
+    /// until they're evicted
+    pub fn len(&self) -> usize {
+        self.lock().map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lock().map.is_empty()
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        let entries = self.lock();
+
+        CacheStats {
+            entries: entries.map.len(),
+            bytes: entries.bytes,
+            max_bytes: self.max_bytes.load(Ordering::SeqCst),
+            hits: self.hits.load(Ordering::SeqCst),
+            misses: self.misses.load(Ordering::SeqCst),
+            evictions: self.evictions.load(Ordering::SeqCst),
+        }
+    }
+
+    /// Empties the cache, returns how many entries were dropped
+    pub fn flush(&self) -> usize {
+        self.lock().remove_where(|_| true)
+    }
+
+    /// Drops everything cached at `name`, whatever the type
+    pub fn flush_name(&self, name: &str) -> usize {
+        self.lock().remove_where(|key| names_eq(&key.name, name))
+    }
+
+    /// Drops everything cached at `name` and below it
+    pub fn flush_subtree(&self, name: &str) -> usize {
+        self.lock().remove_where(|key| is_subdomain(&key.name, name))
+    }
+
+    /// Caches `records`, which have to form a single RRset. Returns whether it
+    /// was stored, it isn't when what's cached is trusted more or it doesn't
+    /// fit in the cache at all. Least recently used entries make room for it.
+    pub fn insert(&self, records: Vec<DNSRecord>, trust: Trust, now: Instant) -> bool {
+        let Some(first) = records.first() else {
+            return false;
+        };
+
+        // The shortest TTL wins when they differ (RFC 2181 5.2)
+        let ttl = records.iter().map(|record| record.ttl).min().unwrap_or(0).min(MAX_TTL);
+        let key = CacheKey::new(&first.name, first.rtype, first.class);
+
+        // The name exists after all
+        let nxdomain = CacheKey { rtype: RecordType::ANY, ..key.clone() };
+
+        let inserted = self.store(key, CachedData::Records(records), ttl, now + Duration::from_secs(ttl as u64), trust, now);
+
+        if inserted {
+            self.lock().remove(&nxdomain);
+        }
+
+        inserted
+    }
+
+    /// Caches that `name` has no `rtype` records, or doesn't exist at all
+    /// with `ResultCode::NameError`, for as long as `soa` allows (RFC 2308 5)
+    pub fn insert_negative(&self, name: &str, rtype: RecordType, rcode: ResultCode, soa: DNSRecord, trust: Trust, now: Instant) -> bool {
+        let minimum = match soa.record {
+            DNSRecordData::SOA(ref data) => data.minimum,
+            _ => return false,
+        };
+
+        let ttl = soa.ttl.min(minimum).min(MAX_NEGATIVE_TTL);
+
+        let rtype = match rcode {
+            ResultCode::NameError => RecordType::ANY,
+            ResultCode::NoError => rtype,
+            _ => return false,
+        };
+
+        self.store(CacheKey::new(name, rtype, soa.class), CachedData::Negative(soa), ttl, now + Duration::from_secs(ttl as u64), trust, now)
+    }
+
+    /// `ttl` is the one the data came with, it may have less left when it's
+    /// loaded from a file
+    fn store(&self, key: CacheKey, data: CachedData, ttl: u32, expires: Instant, trust: Trust, now: Instant) -> bool {
+        if expires <= now {
+            return false;
+        }
+
+        let mut entries = self.lock();
+
+        if entries.get(&key).is_some_and(|entry| entry.expires > now && entry.trust > trust) {
+            return false;
+        }
+
+        let max_bytes = self.max_bytes.load(Ordering::SeqCst);
+        let size = mem::size_of::<CacheKey>() + mem::size_of::<CacheEntry>() + key.name.len() + data.size();
+
+        if size > max_bytes {
+            return false;
+        }
+
+        // What it replaces doesn't count
+        entries.remove(&key);
+
+        let evicted = entries.make_room(size, max_bytes);
+        self.evictions.fetch_add(evicted, Ordering::SeqCst);
+
+        entries.insert(key, CacheEntry {
+            data,
+            trust,
+            ttl,
+            expires,
+            hits: 0,
+            prefetching: false,
+            size,
+            last_used: 0,
+        });
+
+        true
+    }
+
+    /// Caches what an upstream server told us in answer to the question of
+    /// `resp`. Only records related to the question are kept: the answer's
+    /// CNAME chain, name servers of zones enclosing the name and their
+    /// addresses. NXDOMAIN and NODATA are cached when they come with an SOA.
+    /// Truncated responses may hold partial RRsets and aren't cached at all.
+    ///
+    /// `zone` is the zone the server was asked as being authoritative for,
+    /// "" for forwarders, it's only believed about names in that zone and
+    /// about name servers at or below it.
+    pub fn insert_response(&self, resp: &DNSPacket, zone: &str, now: Instant) {
+        if resp.header.tc {
+            return;
+        }
+
+        let Some(question) = resp.questions.first() else {
+            return;
+        };
+
+        let (answer_trust, authority_trust) = match resp.header.aa {
+            true => (Trust::AuthoritativeAnswer, Trust::AuthoritativeAuthority),
+            false => (Trust::Answer, Trust::Authority),
+        };
+
+        let AnswerChain { records: answers, target, left_zone } = AnswerChain::new(resp, zone);
+
+        let authority = resp.authority
+            .iter()
+            .filter(|record| record.rtype == RecordType::NS && is_subdomain(&question.name, &record.name) && is_subdomain(&record.name, zone))
+            .collect::<Vec<_>>();
+
+        // Glue is only worth anything for the name servers we were just told about
+        let name_servers = answers.iter().chain(&authority)
+            .filter_map(|record| match record.record {
+                DNSRecordData::NS(ref ns) => Some(ns.nsdname.to_ascii_lowercase()),
+                _ => None,
+            })
+            .collect::<HashSet<_>>();
+
+        let additional = resp.additional
+            .iter()
+            .filter(|record| matches!(record.rtype, RecordType::A | RecordType::AAAA) && is_subdomain(&record.name, zone))
+            .filter(|record| name_servers.contains(&record.name.to_ascii_lowercase()))
+            .collect::<Vec<_>>();
+
+        // Where the CNAME chain ends, that's what a negative answer is about,
+        // unless it ends outside of the zone where the server has no say
+        let answered = answers.iter().any(|record| record.rtype == question.rtype && names_eq(&record.name, &target));
+
+        let soa = resp.authority
+            .iter()
+            .find(|record| record.rtype == RecordType::SOA && is_subdomain(&target, &record.name));
+
+        // A NOERROR with name servers is a referral rather than NODATA
+        let negative = ! left_zone && match resp.header.rcode {
+            ResultCode::NameError => true,
+            ResultCode::NoError => ! answered && authority.is_empty(),
+            _ => false,
+        };
+
+        if let Some(soa) = soa.filter(|_| negative) {
+            self.insert_negative(&target, question.rtype, resp.header.rcode, soa.clone(), answer_trust, now);
+        }
+
+        for (records, trust) in [(answers, answer_trust), (authority, authority_trust), (additional, Trust::Additional)] {
+            for rrset in group_rrsets(records) {
+                self.insert(rrset, trust, now);
+            }
+        }
+    }
+
+    /// The RRset at `name`, if it's trusted at least as much as `min_trust`
+    pub fn get(&self, name: &str, rtype: RecordType, class: RecordClass, min_trust: Trust, now: Instant) -> Option<Vec<DNSRecord>> {
+        self.lock()
+            .touch(&CacheKey::new(name, rtype, class), |entry| entry.expires > now && entry.trust >= min_trust)
+            .and_then(|entry| entry.records_at(now))
+    }
+
+    /// A full answer to `qname`/`qtype`, following cached CNAMEs, either
+    /// records or a negative answer. Only data from answer sections is used,
+    /// referrals and glue are never served.
+    pub fn answer(&self, qname: &str, qtype: RecordType, class: RecordClass, now: Instant) -> Option<CachedAnswer> {
+        let answer = self.find_answer(qname, qtype, class, Duration::ZERO, now);
+
+        match answer {
+            Some(_) => self.hits.fetch_add(1, Ordering::SeqCst),
+            None => self.misses.fetch_add(1, Ordering::SeqCst),
+        };
+
+        answer
+    }
+
+    /// Same as `answer`, but data that expired less than the maximum
+    /// staleness ago is used too, with a TTL of 30 seconds (RFC 8767 4)
+    pub fn stale_answer(&self, qname: &str, qtype: RecordType, class: RecordClass, now: Instant) -> Option<CachedAnswer> {
+        match self.max_stale() {
+            max_stale if max_stale.is_zero() => None,
+            max_stale => self.find_answer(qname, qtype, class, max_stale, now),
+        }
+    }
+
+    fn find_answer(&self, qname: &str, qtype: RecordType, class: RecordClass, max_stale: Duration, now: Instant) -> Option<CachedAnswer> {
+        let mut entries = self.lock();
+        let mut answer = CachedAnswer { rcode: ResultCode::NoError, answers: vec![], authority: vec![], prefetch: false };
+        let mut name = qname.to_owned();
+
+        let mut usable = |name: &str, rtype: RecordType| entries
+            .touch(&CacheKey::new(name, rtype, class), |entry| entry.expires + max_stale > now && entry.trust >= Trust::Answer)
+            .map(|entry| {
+                answer.prefetch |= entry.hit(now);
+                entry.data_at(now)
+            });
+
+        for _ in 0..MAX_CNAME_CHAIN {
+            // Every entry is looked at once, so that it's only counted as one hit
+            let nodata = match usable(&name, qtype) {
+                Some(CachedData::Records(records)) => {
+                    answer.answers.extend(records);
+
+                    return Some(answer);
+                },
+                Some(CachedData::Negative(soa)) => Some(soa),
+                None => None,
+            };
+
+            // NXDOMAIN first, then NODATA for the type asked for
+            if let Some(CachedData::Negative(soa)) = usable(&name, RecordType::ANY) {
+                answer.rcode = ResultCode::NameError;
+                answer.authority.push(soa);
+
+                return Some(answer);
+            }
+
+            if let Some(soa) = nodata {
+                answer.authority.push(soa);
+
+                return Some(answer);
+            }
+
+            if qtype == RecordType::CNAME {
+                return None;
+            }
+
+            let Some(CachedData::Records(cname)) = usable(&name, RecordType::CNAME) else {
+                return None;
+            };
+
+            name = match cname.first().map(|record| &record.record) {
+                Some(DNSRecordData::CNAME(target)) => target.cname.clone(),
+                _ => return None,
+            };
+
+            answer.answers.extend(cname);
+        }
+
+        None
+    }
+
+    /// IPv4 addresses of the name servers of the closest zone enclosing
+    /// `qname` we know about, recursion can start there instead of at the root
+    pub fn delegation(&self, qname: &str, now: Instant) -> Option<(String, Vec<IpAddr>)> {
+        let mut zone = qname;
+
+        loop {
+            let name_servers = self.get(zone, RecordType::NS, RecordClass::IN, Trust::Additional, now).unwrap_or_default();
+
+            let addresses = name_servers
+                .iter()
+                .filter_map(|record| match record.record {
+                    DNSRecordData::NS(ref ns) => Some(self.addresses(&ns.nsdname, now)),
+                    _ => None,
+                })
+                .flatten()
+                .collect::<Vec<_>>();
+
+            if ! addresses.is_empty() {
+                return Some((zone.to_owned(), addresses));
+            }
+
+            if zone.is_empty() {
+                return None;
+            }
+
+            zone = zone.split_once('.').map_or("", |(_, parent)| parent);
+        }
+    }
+
+    /// Cached IPv4 addresses of `name`, glue included
+    pub fn addresses(&self, name: &str, now: Instant) -> Vec<IpAddr> {
+        self.get(name, RecordType::A, RecordClass::IN, Trust::Additional, now)
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|record| match record.record {
+                DNSRecordData::A(ref a) => Some(IpAddr::V4(Ipv4Addr::from(a.ip))),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Writes what hasn't expired yet to `path`, replacing it as a whole.
+    /// `wall_clock` is the current UNIX time, TTLs are counted down by how
+    /// long the file sat around when it's loaded. Returns how many entries were saved.
+    pub fn save(&self, path: &str, now: Instant, wall_clock: u64) -> Result<usize, String> {
+        let (data, count) = self.dump(now, wall_clock)?;
+
+        // Written aside first, a crash halfway through leaves the previous file alone
+        let partial = format!("{}.tmp", path);
+        fs::write(&partial, data)
+            .and_then(|_| fs::rename(&partial, path))
+            .map_err(|e| format!("Failed to write cache file {}, {}", path, e))?;
+
+        Ok(count)
+    }
+
+    /// Loads a file written by `save`, a missing one loads nothing. Entries
+    /// that expired since it was saved are left out. Returns how many were loaded.
+    pub fn load(&self, path: &str, now: Instant, wall_clock: u64) -> Result<usize, String> {
+        let data = match fs::read(path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(0),
+            Err(e) => return Err(format!("Failed to read cache file {}, {}", path, e)),
+        };
+
+        self.restore(&data, now, wall_clock).map_err(|e| format!("Invalid cache file {}, {}", path, e))
+    }
+
+    /// Header: magic, version (u16), UNIX time it was saved at (u64) and
+    /// number of entries (u32). Then for each entry, least recently used
+    /// first: name, type (u16) and class (u16) it's kept under, whether it's
+    /// negative (u8), trust (u8), original TTL (u32), TTL left (u32) and a DNS
+    /// message with the records, or the SOA, in its answer section. Numbers
+    /// are big endian, the name and message are prefixed with their u16 length.
+    fn dump(&self, now: Instant, wall_clock: u64) -> Result<(Vec<u8>, usize), String> {
+        // Copied out first, queries shouldn't wait on us serializing all of it
+        let snapshot = {
+            let entries = self.lock();
+
+            entries.lru
+                .values()
+                .filter_map(|key| entries.get(key).filter(|entry| entry.expires > now).map(|entry| (key, entry)))
+                .map(|(key, entry)| (key.clone(), entry.data.clone(), entry.trust, entry.ttl, entry.ttl_at(now)))
+                .collect::<Vec<_>>()
+        };
+
+        let mut body = vec![];
+        let mut count = 0u32;
+
+        for (key, data, trust, ttl, left) in snapshot {
+            let (negative, records) = match data {
+                CachedData::Records(records) => (false, records),
+                CachedData::Negative(soa) => (true, vec![soa]),
+            };
+
+            let message = rrset_message(records).serialize()?;
+
+            write_bytes(&mut body, key.name.as_bytes())?;
+            body.extend_from_slice(&u16::from(key.rtype).to_be_bytes());
+            body.extend_from_slice(&u16::from(key.class).to_be_bytes());
+            body.push(negative as u8);
+            body.push(trust as u8);
+            body.extend_from_slice(&ttl.to_be_bytes());
+            body.extend_from_slice(&left.to_be_bytes());
+            write_bytes(&mut body, &message)?;
+
+            count += 1;
+        }
+
+        let mut data = FILE_MAGIC.to_vec();
+        data.extend_from_slice(&FILE_VERSION.to_be_bytes());
+        data.extend_from_slice(&wall_clock.to_be_bytes());
+        data.extend_from_slice(&count.to_be_bytes());
+        data.extend_from_slice(&body);
+
+        Ok((data, count as usize))
+    }
+
+    fn restore(&self, data: &[u8], now: Instant, wall_clock: u64) -> Result<usize, String> {
+        let mut reader = Reader { data, ptr: 0 };
+
+        if reader.bytes(FILE_MAGIC.len())? != FILE_MAGIC {
+            return Err("not a cache file".to_owned());
+        }
+
+        let version = reader.u16()?;
+
+        if version != FILE_VERSION {
+            return Err(format!("unsupported version {}", version));
+        }
+
+        // A clock that went backwards doesn't make anything younger
+        let elapsed = wall_clock.saturating_sub(reader.u64()?);
+        let count = reader.u32()?;
+        let mut entries = vec![];
+
+        for _ in 0..count {
+            let name_len = reader.u16()? as usize;
+            let name = String::from_utf8(reader.bytes(name_len)?.to_vec()).map_err(|_| "invalid name".to_owned())?;
+            let rtype = RecordType::from(reader.u16()?);
+            let class = RecordClass::from(reader.u16()?);
+            let negative = reader.u8()? != 0;
+            let trust = Trust::try_from(reader.u8()?)?;
+            // No more than what we'd have cached them for in the first place
+            let max_ttl = if negative { MAX_NEGATIVE_TTL } else { MAX_TTL };
+            let ttl = reader.u32()?.min(max_ttl);
+            let left = (reader.u32()?.min(ttl) as u64).saturating_sub(elapsed);
+            let message_len = reader.u16()? as usize;
+            let message = reader.bytes(message_len)?;
+
+            let mut records = DNSPacketView::new(message).and_then(|view| view.to_packet())?.answers;
+
+            let data = match (negative, records.pop()) {
+                (true, Some(soa)) if records.is_empty() && matches!(soa.record, DNSRecordData::SOA(_)) => CachedData::Negative(soa),
+                (true, _) => return Err(format!("negative entry for {} without an SOA", name)),
+                (false, Some(last)) => {
+                    records.push(last);
+
+                    if ! records.iter().all(|record| record.rtype == rtype && record.class == class && names_eq(&record.name, &name)) {
+                        return Err(format!("entry for {} {} holds other records", name, rtype));
+                    }
+
+                    CachedData::Records(records)
+                },
+                (false, None) => return Err(format!("empty entry for {} {}", name, rtype)),
+            };
+
+            entries.push((CacheKey::new(&name, rtype, class), data, ttl, now + Duration::from_secs(left), trust));
+        }
+
+        // Nothing is loaded unless the whole file reads
+        Ok(entries
+            .into_iter()
+            .map(|(key, data, ttl, expires, trust)| self.store(key, data, ttl, expires, trust, now))
+            .filter(|stored| *stored)
+            .count())
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Entries> {
+        self.entries.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// A response holding `records` in its answer section, which is how they're
+/// written to cache files
+fn rrset_message(records: Vec<DNSRecord>) -> DNSPacket {
+    DNSPacket {
+        header: DNSHeader {
+            id: 0,
+            qr: DNSHeaderType::Response,
+            opcode: Opcode::Query,
+            aa: false,
+            tc: false,
+            rd: false,
+            ra: false,
+            z: 0,
+            ad: false,
+            cd: false,
+            rcode: ResultCode::NoError,
+            qdcount: 0,
+            ancount: records.len() as u16,
+            nscount: 0,
+            arcount: 0,
+        },
+        questions: vec![],
+        answers: records,
+        authority: vec![],
+        additional: vec![],
+    }
+}
+
+fn write_bytes(buf: &mut Vec<u8>, bytes: &[u8]) -> Result<(), String> {
+    let len = u16::try_from(bytes.len()).map_err(|_| "Cache entry too large to save".to_owned())?;
+
+    buf.extend_from_slice(&len.to_be_bytes());
+    buf.extend_from_slice(bytes);
+
+    Ok(())
+}
+
+/// Reads cache files, running out of data is an error rather than a panic
+struct Reader<'a> {
+    data: &'a [u8],
+    ptr: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], String> {
+        let bytes = self.data.get(self.ptr..self.ptr + len).ok_or_else(|| "truncated".to_owned())?;
+        self.ptr += len;
+
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, String> {
+        Ok(u16::from_be_bytes(self.bytes(2)?.try_into().expect("Read 2 bytes")))
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        Ok(u32::from_be_bytes(self.bytes(4)?.try_into().expect("Read 4 bytes")))
+    }
+
+    fn u64(&mut self) -> Result<u64, String> {
+        Ok(u64::from_be_bytes(self.bytes(8)?.try_into().expect("Read 8 bytes")))
+    }
+}
+
+/// Splits records into RRsets, keeping the order they first appeared in
+fn group_rrsets(records: Vec<&DNSRecord>) -> Vec<Vec<DNSRecord>> {
+    let mut rrsets: Vec<Vec<DNSRecord>> = vec![];
+
+    for record in records {
+        let rrset = rrsets.iter_mut().find(|rrset| {
+            let first = &rrset[0];
+            first.rtype == record.rtype && first.class == record.class && names_eq(&first.name, &record.name)
+        });
+
+        match rrset {
+            Some(rrset) => rrset.push(record.clone()),
+            None => rrsets.push(vec![record.clone()]),
+        }
+    }
+
+    rrsets
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::IpAddr, time::{Duration, Instant}};
+
+    use crate::parser::{header::{DNSHeader, DNSHeaderType, Opcode, ResultCode}, packet::DNSPacket, question::DNSQuestion, record::{DNSARecord, DNSCNameRecord, DNSNSRecord, DNSRecord, DNSRecordData, DNSSOARecord}, types::{RecordClass, RecordType}};
+
+    use super::{rrset_message, write_bytes, Cache, CacheStats, Trust, FILE_MAGIC, FILE_VERSION, MAX_NEGATIVE_TTL, MAX_TTL};
+
+    fn record(name: &str, ttl: u32, record: DNSRecordData) -> DNSRecord {
+        let rtype = match record {
+            DNSRecordData::A(_) => RecordType::A,
+            DNSRecordData::NS(_) => RecordType::NS,
+            DNSRecordData::CNAME(_) => RecordType::CNAME,
+            DNSRecordData::SOA(_) => RecordType::SOA,
+            _ => unreachable!(),
+        };
+
+        DNSRecord { name: name.to_owned(), rtype, class: RecordClass::IN, ttl, len: 0, record }
+    }
+
+    fn a(name: &str, ttl: u32, ip: [u8; 4]) -> DNSRecord {
+        record(name, ttl, DNSRecordData::A(DNSARecord { ip }))
+    }
+
+    fn ns(name: &str, nsdname: &str) -> DNSRecord {
+        record(name, 3600, DNSRecordData::NS(DNSNSRecord { nsdname: nsdname.to_owned() }))
+    }
+
+    fn soa(name: &str, ttl: u32, minimum: u32) -> DNSRecord {
+        record(name, ttl, DNSRecordData::SOA(DNSSOARecord {
+            mname: format!("ns1.{}", name),
+            rname: format!("hostmaster.{}", name),
+            serial: 1,
+            refresh: 7200,
+            retry: 3600,
+            expire: 1209600,
+            minimum,
+        }))
+    }
+
+    fn response(qname: &str, aa: bool, answers: Vec<DNSRecord>, authority: Vec<DNSRecord>, additional: Vec<DNSRecord>) -> DNSPacket {
+        DNSPacket {
+            header: DNSHeader {
+                id: 1,
+                qr: DNSHeaderType::Response,
+                opcode: Opcode::Query,
+                aa,
+                tc: false,
+                rd: false,
+                ra: false,
+                z: 0,
+                ad: false,
+                cd: false,
+                rcode: ResultCode::NoError,
+                qdcount: 1,
+                ancount: answers.len() as u16,
+                nscount: authority.len() as u16,
+                arcount: additional.len() as u16,
+            },
+            questions: vec![DNSQuestion { name: qname.to_owned(), rtype: RecordType::A, class: RecordClass::IN }],
+            answers,
+            authority,
+            additional,
+        }
+    }
+
+    #[test]
+    fn counts_ttls_down_and_expires_entries() {
+        let cache = Cache::new(1 << 20);
+        let now = Instant::now();
+
+        assert!(cache.insert(vec![a("www.example.com.", 300, [192, 0, 2, 1]), a("www.example.com.", 60, [192, 0, 2, 2])], Trust::Answer, now));
+        assert!(! cache.insert(vec![a("zero.example.com.", 0, [192, 0, 2, 1])], Trust::Answer, now));
+
+        let records = cache.answer("WWW.example.com.", RecordType::A, RecordClass::IN, now + Duration::from_secs(20)).unwrap().answers;
+        assert_eq!(records.iter().map(|record| record.ttl).collect::<Vec<_>>(), vec![40, 40]);
+
+        assert!(cache.answer("www.example.com.", RecordType::A, RecordClass::IN, now + Duration::from_secs(60)).is_none());
+    }
+
+    #[test]
+    fn never_lets_glue_override_answers() {
+        let cache = Cache::new(1 << 20);
+        let now = Instant::now();
+
+        cache.insert(vec![a("ns1.example.com.", 3600, [192, 0, 2, 53])], Trust::AuthoritativeAnswer, now);
+
+        // Glue pointing somewhere else doesn't replace it
+        assert!(! cache.insert(vec![a("ns1.example.com.", 3600, [203, 0, 113, 1])], Trust::Additional, now));
+        assert_eq!(cache.addresses("ns1.example.com.", now), vec!["192.0.2.53".parse::<IpAddr>().unwrap()]);
+
+        // Glue alone is never served as an answer
+        cache.insert(vec![a("ns2.example.com.", 3600, [192, 0, 2, 54])], Trust::Additional, now);
+        assert!(cache.answer("ns2.example.com.", RecordType::A, RecordClass::IN, now).is_none());
+        assert!(cache.insert(vec![a("ns2.example.com.", 3600, [192, 0, 2, 55])], Trust::Answer, now));
+        assert!(cache.answer("ns2.example.com.", RecordType::A, RecordClass::IN, now).is_some());
+
+        // Expired data is replaced by anything
+        assert!(cache.insert(vec![a("ns1.example.com.", 60, [203, 0, 113, 1])], Trust::Additional, now + Duration::from_secs(3600)));
+    }
+
+    #[test]
+    fn caches_referrals_and_answers() {
+        let cache = Cache::new(1 << 20);
+        let now = Instant::now();
+
+        cache.insert_response(&response(
+            "www.example.com.",
+            false,
+            vec![],
+            vec![ns("example.com.", "ns1.example.com."), ns("example.org.", "ns1.example.org.")],
+            vec![a("ns1.example.com.", 3600, [192, 0, 2, 53]), a("ns1.example.org.", 3600, [192, 0, 2, 54]), a("www.example.com.", 3600, [203, 0, 113, 1])],
+        ), "com.", now);
+
+        // Only the delegation for the name that was asked about, and its glue
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.delegation("mail.example.com.", now), Some(("example.com.".to_owned(), vec!["192.0.2.53".parse().unwrap()])));
+        assert_eq!(cache.delegation("example.net.", now), None);
+
+        cache.insert_response(&response(
+            "www.example.com.",
+            true,
+            vec![
+                record("www.example.com.", 300, DNSRecordData::CNAME(DNSCNameRecord { cname: "web.example.com.".to_owned() })),
+                a("web.example.com.", 300, [192, 0, 2, 80]),
+                a("unrelated.example.net.", 300, [192, 0, 2, 99]),
+            ],
+            vec![],
+            vec![],
+        ), "example.com.", now);
+
+        let records = cache.answer("www.example.com.", RecordType::A, RecordClass::IN, now).unwrap().answers;
+        assert_eq!(records.iter().map(|record| record.rtype).collect::<Vec<_>>(), vec![RecordType::CNAME, RecordType::A]);
+        assert!(cache.get("unrelated.example.net.", RecordType::A, RecordClass::IN, Trust::Additional, now).is_none());
+    }
+
+    #[test]
+    fn only_believes_referrals_at_or_below_the_zone_asked() {
+        let cache = Cache::new(1 << 20);
+        let now = Instant::now();
+
+        // A server for example.com. trying to take over com. and its name servers
+        cache.insert_response(&response(
+            "www.example.com.",
+            false,
+            vec![],
+            vec![ns("com.", "a.gtld-servers.net."), ns("com.", "ns.example.com."), ns("sub.example.com.", "ns.sub.example.com.")],
+            vec![a("a.gtld-servers.net.", 3600, [203, 0, 113, 1]), a("ns.example.com.", 3600, [203, 0, 113, 2])],
+        ), "example.com.", now);
+
+        assert_eq!(cache.delegation("www.example.com.", now), None);
+        assert!(cache.get("com.", RecordType::NS, RecordClass::IN, Trust::Additional, now).is_none());
+        assert!(cache.get("a.gtld-servers.net.", RecordType::A, RecordClass::IN, Trust::Additional, now).is_none());
+        assert!(cache.get("ns.example.com.", RecordType::A, RecordClass::IN, Trust::Additional, now).is_none());
+
+        // Name servers for the zone itself are fine, glue only inside of it
+        cache.insert_response(&response(
+            "www.example.com.",
+            false,
+            vec![],
+            vec![ns("example.com.", "ns1.example.com."), ns("example.com.", "ns.example.net.")],
+            vec![a("ns1.example.com.", 3600, [192, 0, 2, 53]), a("ns.example.net.", 3600, [203, 0, 113, 3])],
+        ), "example.com.", now);
+
+        assert_eq!(cache.delegation("www.example.com.", now), Some(("example.com.".to_owned(), vec!["192.0.2.53".parse().unwrap()])));
+        assert!(cache.get("ns.example.net.", RecordType::A, RecordClass::IN, Trust::Additional, now).is_none());
+    }
+
+    #[test]
+    fn only_believes_answers_inside_the_zone_asked() {
+        let cache = Cache::new(1 << 20);
+        let now = Instant::now();
+        let cname = |name: &str, target: &str| record(name, 300, DNSRecordData::CNAME(DNSCNameRecord { cname: target.to_owned() }));
+
+        // A server for evil.example. vouching for what's behind its alias
+        let mut resp = response(
+            "www.evil.example.",
+            true,
+            vec![cname("www.evil.example.", "www.bank.example."), a("www.bank.example.", 300, [203, 0, 113, 66])],
+            vec![soa("evil.example.", 300, 300)],
+            vec![],
+        );
+        resp.header.rcode = ResultCode::NameError;
+        cache.insert_response(&resp, "evil.example.", now);
+
+        // The alias is its to give, but not the address, nor whether it exists
+        assert!(cache.get("www.evil.example.", RecordType::CNAME, RecordClass::IN, Trust::AuthoritativeAnswer, now).is_some());
+        assert!(cache.get("www.bank.example.", RecordType::A, RecordClass::IN, Trust::Additional, now).is_none());
+        assert!(cache.answer("www.bank.example.", RecordType::A, RecordClass::IN, now).is_none());
+
+        // Not even when asked for directly
+        cache.insert_response(&response("www.bank.example.", true, vec![a("www.bank.example.", 300, [203, 0, 113, 66])], vec![], vec![]), "evil.example.", now);
+        assert!(cache.get("www.bank.example.", RecordType::A, RecordClass::IN, Trust::Additional, now).is_none());
+    }
+
+    #[test]
+    fn skips_truncated_responses() {
+        let cache = Cache::new(1 << 20);
+        let now = Instant::now();
+
+        let mut resp = response("www.example.com.", true, vec![a("www.example.com.", 300, [192, 0, 2, 1])], vec![], vec![]);
+        resp.header.tc = true;
+        cache.insert_response(&resp, "", now);
+
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn evicts_the_least_recently_used_entries() {
+        let now = Instant::now();
+        let rrset = |name: &str| vec![a(name, 300, [192, 0, 2, 1])];
+
+        // Room for two of them
+        let cache = Cache::new(1 << 20);
+        cache.insert(rrset("a.example.com."), Trust::Answer, now);
+        let size = cache.stats().bytes;
+        cache.set_max_bytes(size * 2);
+
+        assert!(cache.insert(rrset("b.example.com."), Trust::Answer, now));
+        assert!(cache.answer("a.example.com.", RecordType::A, RecordClass::IN, now).is_some());
+
+        // b was used last longest ago
+        assert!(cache.insert(rrset("c.example.com."), Trust::Answer, now));
+        assert!(cache.answer("b.example.com.", RecordType::A, RecordClass::IN, now).is_none());
+        assert!(cache.answer("a.example.com.", RecordType::A, RecordClass::IN, now).is_some());
+        assert!(cache.answer("c.example.com.", RecordType::A, RecordClass::IN, now).is_some());
+
+        assert_eq!(cache.stats(), CacheStats { entries: 2, bytes: size * 2, max_bytes: size * 2, hits: 3, misses: 1, evictions: 1 });
+
+        // Shrinking evicts right away, and what doesn't fit at all isn't cached
+        cache.set_max_bytes(size);
+        assert_eq!(cache.len(), 1);
+        assert!(! cache.insert(vec![a("d.example.com.", 300, [192, 0, 2, 1]), a("d.example.com.", 300, [192, 0, 2, 2])], Trust::Answer, now));
+    }
+
+    #[test]
+    fn flushes_names_and_subtrees() {
+        let cache = Cache::new(1 << 20);
+        let now = Instant::now();
+
+        for name in ["example.com.", "www.example.com.", "a.b.example.com.", "example.org."] {
+            cache.insert(vec![a(name, 300, [192, 0, 2, 1])], Trust::Answer, now);
+        }
+        cache.insert(vec![ns("example.com.", "ns1.example.com.")], Trust::Authority, now);
+
+        assert_eq!(cache.flush_name("EXAMPLE.com."), 2);
+        assert_eq!(cache.flush_subtree("b.example.com."), 1);
+        assert_eq!(cache.len(), 2);
+
+        assert_eq!(cache.flush_subtree("com."), 1);
+        assert_eq!(cache.flush(), 1);
+        assert!(cache.is_empty());
+        assert_eq!(cache.stats().bytes, 0);
+    }
+
+    #[test]
+    fn caches_nxdomain_and_nodata() {
+        let cache = Cache::new(1 << 20);
+        let now = Instant::now();
+
+        let mut nxdomain = response("nope.example.com.", true, vec![], vec![soa("example.com.", 3600, 300)], vec![]);
+        nxdomain.header.rcode = ResultCode::NameError;
+        cache.insert_response(&nxdomain, "", now);
+
+        // Kept for the SOA's MINIMUM since it's lower than its TTL, whatever the type
+        let answer = cache.answer("nope.example.com.", RecordType::MX, RecordClass::IN, now + Duration::from_secs(100)).unwrap();
+        assert_eq!(answer.rcode, ResultCode::NameError);
+        assert!(answer.answers.is_empty());
+        assert_eq!(answer.authority.iter().map(|record| (record.rtype, record.ttl)).collect::<Vec<_>>(), vec![(RecordType::SOA, 200)]);
+        assert!(cache.answer("nope.example.com.", RecordType::A, RecordClass::IN, now + Duration::from_secs(300)).is_none());
+
+        // NODATA only covers the type that was asked for
+        cache.insert_response(&response(
+            "www.example.com.",
+            true,
+            vec![record("www.example.com.", 300, DNSRecordData::CNAME(DNSCNameRecord { cname: "web.example.com.".to_owned() }))],
+            vec![soa("example.com.", 60, 300)],
+            vec![],
+        ), "example.com.", now);
+
+        let answer = cache.answer("www.example.com.", RecordType::A, RecordClass::IN, now).unwrap();
+        assert_eq!(answer.rcode, ResultCode::NoError);
+        assert_eq!(answer.answers.iter().map(|record| record.rtype).collect::<Vec<_>>(), vec![RecordType::CNAME]);
+        assert_eq!(answer.authority.iter().map(|record| record.ttl).collect::<Vec<_>>(), vec![60]);
+        assert!(cache.answer("web.example.com.", RecordType::AAAA, RecordClass::IN, now).is_none());
+
+        // Nothing without an SOA to say for how long
+        let mut bare = response("gone.example.com.", true, vec![], vec![], vec![]);
+        bare.header.rcode = ResultCode::NameError;
+        cache.insert_response(&bare, "", now);
+        assert!(cache.answer("gone.example.com.", RecordType::A, RecordClass::IN, now).is_none());
+
+        // The name turned up after all
+        assert!(cache.insert(vec![a("nope.example.com.", 300, [192, 0, 2, 1])], Trust::Answer, now));
+        assert_eq!(cache.answer("nope.example.com.", RecordType::MX, RecordClass::IN, now), None);
+    }
+
+    #[test]
+    fn serves_stale_data_until_the_maximum_staleness() {
+        let cache = Cache::new(1 << 20);
+        let now = Instant::now();
+
+        cache.insert(vec![a("www.example.com.", 60, [192, 0, 2, 1])], Trust::Answer, now);
+        assert!(cache.stale_answer("www.example.com.", RecordType::A, RecordClass::IN, now + Duration::from_secs(61)).is_none());
+
+        cache.set_max_stale(Duration::from_secs(3600));
+        assert!(cache.answer("www.example.com.", RecordType::A, RecordClass::IN, now + Duration::from_secs(61)).is_none());
+
+        let answer = cache.stale_answer("www.example.com.", RecordType::A, RecordClass::IN, now + Duration::from_secs(61)).unwrap();
+        assert_eq!(answer.answers.iter().map(|record| record.ttl).collect::<Vec<_>>(), vec![30]);
+        assert!(cache.stale_answer("www.example.com.", RecordType::A, RecordClass::IN, now + Duration::from_secs(3661)).is_none());
+
+        // Stale data is no match for anything fresh
+        assert!(cache.insert(vec![a("www.example.com.", 60, [192, 0, 2, 2])], Trust::Additional, now + Duration::from_secs(61)));
+    }
+
+    #[test]
+    fn asks_for_popular_records_to_be_prefetched() {
+        let cache = Cache::new(1 << 20);
+        let now = Instant::now();
+
+        cache.insert(vec![a("www.example.com.", 100, [192, 0, 2, 1])], Trust::Answer, now);
+
+        let prefetch = |secs| cache.answer("www.example.com.", RecordType::A, RecordClass::IN, now + Duration::from_secs(secs)).unwrap().prefetch;
+
+        // Not popular enough yet, then not close enough to expiring
+        assert!(! prefetch(95));
+        assert!(! prefetch(10));
+        assert!(! prefetch(10));
+
+        // Only asked for once
+        assert!(prefetch(95));
+        assert!(! prefetch(96));
+
+        // Until it's refreshed
+        cache.insert(vec![a("www.example.com.", 100, [192, 0, 2, 1])], Trust::Answer, now + Duration::from_secs(96));
+        assert!(! prefetch(100));
+
+        // NODATA answers count as one hit each too
+        cache.insert_negative("www.example.com.", RecordType::AAAA, ResultCode::NoError, soa("example.com.", 100, 100), Trust::Answer, now);
+        let prefetch = |secs| cache.answer("www.example.com.", RecordType::AAAA, RecordClass::IN, now + Duration::from_secs(secs)).unwrap().prefetch;
+
+        assert!(! prefetch(95));
+        assert!(! prefetch(95));
+        assert!(prefetch(95));
+    }
+
+    #[test]
+    fn saves_and_loads_entries_with_their_ttls_counted_down() {
+        let cache = Cache::new(1 << 20);
+        let now = Instant::now();
+
+        cache.insert(vec![a("www.example.com.", 3600, [192, 0, 2, 1]), a("www.example.com.", 3600, [192, 0, 2, 2])], Trust::AuthoritativeAnswer, now);
+        cache.insert(vec![a("short.example.com.", 60, [192, 0, 2, 3])], Trust::Answer, now);
+        cache.insert(vec![ns("example.com.", "ns1.example.com.")], Trust::Authority, now);
+        cache.insert_negative("nope.example.com.", RecordType::A, ResultCode::NameError, soa("example.com.", 3600, 600), Trust::Answer, now);
+
+        let (data, count) = cache.dump(now + Duration::from_secs(10), 1_000_000).unwrap();
+        assert_eq!(count, 4);
+
+        // Loaded 100 seconds later, by a process whose clock started elsewhere
+        let loaded = Cache::new(1 << 20);
+        let later = Instant::now() + Duration::from_secs(5);
+        assert_eq!(loaded.restore(&data, later, 1_000_100), Ok(3));
+
+        let answer = loaded.answer("www.example.com.", RecordType::A, RecordClass::IN, later).unwrap();
+        assert_eq!(answer.answers.iter().map(|record| record.ttl).collect::<Vec<_>>(), vec![3490, 3490]);
+        assert!(loaded.answer("short.example.com.", RecordType::A, RecordClass::IN, later).is_none());
+        assert_eq!(loaded.get("example.com.", RecordType::NS, RecordClass::IN, Trust::Authority, later).map(|records| records.len()), Some(1));
+
+        let answer = loaded.answer("nope.example.com.", RecordType::A, RecordClass::IN, later).unwrap();
+        assert_eq!((answer.rcode, answer.authority[0].ttl), (ResultCode::NameError, 490));
+
+        // Trust comes back along with the records
+        assert!(! loaded.insert(vec![a("www.example.com.", 300, [203, 0, 113, 1])], Trust::Answer, later));
+
+        assert_eq!(loaded.restore(b"RDNSCACH\x00\x02", later, 0), Err("unsupported version 2".to_owned()));
+        assert_eq!(Cache::new(1 << 20).restore(&data[..data.len() - 3], later, 0), Err("truncated".to_owned()));
+        assert_eq!(loaded.restore(b"not a cache", later, 0), Err("not a cache file".to_owned()));
+    }
+
+    #[test]
+    fn only_loads_entries_that_add_up() {
+        let now = Instant::now();
+
+        let file = |name: &str, rtype: RecordType, negative: bool, ttl: u32, records: Vec<DNSRecord>| {
+            let mut data = FILE_MAGIC.to_vec();
+            data.extend_from_slice(&FILE_VERSION.to_be_bytes());
+            data.extend_from_slice(&1_000_000u64.to_be_bytes());
+            data.extend_from_slice(&1u32.to_be_bytes());
+            write_bytes(&mut data, name.as_bytes()).unwrap();
+            data.extend_from_slice(&u16::from(rtype).to_be_bytes());
+            data.extend_from_slice(&u16::from(RecordClass::IN).to_be_bytes());
+            data.push(negative as u8);
+            data.push(Trust::Answer as u8);
+            data.extend_from_slice(&ttl.to_be_bytes());
+            data.extend_from_slice(&ttl.to_be_bytes());
+            write_bytes(&mut data, &rrset_message(records).serialize().unwrap()).unwrap();
+
+            data
+        };
+
+        // TTLs are held to what they'd be cached for
+        let cache = Cache::new(1 << 20);
+        assert_eq!(cache.restore(&file("www.example.com.", RecordType::A, false, u32::MAX, vec![a("www.example.com.", 300, [192, 0, 2, 1])]), now, 1_000_000), Ok(1));
+        assert_eq!(cache.answer("www.example.com.", RecordType::A, RecordClass::IN, now).unwrap().answers[0].ttl, MAX_TTL);
+
+        let cache = Cache::new(1 << 20);
+        assert_eq!(cache.restore(&file("nope.example.com.", RecordType::ANY, true, u32::MAX, vec![soa("example.com.", 300, 300)]), now, 1_000_000), Ok(1));
+        assert_eq!(cache.answer("nope.example.com.", RecordType::A, RecordClass::IN, now).unwrap().authority[0].ttl, MAX_NEGATIVE_TTL);
+
+        // Negative entries hold an SOA and nothing else
+        let data = file("nope.example.com.", RecordType::ANY, true, 300, vec![a("nope.example.com.", 300, [192, 0, 2, 1])]);
+        assert_eq!(cache.restore(&data, now, 1_000_000), Err("negative entry for nope.example.com. without an SOA".to_owned()));
+
+        // Records have to be what the entry is kept under
+        let data = file("example.com.", RecordType::NS, false, 300, vec![a("example.com.", 300, [192, 0, 2, 1])]);
+        assert!(cache.restore(&data, now, 1_000_000).is_err());
+
+        let data = file("example.com.", RecordType::A, false, 300, vec![a("www.example.org.", 300, [192, 0, 2, 1])]);
+        assert!(cache.restore(&data, now, 1_000_000).is_err());
+
+        assert!(cache.restore(&file("example.com.", RecordType::A, false, 300, vec![]), now, 1_000_000).is_err());
+        assert!(cache.answer("example.com.", RecordType::A, RecordClass::IN, now).is_none());
+    }
+}
@@ -0,0 +1,651 @@
+use std::{fmt, fs, net::{IpAddr, SocketAddr}, str::FromStr, time::Duration};
+
+use crate::server::{cache::CacheOptions, log::LogLevel, pool::PoolOptions, root_server::ROOT_SERVERS, server::TcpOptions, toml::{self, Table, Value}};
+
+/// Port used for addresses given without one
+const DNS_PORT: u16 = 53;
+
+/// How queries outside of our own zones are answered
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    /// Resolve them ourselves, starting from the root hints
+    Recursive,
+
+    /// Hand them to the forwarders
+    Forwarding,
+
+    /// Refuse them, only our zones are served
+    Authoritative,
+}
+
+impl FromStr for Mode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "recursive" => Ok(Self::Recursive),
+            "forwarding" => Ok(Self::Forwarding),
+            "authoritative" => Ok(Self::Authoritative),
+            _ => Err(format!("Unknown mode {}, expected recursive, forwarding or authoritative", s)),
+        }
+    }
+}
+
+impl fmt::Display for Mode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Recursive => "recursive",
+            Self::Forwarding => "forwarding",
+            Self::Authoritative => "authoritative",
+        })
+    }
+}
+
+/// An address prefix like 192.0.2.0/24 or 2001:db8::/32, a bare address
+/// only matches itself
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Network {
+    addr: IpAddr,
+    prefix: u8,
+}
+
+impl Network {
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        // Clients on dual stack sockets show up as IPv4 mapped IPv6 addresses
+        let bits = |ip: IpAddr| match ip.to_canonical() {
+            IpAddr::V4(ip) => (u32::from(ip) as u128, 32),
+            IpAddr::V6(ip) => (u128::from(ip), 128),
+        };
+
+        let (network, len) = bits(self.addr);
+        let (ip, ip_len) = bits(ip);
+
+        if len != ip_len {
+            return false;
+        }
+
+        let shift = len - self.prefix as u32;
+
+        self.prefix == 0 || network >> shift == ip >> shift
+    }
+}
+
+impl FromStr for Network {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (addr, prefix) = s.split_once('/').map_or((s, None), |(addr, prefix)| (addr, Some(prefix)));
+
+        let addr = addr.parse::<IpAddr>()
+            .map_err(|_| format!("Invalid network {}", s))?
+            .to_canonical();
+        let max = if addr.is_ipv4() { 32 } else { 128 };
+
+        let prefix = match prefix {
+            Some(prefix) => prefix.parse::<u8>().ok().filter(|prefix| *prefix <= max).ok_or_else(|| format!("Invalid prefix length in {}", s))?,
+            None => max,
+        };
+
+        Ok(Self { addr, prefix })
+    }
+}
+
+impl fmt::Display for Network {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix)
+    }
+}
+
+/// Parses `192.0.2.1`, `192.0.2.1:5353`, `2001:db8::1` or `[2001:db8::1]:5353`
+pub fn parse_address(s: &str) -> Result<SocketAddr, String> {
+    s.parse::<SocketAddr>()
+        .or_else(|_| s.parse::<IpAddr>().map(|ip| SocketAddr::new(ip, DNS_PORT)))
+        .map_err(|_| format!("Invalid address {}", s))
+}
+
+/// Everything that can be set from the configuration file or the command line
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Config {
+    pub mode: Mode,
+
+    /// Addresses to serve queries on, IPv4 and IPv6 alike
+    pub listen_udp: Vec<SocketAddr>,
+    pub listen_tcp: Vec<SocketAddr>,
+
+    /// Servers recursion starts from, tried in order
+    pub root_hints: Vec<SocketAddr>,
+
+    /// Upstream resolvers used in forwarding mode, tried in order
+    pub forwarders: Vec<SocketAddr>,
+
+    pub cache: CacheOptions,
+
+    pub log_level: LogLevel,
+
+    /// Clients allowed to query us, everyone else is refused
+    pub allow: Vec<Network>,
+
+    /// Zones we're authoritative for, as origin and master file path
+    pub zones: Vec<(String, String)>,
+
+    /// BIND style key file with the TSIG keys
+    pub tsig_keys: Option<String>,
+
+    /// Files listing names that are answered with NXDOMAIN
+    pub blocklists: Vec<String>,
+
+    /// Local address the control channel listens on, it's off when unset
+    pub control: Option<SocketAddr>,
+
+    /// How long queries in flight get to finish when shutting down
+    pub shutdown_timeout: Duration,
+
+    pub tcp: TcpOptions,
+    pub pool: PoolOptions,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        let any = |addr: &str| addr.parse::<SocketAddr>().expect("Default listen addresses should be valid");
+
+        Self {
+            mode: Mode::Recursive,
+            listen_udp: vec![any("0.0.0.0:8000")],
+            listen_tcp: vec![any("0.0.0.0:8000")],
+            root_hints: ROOT_SERVERS.iter().map(|root| SocketAddr::new(IpAddr::V4(root.ipv4), DNS_PORT)).collect(),
+            forwarders: vec![],
+            cache: CacheOptions::default(),
+            log_level: LogLevel::Info,
+            allow: vec!["0.0.0.0/0".parse().expect("Should parse"), "::/0".parse().expect("Should parse")],
+            zones: vec![],
+            tsig_keys: None,
+            blocklists: vec![],
+            control: None,
+            shutdown_timeout: Duration::from_secs(10),
+            tcp: TcpOptions::default(),
+            pool: PoolOptions::default(),
+        }
+    }
+}
+
+/// Command line options, `--config` is handled separately since the file is
+/// read first and the other options override it
+const USAGE: &str = "\
+Usage: rustdns [options]
+
+  --config <path>              TOML configuration file
+  --mode <mode>                recursive, forwarding or authoritative
+  --listen <addr>              UDP and TCP address to listen on, repeatable
+  --listen-udp <addr>          UDP address to listen on, repeatable
+  --listen-tcp <addr>          TCP address to listen on, repeatable
+  --root-hint <addr>           Root server to start recursion from, repeatable
+  --forwarder <addr>           Upstream resolver for forwarding mode, repeatable
+  --cache-max-bytes <bytes>    Approximate memory the cache may take
+  --max-stale <secs>           How long expired records may be served when upstream is down, 0 turns it off
+  --client-timeout-ms <ms>     How long clients wait on upstream before getting stale records
+  --no-prefetch                Don't refresh popular records before they expire
+  --cache-file <path>          File the cache is saved to on shutdown and loaded from on startup
+  --cache-save-interval <secs> How often the cache is saved while running, 0 only saves on shutdown
+  --log-level <level>          error, warn, info or debug
+  --allow <network>            Client network allowed to query, repeatable
+  --zone <origin>=<path>       Zone to serve authoritatively, repeatable
+  --tsig-keys <path>           BIND style TSIG key file
+  --blocklist <path>           Names to answer with NXDOMAIN, repeatable
+  --control <addr>             Local address for the control channel
+  --shutdown-timeout <secs>    How long queries in flight get to finish on shutdown
+  --workers <count>            Threads resolving queries
+  --max-in-flight <count>      Queries resolving or waiting before new ones are turned away
+  --tcp-idle-timeout <secs>    Idle TCP connections are closed after this long
+  --tcp-max-connections <n>    Most TCP connections served at once
+";
+
+impl Config {
+    pub fn usage() -> &'static str {
+        USAGE
+    }
+
+    /// The configuration file given with `--config`, if any, overridden by the
+    /// rest of the command line. `args` doesn't include the program name.
+    pub fn from_args(args: &[String]) -> Result<Self, String> {
+        let mut config = match args.windows(2).find(|arg| arg[0] == "--config") {
+            Some(arg) => Self::load(&arg[1])?,
+            None => Self::default(),
+        };
+
+        config.apply_args(args)?;
+        config.validate()?;
+
+        Ok(config)
+    }
+
+    pub fn load(path: &str) -> Result<Self, String> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read config file {}, {}", path, e))?;
+
+        Self::parse(&text).map_err(|e| format!("{}: {}", path, e))
+    }
+
+    /// Parses a configuration file, anything left out keeps its default
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut root = toml::parse(text)?;
+        let mut config = Self::default();
+
+        if let Some(mode) = take_string(&mut root, "mode")? {
+            config.mode = mode.parse()?;
+        }
+
+        config.tsig_keys = take_string(&mut root, "tsig_keys")?.or(config.tsig_keys);
+
+        if let Some(secs) = take_integer(&mut root, "shutdown_timeout")? {
+            config.shutdown_timeout = Duration::from_secs(secs);
+        }
+
+        if let Some(paths) = take_strings(&mut root, "blocklists")? {
+            config.blocklists = paths;
+        }
+
+        if let Some(mut control) = take_table(&mut root, "control")? {
+            if let Some(addr) = take_string(&mut control, "listen")? {
+                config.control = Some(parse_address(&addr)?);
+            }
+
+            no_leftovers(&control, "control.")?;
+        }
+
+        if let Some(mut listen) = take_table(&mut root, "listen")? {
+            if let Some(addrs) = take_strings(&mut listen, "udp")? {
+                config.listen_udp = parse_all(&addrs, parse_address)?;
+            }
+
+            if let Some(addrs) = take_strings(&mut listen, "tcp")? {
+                config.listen_tcp = parse_all(&addrs, parse_address)?;
+            }
+
+            if let Some(secs) = take_integer(&mut listen, "tcp_idle_timeout")? {
+                config.tcp.idle_timeout = Duration::from_secs(secs);
+            }
+
+            if let Some(count) = take_integer(&mut listen, "tcp_max_connections")? {
+                config.tcp.max_connections = count as usize;
+            }
+
+            no_leftovers(&listen, "listen.")?;
+        }
+
+        if let Some(mut resolver) = take_table(&mut root, "resolver")? {
+            if let Some(addrs) = take_strings(&mut resolver, "root_hints")? {
+                config.root_hints = parse_all(&addrs, parse_address)?;
+            }
+
+            if let Some(addrs) = take_strings(&mut resolver, "forwarders")? {
+                config.forwarders = parse_all(&addrs, parse_address)?;
+            }
+
+            if let Some(count) = take_integer(&mut resolver, "workers")? {
+                config.pool.workers = count as usize;
+            }
+
+            if let Some(count) = take_integer(&mut resolver, "max_in_flight")? {
+                config.pool.max_in_flight = count as usize;
+            }
+
+            no_leftovers(&resolver, "resolver.")?;
+        }
+
+        if let Some(mut cache) = take_table(&mut root, "cache")? {
+            if let Some(bytes) = take_integer(&mut cache, "max_bytes")? {
+                config.cache.max_bytes = bytes as usize;
+            }
+
+            if let Some(secs) = take_integer(&mut cache, "max_stale")? {
+                config.cache.max_stale = Duration::from_secs(secs);
+            }
+
+            if let Some(ms) = take_integer(&mut cache, "client_timeout_ms")? {
+                config.cache.client_timeout = Duration::from_millis(ms);
+            }
+
+            config.cache.prefetch = take_boolean(&mut cache, "prefetch")?.unwrap_or(config.cache.prefetch);
+            config.cache.file = take_string(&mut cache, "file")?.or(config.cache.file);
+
+            if let Some(secs) = take_integer(&mut cache, "save_interval")? {
+                config.cache.save_interval = Duration::from_secs(secs);
+            }
+
+            no_leftovers(&cache, "cache.")?;
+        }
+
+        if let Some(mut log) = take_table(&mut root, "log")? {
+            if let Some(level) = take_string(&mut log, "level")? {
+                config.log_level = level.parse()?;
+            }
+
+            no_leftovers(&log, "log.")?;
+        }
+
+        if let Some(mut acl) = take_table(&mut root, "acl")? {
+            if let Some(networks) = take_strings(&mut acl, "allow")? {
+                config.allow = parse_all(&networks, str::parse)?;
+            }
+
+            no_leftovers(&acl, "acl.")?;
+        }
+
+        if let Some(zones) = take_table(&mut root, "zones")? {
+            for (origin, path) in zones {
+                match path {
+                    Value::String(path) => config.zones.push((origin, path)),
+                    value => return Err(format!("zones.{} should be a string, got a {}", origin, value.type_name())),
+                }
+            }
+        }
+
+        no_leftovers(&root, "")?;
+
+        Ok(config)
+    }
+
+    /// Applies command line options, repeatable ones replace whatever the
+    /// configuration file had the first time they're given
+    pub fn apply_args(&mut self, args: &[String]) -> Result<(), String> {
+        let mut replaced: Vec<&str> = vec![];
+        let mut args = args.iter();
+
+        while let Some(flag) = args.next() {
+            let mut value = || args.next().ok_or_else(|| format!("Missing value for {}", flag));
+
+            match flag.as_str() {
+                "--config" => { value()?; },
+                "--mode" => self.mode = value()?.parse()?,
+                "--listen" => {
+                    let addr = parse_address(value()?)?;
+                    reset(&mut replaced, "--listen-udp", &mut self.listen_udp);
+                    reset(&mut replaced, "--listen-tcp", &mut self.listen_tcp);
+                    self.listen_udp.push(addr);
+                    self.listen_tcp.push(addr);
+                },
+                "--listen-udp" => {
+                    let addr = parse_address(value()?)?;
+                    reset(&mut replaced, "--listen-udp", &mut self.listen_udp);
+                    self.listen_udp.push(addr);
+                },
+                "--listen-tcp" => {
+                    let addr = parse_address(value()?)?;
+                    reset(&mut replaced, "--listen-tcp", &mut self.listen_tcp);
+                    self.listen_tcp.push(addr);
+                },
+                "--root-hint" => {
+                    let addr = parse_address(value()?)?;
+                    reset(&mut replaced, "--root-hint", &mut self.root_hints);
+                    self.root_hints.push(addr);
+                },
+                "--forwarder" => {
+                    let addr = parse_address(value()?)?;
+                    reset(&mut replaced, "--forwarder", &mut self.forwarders);
+                    self.forwarders.push(addr);
+                },
+                "--cache-max-bytes" => self.cache.max_bytes = parse_number(flag, value()?)?,
+                "--max-stale" => self.cache.max_stale = Duration::from_secs(parse_number(flag, value()?)?),
+                "--client-timeout-ms" => self.cache.client_timeout = Duration::from_millis(parse_number(flag, value()?)?),
+                "--no-prefetch" => self.cache.prefetch = false,
+                "--cache-file" => self.cache.file = Some(value()?.to_owned()),
+                "--cache-save-interval" => self.cache.save_interval = Duration::from_secs(parse_number(flag, value()?)?),
+                "--log-level" => self.log_level = value()?.parse()?,
+                "--allow" => {
+                    let network = value()?.parse()?;
+                    reset(&mut replaced, "--allow", &mut self.allow);
+                    self.allow.push(network);
+                },
+                // Zones add up, unlike the other lists
+                "--zone" => {
+                    let zone = value()?;
+                    let (origin, path) = zone.split_once('=')
+                        .ok_or_else(|| format!("Zones should be given as <origin>=<path>, got {}", zone))?;
+                    self.zones.push((origin.to_owned(), path.to_owned()));
+                },
+                "--tsig-keys" => self.tsig_keys = Some(value()?.to_owned()),
+                "--blocklist" => {
+                    let path = value()?.to_owned();
+                    reset(&mut replaced, "--blocklist", &mut self.blocklists);
+                    self.blocklists.push(path);
+                },
+                "--control" => self.control = Some(parse_address(value()?)?),
+                "--shutdown-timeout" => self.shutdown_timeout = Duration::from_secs(parse_number(flag, value()?)?),
+                "--workers" => self.pool.workers = parse_number(flag, value()?)?,
+                "--max-in-flight" => self.pool.max_in_flight = parse_number(flag, value()?)?,
+                "--tcp-idle-timeout" => self.tcp.idle_timeout = Duration::from_secs(parse_number(flag, value()?)?),
+                "--tcp-max-connections" => self.tcp.max_connections = parse_number(flag, value()?)?,
+                _ => return Err(format!("Unknown option {}", flag)),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Checks that the options make sense together
+    pub fn validate(&self) -> Result<(), String> {
+        if self.listen_udp.is_empty() && self.listen_tcp.is_empty() {
+            return Err("At least one listen address is needed".to_owned());
+        }
+
+        match self.mode {
+            Mode::Recursive if self.root_hints.is_empty() => {
+                return Err("Recursive mode needs at least one root hint".to_owned());
+            },
+            Mode::Forwarding if self.forwarders.is_empty() => {
+                return Err("Forwarding mode needs at least one forwarder".to_owned());
+            },
+            Mode::Authoritative if self.zones.is_empty() => {
+                return Err("Authoritative mode needs at least one zone".to_owned());
+            },
+            _ => {},
+        }
+
+        if self.pool.workers == 0 {
+            return Err("At least one worker is needed".to_owned());
+        }
+
+        if self.pool.max_in_flight < self.pool.workers {
+            return Err(format!("max_in_flight ({}) can't be lower than the number of workers ({})", self.pool.max_in_flight, self.pool.workers));
+        }
+
+        if self.tcp.idle_timeout.is_zero() {
+            return Err("The TCP idle timeout can't be 0".to_owned());
+        }
+
+        // Anyone who can reach it can reload the server
+        if let Some(addr) = self.control.filter(|addr| ! addr.ip().is_loopback()) {
+            return Err(format!("The control channel has to listen on a loopback address, got {}", addr));
+        }
+
+        Ok(())
+    }
+
+    /// Whether `ip` may query us
+    pub fn allows(&self, ip: IpAddr) -> bool {
+        self.allow.iter().any(|network| network.contains(ip))
+    }
+}
+
+/// Lists given on the command line start over rather than add to the file's
+fn reset<T>(replaced: &mut Vec<&'static str>, flag: &'static str, list: &mut Vec<T>) {
+    if ! replaced.contains(&flag) {
+        replaced.push(flag);
+        list.clear();
+    }
+}
+
+fn parse_number<T: FromStr>(flag: &str, value: &str) -> Result<T, String> {
+    value.parse().map_err(|_| format!("Invalid number {} for {}", value, flag))
+}
+
+fn parse_all<T>(values: &[String], parse: impl Fn(&str) -> Result<T, String>) -> Result<Vec<T>, String> {
+    values.iter().map(|value| parse(value)).collect()
+}
+
+fn take_string(table: &mut Table, key: &str) -> Result<Option<String>, String> {
+    match table.remove(key) {
+        None => Ok(None),
+        Some(Value::String(value)) => Ok(Some(value)),
+        Some(value) => Err(format!("{} should be a string, got a {}", key, value.type_name())),
+    }
+}
+
+fn take_integer(table: &mut Table, key: &str) -> Result<Option<u64>, String> {
+    match table.remove(key) {
+        None => Ok(None),
+        Some(Value::Integer(value)) => u64::try_from(value)
+            .map(Some)
+            .map_err(|_| format!("{} can't be negative", key)),
+        Some(value) => Err(format!("{} should be an integer, got a {}", key, value.type_name())),
+    }
+}
+
+fn take_boolean(table: &mut Table, key: &str) -> Result<Option<bool>, String> {
+    match table.remove(key) {
+        None => Ok(None),
+        Some(Value::Boolean(value)) => Ok(Some(value)),
+        Some(value) => Err(format!("{} should be a boolean, got a {}", key, value.type_name())),
+    }
+}
+
+fn take_strings(table: &mut Table, key: &str) -> Result<Option<Vec<String>>, String> {
+    match table.remove(key) {
+        None => Ok(None),
+        Some(Value::Array(values)) => values
+            .into_iter()
+            .map(|value| match value {
+                Value::String(value) => Ok(value),
+                value => Err(format!("{} should only hold strings, got a {}", key, value.type_name())),
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .map(Some),
+        Some(value) => Err(format!("{} should be an array, got a {}", key, value.type_name())),
+    }
+}
+
+fn take_table(table: &mut Table, key: &str) -> Result<Option<Table>, String> {
+    match table.remove(key) {
+        None => Ok(None),
+        Some(Value::Table(value)) => Ok(Some(value)),
+        Some(value) => Err(format!("{} should be a table, got a {}", key, value.type_name())),
+    }
+}
+
+/// Typos shouldn't be silently ignored
+fn no_leftovers(table: &Table, prefix: &str) -> Result<(), String> {
+    match table.keys().next() {
+        Some(key) => Err(format!("Unknown option {}{}", prefix, key)),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::SocketAddr, time::Duration};
+
+    use crate::server::log::LogLevel;
+
+    use super::{Config, Mode, Network};
+
+    fn args(args: &str) -> Vec<String> {
+        args.split_whitespace().map(str::to_owned).collect()
+    }
+
+    fn addr(addr: &str) -> SocketAddr {
+        addr.parse().unwrap()
+    }
+
+    #[test]
+    fn parses_config_files() {
+        let config = Config::parse(r#"
+mode = "forwarding"
+blocklists = ["ads.txt"]
+shutdown_timeout = 3
+
+[control]
+listen = "[::1]:8053"
+
+[listen]
+udp = ["127.0.0.1:5353", "::1"]
+tcp = ["[::]:5353"]
+tcp_idle_timeout = 30
+
+[resolver]
+forwarders = ["192.0.2.53"]
+workers = 4
+max_in_flight = 64
+
+[cache]
+max_bytes = 500000
+max_stale = 3600
+client_timeout_ms = 500
+prefetch = false
+file = "/var/cache/rustdns/cache.bin"
+save_interval = 300
+
+[log]
+level = "debug"
+
+[acl]
+allow = ["10.0.0.0/8", "2001:db8::/32"]
+
+[zones]
+"example.com." = "db.example"
+"#).unwrap();
+
+        assert_eq!(config.mode, Mode::Forwarding);
+        assert_eq!(config.listen_udp, vec![addr("127.0.0.1:5353"), addr("[::1]:53")]);
+        assert_eq!(config.listen_tcp, vec![addr("[::]:5353")]);
+        assert_eq!(config.tcp.idle_timeout, Duration::from_secs(30));
+        assert_eq!(config.forwarders, vec![addr("192.0.2.53:53")]);
+        assert_eq!((config.pool.workers, config.pool.max_in_flight), (4, 64));
+        assert_eq!(config.cache.max_bytes, 500_000);
+        assert_eq!((config.cache.max_stale, config.cache.client_timeout, config.cache.prefetch), (Duration::from_secs(3600), Duration::from_millis(500), false));
+        assert_eq!((config.cache.file.as_deref(), config.cache.save_interval), (Some("/var/cache/rustdns/cache.bin"), Duration::from_secs(300)));
+        assert_eq!(config.log_level, LogLevel::Debug);
+        assert_eq!(config.zones, vec![("example.com.".to_owned(), "db.example".to_owned())]);
+        assert_eq!(config.blocklists, vec!["ads.txt".to_owned()]);
+        assert_eq!(config.control, Some(addr("[::1]:8053")));
+        assert_eq!(config.shutdown_timeout, Duration::from_secs(3));
+        assert!(config.allows("10.1.2.3".parse().unwrap()));
+        assert!(config.allows("::ffff:10.1.2.3".parse().unwrap()));
+        assert!(config.allows("2001:db8::1".parse().unwrap()));
+        assert!(! config.allows("192.0.2.1".parse().unwrap()));
+        assert_eq!(config.validate(), Ok(()));
+
+        assert_eq!(Config::parse("[listen]\nudp = \"0.0.0.0\""), Err("udp should be an array, got a string".to_owned()));
+        assert_eq!(Config::parse("[cache]\nsise = 10"), Err("Unknown option cache.sise".to_owned()));
+        assert_eq!(Config::parse("[cache]\nprefetch = \"yes\""), Err("prefetch should be a boolean, got a string".to_owned()));
+        assert_eq!(Config::parse("mode = \"caching\""), Err("Unknown mode caching, expected recursive, forwarding or authoritative".to_owned()));
+    }
+
+    #[test]
+    fn command_line_overrides_the_file() {
+        let mut config = Config::parse("[acl]\nallow = [\"10.0.0.0/8\"]\n[zones]\n\"a.example.\" = \"db.a\"").unwrap();
+
+        config.apply_args(&args("--listen 127.0.0.1:53 --listen ::1 --allow 192.0.2.0/24 --zone b.example.=db.b --log-level warn --no-prefetch --max-stale 0")).unwrap();
+
+        assert_eq!(config.listen_udp, vec![addr("127.0.0.1:53"), addr("[::1]:53")]);
+        assert_eq!(config.listen_tcp, config.listen_udp);
+        assert_eq!(config.allow, vec!["192.0.2.0/24".parse::<Network>().unwrap()]);
+        assert_eq!(config.zones.len(), 2);
+        assert_eq!(config.log_level, LogLevel::Warn);
+        assert!(! config.cache.prefetch);
+        assert!(config.cache.max_stale.is_zero());
+
+        assert_eq!(config.apply_args(&args("--cache-max-bytes lots")), Err("Invalid number lots for --cache-max-bytes".to_owned()));
+        assert_eq!(config.apply_args(&args("--verbose")), Err("Unknown option --verbose".to_owned()));
+        assert_eq!(config.apply_args(&args("--mode")), Err("Missing value for --mode".to_owned()));
+    }
+
+    #[test]
+    fn validates_configs() {
+        assert_eq!(Config::from_args(&args("--mode forwarding")), Err("Forwarding mode needs at least one forwarder".to_owned()));
+        assert_eq!(Config::from_args(&args("--mode authoritative")), Err("Authoritative mode needs at least one zone".to_owned()));
+        assert_eq!(Config::from_args(&args("--control 0.0.0.0:8053")), Err("The control channel has to listen on a loopback address, got 0.0.0.0:8053".to_owned()));
+        assert_eq!(Config::from_args(&args("--workers 8 --max-in-flight 4")), Err("max_in_flight (4) can't be lower than the number of workers (8)".to_owned()));
+        assert!(Config::from_args(&args("--config /nonexistent/rustdns.toml")).unwrap_err().starts_with("Failed to read config file"));
+        assert_eq!(Config::from_args(&[]), Ok(Config::default()));
+    }
+}
@@ -0,0 +1,196 @@
+use std::{io::{BufRead, BufReader, Write}, net::{TcpListener, TcpStream}, thread, time::Duration};
+
+use crate::{parser::zone_file::normalize_origin, server::{log::log, server::{accept, SharedState}, signal::{self, Signal}}};
+
+/// How long a control client has to send its command
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How often pending signals are looked at
+const SIGNAL_POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Serves the control channel until the server stops. Each connection sends a single command
+/// line and gets back `ok` or `error: <reason>`, followed by the command's
+/// output if it has any.
+///
+/// ```text
+/// $ echo reload | nc 127.0.0.1 8053
+/// ok
+/// ```
+pub fn serve_control(listener: &TcpListener, shared: &SharedState) {
+    if let Err(e) = listener.set_nonblocking(true) {
+        log!(Error, "Failed to set up the control channel, {}", e);
+        return;
+    }
+
+    while let Some(stream) = accept(listener, shared) {
+        let result = stream
+            .map_err(|e| format!("Failed to accept control connection, {}", e))
+            .and_then(|stream| handle_control(stream, shared));
+
+        if let Err(e) = result {
+            log!(Warn, "{}", e);
+        }
+    }
+}
+
+fn handle_control(stream: TcpStream, shared: &SharedState) -> Result<(), String> {
+    stream.set_read_timeout(Some(COMMAND_TIMEOUT))
+        .and_then(|_| stream.set_write_timeout(Some(COMMAND_TIMEOUT)))
+        .map_err(|e| format!("Failed to set control connection timeouts, {}", e))?;
+
+    let mut line = String::new();
+    BufReader::new(&stream).read_line(&mut line)
+        .map_err(|e| format!("Failed to read control command, {}", e))?;
+
+    let reply = match run_command(line.trim(), shared) {
+        Ok(output) => format!("ok\n{}", output),
+        Err(e) => format!("error: {}\n", e),
+    };
+
+    (&stream).write_all(reply.as_bytes())
+        .map_err(|e| format!("Failed to reply to control command, {}", e))
+}
+
+/// Runs one control command, returning its output:
+///
+/// - `reload` and `stop`
+/// - `stats`, what the cache holds and its hit, miss and eviction counts
+/// - `flush`, `flush <name>` and `flush-tree <name>`, to drop the whole
+///   cache, what's cached at a name, or at a name and below it
+pub fn run_command(command: &str, shared: &SharedState) -> Result<String, String> {
+    log!(Info, "Control command {:?}", command);
+
+    let (command, name) = match command.split_once(char::is_whitespace) {
+        Some((command, name)) => (command, Some(normalize_origin(name.trim()))),
+        None => (command, None),
+    };
+    let cache = &shared.load().cache;
+
+    match (command, name) {
+        ("reload", None) => shared.reload().map(|_| String::new()),
+        ("stop", None) => {
+            shared.stop();
+            Ok(String::new())
+        },
+        ("stats", None) => {
+            let stats = cache.stats();
+
+            Ok(format!(
+                "entries {}\nbytes {}\nmax_bytes {}\nhits {}\nmisses {}\nevictions {}\n",
+                stats.entries, stats.bytes, stats.max_bytes, stats.hits, stats.misses, stats.evictions,
+            ))
+        },
+        ("flush", None) => Ok(format!("flushed {}\n", cache.flush())),
+        ("flush", Some(name)) => Ok(format!("flushed {}\n", cache.flush_name(&name))),
+        ("flush-tree", Some(name)) => Ok(format!("flushed {}\n", cache.flush_subtree(&name))),
+        ("flush-tree", None) => Err("flush-tree needs a name".to_owned()),
+        ("reload" | "stop" | "stats", Some(_)) => Err(format!("{} doesn't take a name", command)),
+        ("", _) => Err("Missing command".to_owned()),
+        (command, _) => Err(format!("Unknown command {}", command)),
+    }
+}
+
+/// Reloads on SIGHUP, and stops the server on SIGINT or SIGTERM. Returns once
+/// the server is stopping, whatever stopped it.
+pub fn handle_signals(shared: &SharedState) {
+    if let Err(e) = signal::catch(&[Signal::Hangup, Signal::Interrupt, Signal::Terminate]) {
+        log!(Warn, "{}, the server can only be controlled through the control channel", e);
+        return;
+    }
+
+    while ! shared.is_stopping() {
+        thread::sleep(SIGNAL_POLL_INTERVAL);
+
+        if signal::take(Signal::Interrupt) || signal::take(Signal::Terminate) {
+            log!(Info, "Shutting down");
+            shared.stop();
+        }
+
+        if signal::take(Signal::Hangup) {
+            log!(Info, "Got SIGHUP, reloading");
+
+            if let Err(e) = shared.reload() {
+                log!(Error, "Reload failed, keeping the current configuration, {}", e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, io::{Read, Write}, net::{SocketAddr, TcpListener, TcpStream}, process, sync::Arc, thread, time::Instant};
+
+    use crate::{parser::{record::{DNSARecord, DNSRecord, DNSRecordData}, types::{RecordClass, RecordType}}, server::{cache::Trust, server::{ServerState, SharedState}}};
+
+    use super::serve_control;
+
+    fn send(addr: SocketAddr, command: &str) -> String {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream.write_all(command.as_bytes()).unwrap();
+
+        let mut reply = String::new();
+        stream.read_to_string(&mut reply).unwrap();
+
+        reply
+    }
+
+    #[test]
+    fn reloads_through_the_control_channel() {
+        let dir = env::temp_dir().join(format!("rustdns-control-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let blocklist = dir.join("blocked.txt");
+        let config = dir.join("rustdns.toml");
+        fs::write(&blocklist, "ads.example.com\n").unwrap();
+        fs::write(&config, format!("blocklists = [{:?}]\n", blocklist.to_str().unwrap())).unwrap();
+
+        let args = vec!["--config".to_owned(), config.to_str().unwrap().to_owned()];
+        let shared = Arc::new(SharedState::new(ServerState::default(), args));
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        // The listener never returns, it's left running until the tests are done
+        let control_shared = Arc::clone(&shared);
+        thread::spawn(move || serve_control(&listener, &control_shared));
+
+        assert!(! shared.load().blocklist.blocks("ads.example.com."));
+
+        assert_eq!(send(addr, "reload\n"), "ok\n");
+        assert!(shared.load().blocklist.blocks("ads.example.com."));
+
+        // A broken config leaves the running one alone
+        fs::write(&config, "mode = \"forwarding\"\n").unwrap();
+        assert_eq!(send(addr, "reload\n"), "error: Forwarding mode needs at least one forwarder\n");
+        assert!(shared.load().blocklist.blocks("ads.example.com."));
+
+        assert_eq!(send(addr, "restart\n"), "error: Unknown command restart\n");
+
+        let record = |name: &str| DNSRecord {
+            name: name.to_owned(),
+            rtype: RecordType::A,
+            class: RecordClass::IN,
+            ttl: 300,
+            len: 0,
+            record: DNSRecordData::A(DNSARecord { ip: [192, 0, 2, 1] }),
+        };
+
+        let cache = &shared.load().cache;
+        cache.set_max_bytes(1 << 20);
+
+        for name in ["example.com.", "www.example.com.", "example.org."] {
+            cache.insert(vec![record(name)], Trust::Answer, Instant::now());
+        }
+
+        assert!(send(addr, "stats\n").starts_with("ok\nentries 3\n"));
+        assert_eq!(send(addr, "flush www.example.com\n"), "ok\nflushed 1\n");
+        assert_eq!(send(addr, "flush-tree com\n"), "ok\nflushed 1\n");
+        assert_eq!(send(addr, "flush-tree\n"), "error: flush-tree needs a name\n");
+        assert_eq!(send(addr, "flush\n"), "ok\nflushed 1\n");
+
+        assert_eq!(send(addr, "stop\n"), "ok\n");
+        assert!(shared.is_stopping());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,56 @@
+use std::{fmt, str::FromStr, sync::atomic::{AtomicU8, Ordering}};
+
+/// How much the server writes to stderr, each level includes the ones before it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LogLevel {
+    Error,
+    Warn,
+    Info,
+    Debug,
+}
+
+impl FromStr for LogLevel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "error" => Ok(Self::Error),
+            "warn" => Ok(Self::Warn),
+            "info" => Ok(Self::Info),
+            "debug" => Ok(Self::Debug),
+            _ => Err(format!("Unknown log level {}, expected error, warn, info or debug", s)),
+        }
+    }
+}
+
+impl fmt::Display for LogLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Error => "error",
+            Self::Warn => "warn",
+            Self::Info => "info",
+            Self::Debug => "debug",
+        })
+    }
+}
+
+static LEVEL: AtomicU8 = AtomicU8::new(LogLevel::Info as u8);
+
+pub fn set_level(level: LogLevel) {
+    LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+pub fn enabled(level: LogLevel) -> bool {
+    level as u8 <= LEVEL.load(Ordering::Relaxed)
+}
+
+/// `log!(Warn, "format", args..)` writes the message if `Warn` is enabled
+macro_rules! log {
+    ($level:ident, $($arg:tt)*) => {
+        if $crate::server::log::enabled($crate::server::log::LogLevel::$level) {
+            eprintln!("[{}] {}", $crate::server::log::LogLevel::$level, format_args!($($arg)*));
+        }
+    };
+}
+
+pub(crate) use log;
@@ -1,22 +1,63 @@
-use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4, UdpSocket};
-use std::fs;
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant, SystemTime};
 
-use crate::parser::{header::{DNSHeader, DNSHeaderType, ResultCode}, packet::{DNSPacket, DNSPacketParser}, question::DNSQuestion};
+use crate::parser::DomainNameLabel;
+use crate::server::{cache::{AnswerChain, Cache}, log::log, zone::{is_subdomain, names_eq}};
+
+use crate::parser::{header::{DNSHeader, DNSHeaderType, Opcode, ResultCode}, packet::DNSPacket, question::DNSQuestion, types::{RecordClass, RecordType}, view::DNSPacketView};
 use crate::parser::record::{DNSARecord, DNSRecordData, DNSRecordPack};
 
+/// How long we wait on an upstream server before giving up on it
+const UPSTREAM_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Port name servers we're referred to are asked on
+const DNS_PORT: u16 = 53;
+
+/// Most referrals followed for one name, guards against delegation loops
+const MAX_REFERRALS: usize = 16;
+
+/// How deep looking up name servers that came without glue, and aliases
+/// pointing outside of the zone they're in, may nest
+const MAX_NS_DEPTH: usize = 4;
+
+/// A fresh transaction ID for every query, so that answers can't be
+/// guessed by someone who saw an earlier one
+fn query_id() -> u16 {
+    static QUERIES: AtomicU64 = AtomicU64::new(0);
+
+    // Hashers are keyed randomly, that's all the randomness we need
+    RandomState::new().hash_one((QUERIES.fetch_add(1, Ordering::Relaxed), SystemTime::now())) as u16
+}
+
+/// `cd` is forwarded as the checking disabled bit so that upstream validating
+/// servers hand back data even if it fails DNSSEC validation
+pub fn lookup(server: SocketAddr, qname: &str, qtype: RecordType, cd: bool) -> Result<DNSPacket, String> {
+    // A port of its own for every lookup, many of them run at the same time,
+    // in the address family of the server
+    let local: SocketAddr = match server {
+        SocketAddr::V4(_) => (Ipv4Addr::UNSPECIFIED, 0).into(),
+        SocketAddr::V6(_) => (Ipv6Addr::UNSPECIFIED, 0).into(),
+    };
 
-pub fn lookup(server: SocketAddr, qname: &str, qtype: u16) -> Result<DNSPacket, String> {
-    let socket = UdpSocket::bind("0.0.0.0:50000").expect("Should bind socket");
+    let socket = UdpSocket::bind(local)
+        .map_err(|e| format!("Failed to bind lookup socket, {}", e))?;
+    socket.set_read_timeout(Some(UPSTREAM_TIMEOUT))
+        .map_err(|e| format!("Failed to set lookup timeout, {}", e))?;
 
     let header = DNSHeader {
-        id: 6666,
+        id: query_id(),
         qr: DNSHeaderType::Query,
-        opcode: 0,
+        opcode: Opcode::Query,
         aa: false,
         tc: false,
         rd: true,
         ra: false,
         z: 0,
+        ad: false,
+        cd,
         rcode: ResultCode::NoError,
         qdcount: 1,
         ancount: 0,
@@ -27,7 +68,7 @@ pub fn lookup(server: SocketAddr, qname: &str, qtype: u16) -> Result<DNSPacket,
     let questions: Vec<DNSQuestion> = vec![
         DNSQuestion {
             name: qname.to_string(),
-            class: 1,
+            class: RecordClass::IN,
             rtype: qtype,
         },
     ];
@@ -39,99 +80,204 @@ pub fn lookup(server: SocketAddr, qname: &str, qtype: u16) -> Result<DNSPacket,
         authority: vec![],
         additional: vec![],
     };
-    // println!("QUERY PACKET BIN: {:02x?}", query_packet.serialize().unwrap());
-    
+
     socket.connect(server).map_err(|e| format!("Socket failed to connect to {}, {}", server, e))?;
     socket.send(&query_packet.serialize()?)
         .map_err(|e| format!("Failed to send packet to {}, {}", server, e))?;
-    // println!("Query was sent");
 
     let mut res_buffer = [0u8; 66_000];
     let bytes_received = socket.recv(&mut res_buffer)
         .map_err(|e| format!("Failed to receive response from {}, {}", server, e))?;
-    
-    fs::write("./their_response", &res_buffer[0..bytes_received])
-        .expect("Should write their response");
-
-    // println!("Bytes received {:?}", bytes_received);
-    let resp_packet = DNSPacketParser::new(&res_buffer[0..bytes_received]).parse()?;
-    
-    Ok(resp_packet)
+
+    // Check that this is the answer to our query before decoding all of it
+    let view = DNSPacketView::new(&res_buffer[0..bytes_received])?;
+
+    let answers_question = match view.questions().next() {
+        Some(question) => {
+            let question = question?;
+            question.rtype == qtype && question.name.eq_name(qname)?
+        },
+        None => false,
+    };
+
+    if view.id() != query_packet.header.id || view.qr() != DNSHeaderType::Response || ! answers_question {
+        return Err(format!("Received a packet from {} that doesn't answer our query", server));
+    }
+
+    view.to_packet()
 }
 
-pub fn lookup_recursively(qname: &str, qtype: u16) -> Result<DNSPacket, String> {
-    let mut server = SocketAddr::V4(
-        SocketAddrV4::new(Ipv4Addr::new(192, 203, 230, 10), 53),
-    );
+/// Asks each of `servers` in turn until one of them answers, used for
+/// forwarding as well as for picking a root server
+pub fn forward(servers: &[SocketAddr], qname: &str, qtype: RecordType, cd: bool) -> Result<DNSPacket, String> {
+    let mut error = "No servers to ask".to_owned();
 
-    loop {
-        let resp = lookup(server, qname, qtype)?;
-        
-        // We got our answers, we're done
-        if ! resp.answers.is_empty() {
-            return Ok(resp);
+    for server in servers {
+        match lookup(*server, qname, qtype, cd) {
+            Ok(resp) => return Ok(resp),
+            Err(e) => {
+                log!(Debug, "{}", e);
+                error = e;
+            },
         }
+    }
 
-        let ns_option = resp.authority
-            .iter()
-            .filter_map(|x| {
-                if ! qname.ends_with(&x.name) {
-                    return None;
-                }
+    Err(error)
+}
 
-                match x.record {
-                    DNSRecordData::NS(ref ns) => Some(ns.nsdname.clone()),
-                    _ => None,
-                }
-            })
-            .nth(0);
+/// Follows referrals down from the closest delegation in `cache`, or from
+/// `roots` when there's none, until some server answers. Everything learned
+/// along the way is cached.
+pub fn lookup_recursively(roots: &[SocketAddr], cache: &Cache, qname: &str, qtype: RecordType, cd: bool) -> Result<DNSPacket, String> {
+    resolve_from(roots, cache, qname, qtype, cd, 0)
+}
+
+fn resolve_from(roots: &[SocketAddr], cache: &Cache, qname: &str, qtype: RecordType, cd: bool, depth: usize) -> Result<DNSPacket, String> {
+    if depth > MAX_NS_DEPTH {
+        return Err(format!("Gave up on {}, too many name servers without glue or aliases", qname));
+    }
 
-        let ns_domain = match ns_option {
-            None => return Ok(resp),
-            Some(name) => name,
+    // The zone the servers we ask are authoritative for, they're only
+    // believed about name servers at or below it
+    let (mut zone, mut servers) = match cache.delegation(qname, Instant::now()) {
+        Some((zone, addresses)) => {
+            log!(Debug, "Starting {} at cached delegation {}", qname, DomainNameLabel::presentation(&zone));
+            (zone, to_servers(addresses))
+        },
+        None => (String::new(), roots.to_vec()),
+    };
+
+    for _ in 0..MAX_REFERRALS {
+        let resp = forward(&servers, qname, qtype, cd)?;
+        cache.insert_response(&resp, &zone, Instant::now());
+
+        // We got our answers, or the name doesn't exist, either way we're done
+        if ! resp.answers.is_empty() || resp.header.rcode != ResultCode::NoError {
+            return follow_chain(roots, cache, resp, &zone, qtype, cd, depth);
+        }
+
+        // Only a zone below the one asked is progress, a server naming
+        // itself would have us ask it again and again
+        let Some(referral) = resp.authority
+            .iter()
+            .find(|x| x.rtype == RecordType::NS && is_subdomain(qname, &x.name) && is_subdomain(&x.name, &zone) && ! names_eq(&x.name, &zone))
+            .map(|x| x.name.clone()) else {
+            return Ok(resp);
         };
 
-        println!("SERVER: {:?}", ns_domain);
+        let ns_domains = resp.authority
+            .iter()
+            .filter(|x| names_eq(&x.name, &referral))
+            .filter_map(|x| match x.record {
+                DNSRecordData::NS(ref ns) => Some(ns.nsdname.clone()),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+
+        log!(Debug, "Referred to {:?} for {}", ns_domains, qname);
 
-        // Try to find it's ip in additional
-        let ip_option = resp.additional
+        // Glue from the additional section, or addresses we already know
+        let mut addresses = resp.additional
             .iter()
-            .filter_map(|x| {
-                if x.name != ns_domain {
-                    return None;
-                }
+            .filter(|x| is_subdomain(&x.name, &zone) && ns_domains.iter().any(|ns| names_eq(ns, &x.name)))
+            .filter_map(|x| match x.record {
+                DNSRecordData::A(ref rec) => Some(IpAddr::V4(Ipv4Addr::from(rec.ip))),
+                _ => None,
+            })
+            .chain(ns_domains.iter().flat_map(|ns| cache.addresses(ns, Instant::now())))
+            .collect::<Vec<_>>();
 
-                match x.record {
-                    DNSRecordData::A(ref rec) => Some(rec.ip),
+        // No glue, the name servers have to be looked up on their own
+        for ns_domain in &ns_domains {
+            if ! addresses.is_empty() {
+                break;
+            }
+
+            match resolve_from(roots, cache, ns_domain, DNSARecord::RTYPE, cd, depth + 1) {
+                Ok(ns_resp) => addresses.extend(ns_resp.answers.iter().filter_map(|x| match x.record {
+                    DNSRecordData::A(ref rec) => Some(IpAddr::V4(Ipv4Addr::from(rec.ip))),
                     _ => None,
-                }
-            }).nth(0);
-
-        let ip = match ip_option {
-            Some(ip) => ip,
-            None => {
-                let resp = lookup_recursively(&ns_domain, DNSARecord::RTYPE)?;
-                let ip_option = resp.answers
-                    .iter()
-                    .filter_map(|x| {
-                        match x.record {
-                            DNSRecordData::A(ref rec) => Some(rec.ip),
-                            _ => None,
-                        }
-                    }).nth(0);
-
-                match ip_option {
-                    Some(ip) => ip,
-                    None => return Ok(resp),
-                }
-            },
-        };
+                })),
+                Err(e) => log!(Debug, "Failed to resolve name server {}, {}", ns_domain, e),
+            }
+        }
+
+        if addresses.is_empty() {
+            return Ok(resp);
+        }
+
+        zone = referral;
+        servers = to_servers(addresses);
+    }
+
+    Err(format!("Gave up on {}, too many referrals", qname))
+}
+
+/// Keeps only the answers the servers of `zone` can be believed about. When
+/// their CNAME chain leads outside of the zone, where it ends is looked up
+/// on its own and the answers are put together.
+fn follow_chain(roots: &[SocketAddr], cache: &Cache, mut resp: DNSPacket, zone: &str, qtype: RecordType, cd: bool, depth: usize) -> Result<DNSPacket, String> {
+    let chain = AnswerChain::new(&resp, zone);
+    let mut answers = chain.records.into_iter().cloned().collect::<Vec<_>>();
+
+    if ! chain.left_zone {
+        resp.answers = answers;
+
+        return Ok(resp);
+    }
 
-        server = SocketAddr::V4(
-            SocketAddrV4::new(
-                Ipv4Addr::new(ip[0], ip[1], ip[2], ip[3]),
-                53,
-            ),
-        );
+    log!(Debug, "Following {} outside of {}", chain.target, DomainNameLabel::presentation(zone));
+
+    let mut target_resp = resolve_from(roots, cache, &chain.target, qtype, cd, depth + 1)?;
+    answers.append(&mut target_resp.answers);
+    target_resp.answers = answers;
+    target_resp.questions = resp.questions;
+
+    Ok(target_resp)
+}
+
+fn to_servers(addresses: Vec<IpAddr>) -> Vec<SocketAddr> {
+    addresses.into_iter().map(|ip| SocketAddr::new(ip, DNS_PORT)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::UdpSocket, thread};
+
+    use crate::parser::types::RecordType;
+
+    use super::lookup;
+
+    /// Answers one query on `socket` with an empty response
+    fn answer_once(socket: UdpSocket) -> thread::JoinHandle<()> {
+        thread::spawn(move || {
+            let mut buffer = [0u8; 512];
+            let (len, client) = socket.recv_from(&mut buffer).unwrap();
+
+            // QR set, nothing else changes
+            buffer[2] |= 0x80;
+            socket.send_to(&buffer[..len], client).unwrap();
+        })
+    }
+
+    #[test]
+    fn asks_ipv4_and_ipv6_servers() {
+        let mut servers = vec![UdpSocket::bind("127.0.0.1:0").unwrap()];
+
+        // Not every machine has IPv6
+        match UdpSocket::bind("[::1]:0") {
+            Ok(socket) => servers.push(socket),
+            Err(e) => eprintln!("Skipping IPv6, {}", e),
+        }
+
+        for socket in servers {
+            let server = socket.local_addr().unwrap();
+            let handle = answer_once(socket);
+
+            let resp = lookup(server, "example.com.", RecordType::A, false).unwrap();
+            assert_eq!(resp.questions[0].name, "example.com.");
+
+            handle.join().unwrap();
+        }
     }
 }
@@ -1,3 +1,16 @@
 pub mod lookup;
+#[allow(clippy::module_inception)]
 pub mod server;
 pub mod root_server;
+pub mod zone;
+pub mod update;
+pub mod tsig;
+pub mod pool;
+pub mod toml;
+pub mod log;
+pub mod config;
+pub mod cache;
+pub mod refresh;
+pub mod blocklist;
+pub mod signal;
+pub mod control;
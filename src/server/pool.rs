@@ -0,0 +1,197 @@
+use std::{panic::{self, AssertUnwindSafe}, sync::{atomic::{AtomicUsize, Ordering}, mpsc, Arc, Mutex}, thread::{self, JoinHandle}, time::{Duration, Instant}};
+
+use crate::server::log::log;
+
+type Job = Box<dyn FnOnce() + Send + 'static>;
+
+/// Size of the worker pool
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PoolOptions {
+    /// Threads resolving queries, recursion mostly waits on the network so
+    /// this can be well above the number of cores
+    pub workers: usize,
+
+    /// Queries being resolved or waiting for a worker, anything over this is
+    /// turned away instead of queued
+    pub max_in_flight: usize,
+}
+
+impl Default for PoolOptions {
+    fn default() -> Self {
+        Self {
+            workers: 32,
+            max_in_flight: 1024,
+        }
+    }
+}
+
+/// Frees an in flight slot once its job is done, even if it panicked
+struct InFlightSlot(Arc<AtomicUsize>);
+
+impl Drop for InFlightSlot {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Fixed set of threads running queries in parallel
+///
+/// Dropping the pool lets the workers finish whatever was already handed to
+/// them and waits for them to exit.
+pub struct WorkerPool {
+    sender: Option<mpsc::Sender<Job>>,
+    workers: Vec<JoinHandle<()>>,
+    in_flight: Arc<AtomicUsize>,
+    max_in_flight: usize,
+}
+
+impl WorkerPool {
+    pub fn new(options: PoolOptions) -> Self {
+        let (sender, receiver) = mpsc::channel::<Job>();
+        let receiver = Arc::new(Mutex::new(receiver));
+
+        let workers = (0..options.workers.max(1))
+            .map(|_| {
+                let receiver = Arc::clone(&receiver);
+
+                thread::spawn(move || loop {
+                    // The lock is released before running the job
+                    let job = receiver.lock().unwrap_or_else(|e| e.into_inner()).recv();
+
+                    match job {
+                        Ok(job) => {
+                            // A bad query shouldn't take a worker down with it
+                            if panic::catch_unwind(AssertUnwindSafe(job)).is_err() {
+                                log!(Warn, "Worker job panicked");
+                            }
+                        },
+                        // The pool is gone
+                        Err(_) => return,
+                    }
+                })
+            })
+            .collect();
+
+        Self {
+            sender: Some(sender),
+            workers,
+            in_flight: Arc::new(AtomicUsize::new(0)),
+            max_in_flight: options.max_in_flight,
+        }
+    }
+
+    /// Hands `job` to a worker, fails right away when too many jobs are
+    /// already in flight
+    pub fn execute(&self, job: impl FnOnce() + Send + 'static) -> Result<(), String> {
+        if self.in_flight.fetch_add(1, Ordering::SeqCst) >= self.max_in_flight {
+            self.in_flight.fetch_sub(1, Ordering::SeqCst);
+
+            return Err(format!("Too many queries in flight ({})", self.max_in_flight));
+        }
+
+        let slot = InFlightSlot(Arc::clone(&self.in_flight));
+
+        self.sender
+            .as_ref()
+            .ok_or_else(|| "Worker pool is shut down".to_owned())?
+            .send(Box::new(move || {
+                let _slot = slot;
+                job();
+            }))
+            .map_err(|_| "Worker pool is shut down".to_owned())
+    }
+
+    /// Jobs waiting for a worker or running
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.load(Ordering::SeqCst)
+    }
+
+    /// Waits up to `timeout` for the jobs in flight to finish, returns whether
+    /// they all did
+    pub fn drain(&self, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+
+        while self.in_flight() > 0 {
+            if Instant::now() >= deadline {
+                return false;
+            }
+
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        true
+    }
+}
+
+impl Drop for WorkerPool {
+    fn drop(&mut self) {
+        self.sender.take();
+
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::{mpsc, Arc, Barrier}, thread, time::Duration};
+
+    use super::{PoolOptions, WorkerPool};
+
+    #[test]
+    fn runs_jobs_in_parallel_up_to_the_limit() {
+        let pool = WorkerPool::new(PoolOptions { workers: 2, max_in_flight: 3 });
+        let barrier = Arc::new(Barrier::new(3));
+        let (sender, receiver) = mpsc::channel();
+
+        // Both workers block until the test lets them go, which only works if
+        // they run at the same time
+        for i in 0..2 {
+            let barrier = Arc::clone(&barrier);
+            let sender = sender.clone();
+
+            pool.execute(move || {
+                barrier.wait();
+                sender.send(i).unwrap();
+            }).unwrap();
+        }
+
+        // Queued behind the two running ones
+        let queued = sender.clone();
+        pool.execute(move || queued.send(2).unwrap()).unwrap();
+
+        assert_eq!(pool.in_flight(), 3);
+        assert!(pool.execute(|| {}).is_err());
+
+        barrier.wait();
+
+        let mut done = (0..3).map(|_| receiver.recv().unwrap()).collect::<Vec<_>>();
+        done.sort();
+        assert_eq!(done, vec![0, 1, 2]);
+
+        // Slots are given back right after the jobs return
+        while pool.in_flight() > 0 {
+            thread::yield_now();
+        }
+
+        // Panicking jobs give their slot back and leave the workers running
+        for _ in 0..2 {
+            pool.execute(|| panic!("bad job")).unwrap();
+        }
+
+        pool.execute(move || sender.send(3).unwrap()).unwrap();
+        assert_eq!(receiver.recv(), Ok(3));
+
+        // The last slot is given back right after its job returns
+        assert!(pool.drain(Duration::from_secs(5)));
+        assert_eq!(pool.in_flight(), 0);
+
+        pool.execute(|| thread::sleep(Duration::from_millis(200))).unwrap();
+        assert!(! pool.drain(Duration::from_millis(10)));
+        assert!(pool.drain(Duration::from_secs(5)));
+
+        drop(pool);
+        assert!(receiver.recv().is_err());
+    }
+}
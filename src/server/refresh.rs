@@ -0,0 +1,192 @@
+use std::{collections::HashMap, panic::{self, AssertUnwindSafe}, sync::{mpsc::{self, Receiver, Sender}, Arc, Mutex, MutexGuard}, thread, time::{Duration, Instant}};
+
+use crate::parser::{header::ResultCode, packet::DNSPacket, types::{RecordClass, RecordType}};
+
+/// Most lookups refreshing the cache at once, past that stale data is served
+/// without asking upstream until some of them finish
+const MAX_REFRESHES: usize = 64;
+
+/// How long upstream servers are left alone for a name after they failed to
+/// answer for it, stale data is served right away meanwhile (RFC 8767 5)
+const FAILURE_RECHECK: Duration = Duration::from_secs(30);
+
+type RefreshKey = (String, RecordType, RecordClass);
+
+pub type RefreshResult = Result<DNSPacket, String>;
+
+/// Upstream servers failing, as opposed to them saying the name doesn't exist
+pub fn failed(result: &RefreshResult) -> bool {
+    result.as_ref().map_or(true, |resp| matches!(resp.header.rcode, ResultCode::ServerFailure | ResultCode::Refused))
+}
+
+#[derive(Debug, Default)]
+struct RefreshState {
+    /// Lookups running and whoever waits on their results
+    running: HashMap<RefreshKey, Vec<Sender<RefreshResult>>>,
+
+    /// When the last lookup failed, for names that still fail
+    failures: HashMap<RefreshKey, Instant>,
+}
+
+/// Lookups refreshing the cache in the background. There's at most one of
+/// them per name, type and class, and only so many at once.
+#[derive(Debug, Default)]
+pub struct Refreshes {
+    state: Mutex<RefreshState>,
+}
+
+impl Refreshes {
+    fn lock(&self) -> MutexGuard<'_, RefreshState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Runs `lookup` on a thread of its own, its result is sent over the
+    /// returned channel. When `name` is already being refreshed its lookup
+    /// is waited on instead, None when too many of them run already.
+    pub fn start(
+        self: &Arc<Self>,
+        name: &str,
+        rtype: RecordType,
+        class: RecordClass,
+        lookup: impl FnOnce() -> RefreshResult + Send + 'static,
+    ) -> Option<Receiver<RefreshResult>> {
+        let key = (name.to_ascii_lowercase(), rtype, class);
+        let (sender, receiver) = mpsc::channel();
+        let mut state = self.lock();
+
+        if let Some(waiting) = state.running.get_mut(&key) {
+            waiting.push(sender);
+
+            return Some(receiver);
+        }
+
+        if state.running.len() >= MAX_REFRESHES {
+            return None;
+        }
+
+        state.running.insert(key.clone(), vec![sender]);
+        drop(state);
+
+        let refreshes = Arc::clone(self);
+
+        thread::spawn(move || {
+            // The name has to be freed up again no matter what
+            let result = panic::catch_unwind(AssertUnwindSafe(lookup))
+                .unwrap_or_else(|_| Err("Refresh panicked".to_owned()));
+
+            refreshes.finish(key, result, Instant::now());
+        });
+
+        Some(receiver)
+    }
+
+    fn finish(&self, key: RefreshKey, result: RefreshResult, now: Instant) {
+        let mut state = self.lock();
+        let waiting = state.running.remove(&key).unwrap_or_default();
+
+        if failed(&result) {
+            state.failures.retain(|_, at| now < *at + FAILURE_RECHECK);
+            state.failures.insert(key, now);
+        } else {
+            state.failures.remove(&key);
+        }
+
+        drop(state);
+
+        // Nobody may be waiting anymore, the cache has the result either way
+        for sender in waiting {
+            let _ = sender.send(result.clone());
+        }
+    }
+
+    /// Whether upstream servers failed to answer for `name` less than the
+    /// failure recheck time ago
+    pub fn recently_failed(&self, name: &str, rtype: RecordType, class: RecordClass, now: Instant) -> bool {
+        self.lock()
+            .failures
+            .get(&(name.to_ascii_lowercase(), rtype, class))
+            .is_some_and(|at| now < *at + FAILURE_RECHECK)
+    }
+
+    /// Lookups running
+    pub fn len(&self) -> usize {
+        self.lock().running.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::{atomic::{AtomicUsize, Ordering}, mpsc, Arc, Mutex}, time::{Duration, Instant}};
+
+    use crate::parser::types::{RecordClass, RecordType};
+
+    use super::{Refreshes, FAILURE_RECHECK, MAX_REFRESHES};
+
+    #[test]
+    fn runs_one_lookup_per_name() {
+        let refreshes = Arc::new(Refreshes::default());
+        let lookups = Arc::new(AtomicUsize::new(0));
+        let (release, released) = mpsc::channel::<()>();
+
+        let first = {
+            let lookups = Arc::clone(&lookups);
+
+            refreshes.start("example.com.", RecordType::A, RecordClass::IN, move || {
+                lookups.fetch_add(1, Ordering::SeqCst);
+                let _ = released.recv();
+
+                Err("Timed out".to_owned())
+            }).unwrap()
+        };
+
+        // Waits on the first lookup rather than starting one of its own
+        let second = refreshes.start("EXAMPLE.com.", RecordType::A, RecordClass::IN, || unreachable!()).unwrap();
+        assert_eq!(refreshes.len(), 1);
+
+        release.send(()).unwrap();
+        assert_eq!(first.recv_timeout(Duration::from_secs(5)).unwrap(), Err("Timed out".to_owned()));
+        assert_eq!(second.recv_timeout(Duration::from_secs(5)).unwrap(), Err("Timed out".to_owned()));
+        assert_eq!(lookups.load(Ordering::SeqCst), 1);
+
+        // The failure is remembered for a while
+        let now = Instant::now();
+        assert!(refreshes.recently_failed("example.com.", RecordType::A, RecordClass::IN, now));
+        assert!(! refreshes.recently_failed("example.com.", RecordType::AAAA, RecordClass::IN, now));
+        assert!(! refreshes.recently_failed("example.com.", RecordType::A, RecordClass::IN, now + FAILURE_RECHECK));
+    }
+
+    #[test]
+    fn limits_lookups_running_at_once() {
+        let refreshes = Arc::new(Refreshes::default());
+        let (release, released) = mpsc::channel::<()>();
+        let released = Arc::new(Mutex::new(released));
+
+        let receivers = (0..MAX_REFRESHES)
+            .map(|i| {
+                let released = Arc::clone(&released);
+
+                refreshes.start(&format!("{}.example.com.", i), RecordType::A, RecordClass::IN, move || {
+                    let _ = released.lock().unwrap().recv();
+
+                    Err("Timed out".to_owned())
+                }).unwrap()
+            })
+            .collect::<Vec<_>>();
+
+        assert!(refreshes.start("more.example.com.", RecordType::A, RecordClass::IN, || unreachable!()).is_none());
+
+        for _ in 0..MAX_REFRESHES {
+            release.send(()).unwrap();
+        }
+
+        for receiver in receivers {
+            assert!(receiver.recv_timeout(Duration::from_secs(5)).is_ok());
+        }
+
+        assert!(refreshes.start("more.example.com.", RecordType::A, RecordClass::IN, || Err("Timed out".to_owned())).is_some());
+    }
+}
@@ -1,14 +1,25 @@
 use std::net::Ipv4Addr;
 
-struct RootServer {
-    domain: String,
-    ipv4: Ipv4Addr,
+/// One of the 13 root name servers, from IANA's root hints file
+pub struct RootServer {
+    pub domain: &'static str,
+    pub ipv4: Ipv4Addr,
 }
 
-
-
-impl RootServer {
-    fn new() {
-
-    }
-}
+/// Default root hints, recursion starts from these unless the configuration
+/// says otherwise
+pub const ROOT_SERVERS: [RootServer; 13] = [
+    RootServer { domain: "a.root-servers.net.", ipv4: Ipv4Addr::new(198, 41, 0, 4) },
+    RootServer { domain: "b.root-servers.net.", ipv4: Ipv4Addr::new(170, 247, 170, 2) },
+    RootServer { domain: "c.root-servers.net.", ipv4: Ipv4Addr::new(192, 33, 4, 12) },
+    RootServer { domain: "d.root-servers.net.", ipv4: Ipv4Addr::new(199, 7, 91, 13) },
+    RootServer { domain: "e.root-servers.net.", ipv4: Ipv4Addr::new(192, 203, 230, 10) },
+    RootServer { domain: "f.root-servers.net.", ipv4: Ipv4Addr::new(192, 5, 5, 241) },
+    RootServer { domain: "g.root-servers.net.", ipv4: Ipv4Addr::new(192, 112, 36, 4) },
+    RootServer { domain: "h.root-servers.net.", ipv4: Ipv4Addr::new(198, 97, 190, 53) },
+    RootServer { domain: "i.root-servers.net.", ipv4: Ipv4Addr::new(192, 36, 148, 17) },
+    RootServer { domain: "j.root-servers.net.", ipv4: Ipv4Addr::new(192, 58, 128, 30) },
+    RootServer { domain: "k.root-servers.net.", ipv4: Ipv4Addr::new(193, 0, 14, 129) },
+    RootServer { domain: "l.root-servers.net.", ipv4: Ipv4Addr::new(199, 7, 83, 42) },
+    RootServer { domain: "m.root-servers.net.", ipv4: Ipv4Addr::new(202, 12, 27, 33) },
+];
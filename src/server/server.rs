@@ -1,30 +1,590 @@
-use std::net::UdpSocket;
+use std::{collections::HashMap, io::{self, BufReader, Read, Write}, net::{Shutdown, SocketAddr, TcpListener, TcpStream, UdpSocket}, sync::{atomic::{AtomicBool, AtomicU64, Ordering}, mpsc::Receiver, Arc, Mutex, RwLock}, thread, time::{Duration, Instant}};
 
-use crate::{parser::{header::{DNSHeader, DNSHeaderType, ResultCode}, packet::{DNSPacket, DNSPacketParser}}, server::lookup::lookup_recursively};
+use crate::{parser::{header::{DNSHeader, DNSHeaderType, Opcode, ResultCode}, packet::DNSPacket, question::DNSQuestion, update::DNSUpdate, view::{DNSPacketView, DNSPacketViewMut}}, server::{blocklist::Blocklist, cache::Cache, config::{Config, Mode}, refresh::{self, RefreshResult, Refreshes}, log::{self, log}, lookup::{forward, lookup_recursively}, pool::WorkerPool, tsig::{self, attach_error, verify_request, TSIGKeyring, TSIGSigner, TSIGVerifyError}, update::apply_update, zone::{Zone, ZoneStore}}};
 
-pub fn handle_query(socket: &UdpSocket) -> Result<(), String> {
+/// Largest response sent over UDP, clients can't ask for more without EDNS
+/// (RFC 1035 4.2.1), bigger ones are truncated so that they retry over TCP
+const MAX_UDP_SIZE: usize = 512;
+
+/// Largest message that fits behind the 2 byte TCP length prefix
+const MAX_TCP_SIZE: usize = u16::MAX as usize;
+
+/// How often idle sockets and listeners check whether the server is stopping
+const STOP_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Everything the server needs to answer requests
+#[derive(Debug, Default)]
+pub struct ServerState {
+    /// Shared with the states that replace this one on reload, since dynamic
+    /// updates are only kept in memory
+    pub zones: Arc<ZoneStore>,
+
+    /// Kept across reloads as well, the default one holds nothing
+    pub cache: Arc<Cache>,
+    pub refreshes: Arc<Refreshes>,
+    pub keyring: TSIGKeyring,
+    pub blocklist: Blocklist,
+    pub config: Config,
+}
+
+impl ServerState {
+    /// Loads the zones, TSIG keys and blocklists `config` points at
+    pub fn new(config: Config) -> Result<Self, String> {
+        let (keyring, blocklist, zones) = read_files(&config)?;
+
+        let store = ZoneStore::default();
+        store.replace(zones);
+
+        let cache = Cache::new(config.cache.max_bytes);
+        cache.set_max_stale(config.cache.max_stale);
+
+        // Starting cold is no reason not to start
+        if let Some(ref path) = config.cache.file {
+            match cache.load(path, Instant::now(), tsig::now()) {
+                Ok(count) => log!(Info, "Loaded {} cache entries from {}", count, path),
+                Err(e) => log!(Warn, "{}, starting with an empty cache", e),
+            }
+        }
+
+        Ok(Self {
+            zones: Arc::new(store),
+            cache: Arc::new(cache),
+            refreshes: Arc::default(),
+            keyring,
+            blocklist,
+            config,
+        })
+    }
+}
+
+/// Reads everything the configuration points at, nothing is swapped in
+/// unless all of it loads
+fn read_files(config: &Config) -> Result<(TSIGKeyring, Blocklist, Vec<Zone>), String> {
+    let keyring = match config.tsig_keys {
+        Some(ref path) => TSIGKeyring::load(path)?,
+        None => TSIGKeyring::default(),
+    };
+
+    let blocklist = Blocklist::load(&config.blocklists)?;
+
+    let zones = config.zones
+        .iter()
+        .map(|(origin, path)| Zone::load(origin, path))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok((keyring, blocklist, zones))
+}
+
+/// Hands out the state queries are answered with. Reloading swaps it as a
+/// whole, queries that already started finish with the one they got.
+#[derive(Debug, Default)]
+pub struct SharedState {
+    current: RwLock<Arc<ServerState>>,
+
+    /// Command line the configuration is rebuilt from on reload
+    args: Vec<String>,
+
+    /// Set once the server is shutting down
+    stopping: AtomicBool,
+}
+
+impl SharedState {
+    pub fn new(state: ServerState, args: Vec<String>) -> Self {
+        Self { current: RwLock::new(Arc::new(state)), args, stopping: AtomicBool::new(false) }
+    }
+
+    /// Makes the serve loops stop taking new queries and return, queries
+    /// already handed to the pool are still answered
+    pub fn stop(&self) {
+        self.stopping.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_stopping(&self) -> bool {
+        self.stopping.load(Ordering::SeqCst)
+    }
+
+    pub fn load(&self) -> Arc<ServerState> {
+        Arc::clone(&self.current.read().unwrap_or_else(|e| e.into_inner()))
+    }
+
+    /// Re-reads the configuration, zones, TSIG keys and blocklists. When any
+    /// of them fails to load or validate the current state is left untouched.
+    pub fn reload(&self) -> Result<(), String> {
+        let mut config = Config::from_args(&self.args)?;
+        keep_startup_options(&self.load().config, &mut config);
+
+        let (keyring, blocklist, zones) = read_files(&config)?;
+        let zone_count = zones.len();
+
+        let mut current = self.current.write().unwrap_or_else(|e| e.into_inner());
+        current.zones.replace(zones);
+        current.cache.set_max_bytes(config.cache.max_bytes);
+        current.cache.set_max_stale(config.cache.max_stale);
+
+        log::set_level(config.log_level);
+
+        *current = Arc::new(ServerState {
+            zones: Arc::clone(&current.zones),
+            cache: Arc::clone(&current.cache),
+            refreshes: Arc::clone(&current.refreshes),
+            keyring,
+            blocklist,
+            config,
+        });
+
+        log!(Info, "Reloaded, {} zones and {} blocked names", zone_count, current.blocklist.len());
+
+        Ok(())
+    }
+}
+
+impl From<ServerState> for SharedState {
+    fn from(state: ServerState) -> Self {
+        Self::new(state, vec![])
+    }
+}
+
+/// Gives the queries still in flight the configured shutdown timeout to
+/// finish once the serve loops are done, returns whether they all did
+pub fn drain(shared: &SharedState, pool: &WorkerPool) -> bool {
+    let timeout = shared.load().config.shutdown_timeout;
+    let in_flight = pool.in_flight();
+
+    if in_flight > 0 {
+        log!(Info, "Waiting up to {:?} for {} queries in flight", timeout, in_flight);
+    }
+
+    if ! pool.drain(timeout) {
+        log!(Warn, "Giving up on {} queries still in flight", pool.in_flight());
+        return false;
+    }
+
+    true
+}
+
+/// Saves the cache to the configured file, if there is one
+pub fn save_cache(shared: &SharedState) {
+    let state = shared.load();
+
+    let Some(ref path) = state.config.cache.file else {
+        return;
+    };
+
+    match state.cache.save(path, Instant::now(), tsig::now()) {
+        Ok(count) => log!(Info, "Saved {} cache entries to {}", count, path),
+        Err(e) => log!(Warn, "{}", e),
+    }
+}
+
+/// Saves the cache every `cache.save_interval` until the server stops, the
+/// interval is looked up again after reloads
+pub fn save_cache_periodically(shared: &SharedState) {
+    let mut last_save = Instant::now();
+
+    while ! shared.is_stopping() {
+        thread::sleep(STOP_POLL_INTERVAL);
+
+        let interval = shared.load().config.cache.save_interval;
+
+        if ! interval.is_zero() && last_save.elapsed() >= interval {
+            save_cache(shared);
+            last_save = Instant::now();
+        }
+    }
+}
+
+/// Sockets and threads are only set up on startup, changing them takes a restart
+fn keep_startup_options(running: &Config, config: &mut Config) {
+    if (&running.listen_udp, &running.listen_tcp, running.tcp, running.pool, running.control) != (&config.listen_udp, &config.listen_tcp, config.tcp, config.pool, config.control) {
+        log!(Warn, "Listen addresses, TCP, worker and control channel options only change on restart");
+    }
+
+    config.listen_udp.clone_from(&running.listen_udp);
+    config.listen_tcp.clone_from(&running.listen_tcp);
+    config.tcp = running.tcp;
+    config.pool = running.pool;
+    config.control = running.control;
+}
+
+/// Receives UDP queries and hands them to the pool until the server stops,
+/// when the pool is full the query is answered with SERVFAIL right away instead
+pub fn serve_udp(socket: &Arc<UdpSocket>, shared: &SharedState, pool: &WorkerPool) {
     let mut packet_buf = [0u8; 65_535];
-    println!("READY TO RECEIVE");
-    let (bytes_read, src) = match socket.recv_from(&mut packet_buf) {
-        Ok(len) => len,
-        Err(er) => return Err("Failed to receive data from socket".to_owned()),
+
+    // Wakes up every now and then to notice that the server is stopping
+    if let Err(e) = socket.set_read_timeout(Some(STOP_POLL_INTERVAL)) {
+        log!(Error, "Failed to set UDP socket timeout, {}", e);
+        return;
+    }
+
+    while ! shared.is_stopping() {
+        let (bytes_read, src) = match socket.recv_from(&mut packet_buf) {
+            Ok(len) => len,
+            Err(e) if matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => continue,
+            Err(e) => {
+                log!(Warn, "Failed to receive data from socket, {}", e);
+                continue;
+            },
+        };
+
+        let state = shared.load();
+
+        if ! state.config.allows(src.ip()) {
+            log!(Debug, "Refusing query from {}", src);
+
+            if let Some(resp_data) = error_response(&packet_buf[0..bytes_read], ResultCode::Refused) {
+                send_udp(socket, &resp_data, src);
+            }
+
+            continue;
+        }
+
+        let req_data = packet_buf[0..bytes_read].to_vec();
+        let job_socket = Arc::clone(socket);
+
+        let queued = pool.execute(move || {
+            match answer(&req_data, &state, MAX_UDP_SIZE) {
+                Ok(resp_data) => send_udp(&job_socket, &resp_data, src),
+                Err(e) => log!(Debug, "Dropping query from {}, {}", src, e),
+            }
+        });
+
+        if let Err(e) = queued {
+            log!(Warn, "Turning away query from {}, {}", src, e);
+
+            if let Some(resp_data) = error_response(&packet_buf[0..bytes_read], ResultCode::ServerFailure) {
+                send_udp(socket, &resp_data, src);
+            }
+        }
+    }
+}
+
+fn send_udp(socket: &UdpSocket, data: &[u8], dst: SocketAddr) {
+    if let Err(e) = socket.send_to(data, dst) {
+        log!(Warn, "Failed to send response to {}, {}", dst, e);
+    }
+}
+
+/// Limits of the TCP listener
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TcpOptions {
+    /// How long a connection may sit without sending a query before it's
+    /// closed, also bounds how long writing a response may take
+    pub idle_timeout: Duration,
+
+    /// Connections over this limit are closed as soon as they're accepted
+    pub max_connections: usize,
+}
+
+impl Default for TcpOptions {
+    fn default() -> Self {
+        Self {
+            idle_timeout: Duration::from_secs(10),
+            max_connections: 128,
+        }
+    }
+}
+
+/// Open TCP connections, kept so that they can be told to stop reading when
+/// the server stops
+#[derive(Default)]
+struct Connections {
+    open: Mutex<HashMap<u64, TcpStream>>,
+    next_id: AtomicU64,
+}
+
+impl Connections {
+    /// Takes a slot for `stream`, `None` when they're all taken
+    fn register(&self, stream: &TcpStream, max_connections: usize) -> Result<Option<ConnectionSlot<'_>>, String> {
+        let mut open = self.open.lock().unwrap_or_else(|e| e.into_inner());
+
+        if open.len() >= max_connections {
+            return Ok(None);
+        }
+
+        let clone = stream.try_clone()
+            .map_err(|e| format!("Failed to clone connection, {}", e))?;
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+        open.insert(id, clone);
+
+        Ok(Some(ConnectionSlot { connections: self, id }))
+    }
+
+    /// Pending reads return EOF, responses still being resolved can be written
+    fn stop_reading(&self) {
+        for stream in self.open.lock().unwrap_or_else(|e| e.into_inner()).values() {
+            let _ = stream.shutdown(Shutdown::Read);
+        }
+    }
+}
+
+/// Frees a connection slot once its connection is done, even if it panicked
+struct ConnectionSlot<'a> {
+    connections: &'a Connections,
+    id: u64,
+}
+
+impl Drop for ConnectionSlot<'_> {
+    fn drop(&mut self) {
+        self.connections.open.lock().unwrap_or_else(|e| e.into_inner()).remove(&self.id);
+    }
+}
+
+/// Waits for the next connection, `None` once the server is stopping.
+/// `listener` has to be non blocking.
+pub(crate) fn accept(listener: &TcpListener, shared: &SharedState) -> Option<io::Result<TcpStream>> {
+    loop {
+        if shared.is_stopping() {
+            return None;
+        }
+
+        match listener.accept() {
+            // Some platforms pass non blocking on to accepted sockets
+            Ok((stream, _)) => return Some(stream.set_nonblocking(false).map(|_| stream)),
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => thread::sleep(STOP_POLL_INTERVAL / 10),
+            Err(e) => return Some(Err(e)),
+        }
+    }
+}
+
+/// Accepts DNS over TCP connections (RFC 7766) until the server stops, each
+/// one is read by a thread of its own while its queries are resolved by the
+/// pool. Returns once every connection is done reading.
+pub fn serve_tcp(listener: &TcpListener, shared: &SharedState, options: TcpOptions, pool: &WorkerPool) {
+    let connections = Connections::default();
+
+    if let Err(e) = listener.set_nonblocking(true) {
+        log!(Error, "Failed to set up TCP listener, {}", e);
+        return;
+    }
+
+    thread::scope(|scope| {
+        while let Some(stream) = accept(listener, shared) {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(e) => {
+                    log!(Warn, "Failed to accept TCP connection, {}", e);
+                    continue;
+                },
+            };
+
+            let slot = match connections.register(&stream, options.max_connections) {
+                Ok(Some(slot)) => slot,
+                Ok(None) => {
+                    log!(Warn, "Closing TCP connection from {:?}, too many connections", stream.peer_addr());
+                    continue;
+                },
+                Err(e) => {
+                    log!(Warn, "{}", e);
+                    continue;
+                },
+            };
+
+            scope.spawn(move || {
+                let _slot = slot;
+                let peer = stream.peer_addr();
+
+                if let Err(e) = handle_connection(stream, shared, options.idle_timeout, pool) {
+                    log!(Debug, "TCP connection from {:?} failed, {}", peer, e);
+                }
+            });
+        }
+
+        connections.stop_reading();
+    });
+}
+
+/// Answers the queries of a TCP connection until the client closes it or it
+/// goes idle. Pipelined queries are resolved in parallel and each response is
+/// sent as soon as it's ready, so they may come back in any order (RFC 7766 6.2.1.1).
+pub fn handle_connection(stream: TcpStream, shared: &SharedState, idle_timeout: Duration, pool: &WorkerPool) -> Result<(), String> {
+    stream.set_read_timeout(Some(idle_timeout))
+        .and_then(|_| stream.set_write_timeout(Some(idle_timeout)))
+        .map_err(|e| format!("Failed to set connection timeouts, {}", e))?;
+
+    // Pipelined queries usually arrive in the same segment
+    let mut reader = BufReader::new(&stream);
+    let writer = stream.try_clone()
+        .map(|stream| Arc::new(Mutex::new(stream)))
+        .map_err(|e| format!("Failed to clone connection, {}", e))?;
+    let peer = stream.peer_addr()
+        .map_err(|e| format!("Failed to get peer address, {}", e))?;
+
+    // Once the server stops, queries that were already read are still answered
+    while ! shared.is_stopping() {
+        let mut len = [0u8; 2];
+
+        match reader.read_exact(&mut len) {
+            Ok(_) => {},
+            // The client is done, or has been quiet for too long
+            Err(e) if is_closed_or_idle(&e) => return Ok(()),
+            Err(e) => return Err(format!("Failed to read query length, {}", e)),
+        }
+
+        let mut req_data = vec![0u8; u16::from_be_bytes(len) as usize];
+
+        match reader.read_exact(&mut req_data) {
+            Ok(_) => {},
+            Err(e) if is_closed_or_idle(&e) => return Ok(()),
+            Err(e) => return Err(format!("Failed to read query, {}", e)),
+        }
+
+        let state = shared.load();
+
+        // Disallowed clients still get an answer to each of their queries
+        if ! state.config.allows(peer.ip()) {
+            match error_response(&req_data, ResultCode::Refused) {
+                Some(resp_data) => send_framed(&writer, &resp_data),
+                None => return Ok(()),
+            }
+
+            continue;
+        }
+
+        let overload_data = error_response(&req_data, ResultCode::ServerFailure);
+        let job_writer = Arc::clone(&writer);
+
+        let queued = pool.execute(move || {
+            match answer(&req_data, &state, MAX_TCP_SIZE) {
+                Ok(resp_data) => send_framed(&job_writer, &resp_data),
+                // Nothing sensible can be sent back, there's no point in keeping the connection
+                Err(e) => {
+                    log!(Debug, "Closing TCP connection, {}", e);
+                    let _ = job_writer.lock().unwrap_or_else(|e| e.into_inner()).shutdown(Shutdown::Both);
+                },
+            }
+        });
+
+        if let Err(e) = queued {
+            log!(Warn, "Turning away TCP query, {}", e);
+
+            match overload_data {
+                Some(resp_data) => send_framed(&writer, &resp_data),
+                None => return Ok(()),
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Writes a length prefixed response, responses of pipelined queries are
+/// written whole one after the other
+fn send_framed(writer: &Mutex<TcpStream>, resp_data: &[u8]) {
+    let mut framed = Vec::with_capacity(resp_data.len() + 2);
+    framed.extend_from_slice(&(resp_data.len() as u16).to_be_bytes());
+    framed.extend_from_slice(resp_data);
+
+    let mut writer = writer.lock().unwrap_or_else(|e| e.into_inner());
+
+    if let Err(e) = writer.write_all(&framed) {
+        log!(Debug, "Failed to send response, {}", e);
+        let _ = writer.shutdown(Shutdown::Both);
+    }
+}
+
+fn is_closed_or_idle(error: &io::Error) -> bool {
+    matches!(error.kind(), io::ErrorKind::UnexpectedEof | io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut)
+}
+
+/// Answers a raw request, verifying its TSIG and signing the response if it had one
+pub fn handle_packet(req_data: &[u8], state: &ServerState) -> Result<Vec<u8>, String> {
+    answer(req_data, state, MAX_TCP_SIZE)
+}
+
+/// Like `handle_packet`, responses over `max_size` bytes are cut down to
+/// their question with the TC bit set
+fn answer(req_data: &[u8], state: &ServerState, max_size: usize) -> Result<Vec<u8>, String> {
+    let req_packet = match DNSPacketView::new(req_data).and_then(|view| view.to_packet()) {
+        Ok(req_packet) => req_packet,
+        Err(e) => return error_response(req_data, ResultCode::FormatError).ok_or(e),
     };
-    println!("RECEIVED QUERY FROM {:?}", src);
+    let now = tsig::now();
+
+    let signed_request = match verify_request(req_data, &req_packet, &state.keyring, now) {
+        Ok(signed_request) => signed_request,
+        Err(failure) => {
+            let mut resp_packet = empty_response(&req_packet, state);
+            resp_packet.header.rcode = match failure {
+                TSIGVerifyError::FormatError => ResultCode::FormatError,
+                TSIGVerifyError::Rejected { .. } => ResultCode::NotAuth,
+            };
+            attach_error(&mut resp_packet, failure, now)?;
 
-    let mut req_packet = DNSPacketParser::new(&packet_buf[0..bytes_read]).parse()?;
+            return resp_packet.serialize();
+        },
+    };
 
-    let mut resp_packet = DNSPacket {
+    let mut resp_packet = resolve(req_packet, state, signed_request.is_some());
+    let mut unsigned_packet = resp_packet.clone();
+
+    if let Some(ref signed_request) = signed_request {
+        TSIGSigner::for_response(&signed_request.key, &signed_request.mac)
+            .sign(&mut resp_packet, now)?;
+    }
+
+    let resp_data = resp_packet.serialize()?;
+
+    if resp_data.len() <= max_size {
+        return Ok(resp_data);
+    }
+
+    // Truncated responses are still signed (RFC 8945 5.3)
+    unsigned_packet.header.tc = true;
+    unsigned_packet.header.ancount = 0;
+    unsigned_packet.header.nscount = 0;
+    unsigned_packet.header.arcount = 0;
+    unsigned_packet.answers.clear();
+    unsigned_packet.authority.clear();
+    unsigned_packet.additional.clear();
+
+    if let Some(ref signed_request) = signed_request {
+        TSIGSigner::for_response(&signed_request.key, &signed_request.mac)
+            .sign(&mut unsigned_packet, now)?;
+    }
+
+    unsigned_packet.serialize()
+}
+
+/// Answers a request we couldn't parse or won't resolve with `rcode`, reusing
+/// its header as is. Returns `None` when there isn't even a header to answer to.
+fn error_response(req_data: &[u8], rcode: ResultCode) -> Option<Vec<u8>> {
+    let mut resp_data = req_data.get(0..12)?.to_vec();
+
+    let mut view = DNSPacketViewMut::new(&mut resp_data).ok()?;
+
+    // Never answer responses, that could bounce between two servers forever
+    if view.view().qr() == DNSHeaderType::Response {
+        return None;
+    }
+
+    view.set_qr(DNSHeaderType::Response);
+    view.set_aa(false);
+    view.set_tc(false);
+    view.set_ra(true);
+    view.set_ad(false);
+    view.set_rcode(rcode);
+    view.set_counts(0, 0, 0, 0);
+
+    Some(resp_data)
+}
+
+/// A response to `req_packet` with no records and a NOERROR result code
+fn empty_response(req_packet: &DNSPacket, state: &ServerState) -> DNSPacket {
+    DNSPacket {
         header: DNSHeader {
             id: req_packet.header.id,
             qr: DNSHeaderType::Response,
-            rd: true,
-            ra: true,
+            rd: req_packet.header.rd,
+            ra: state.config.mode != Mode::Authoritative,
             aa: false,
             tc: false,
             z: 0,
-            opcode: 0,
+            ad: false,
+            cd: req_packet.header.cd,
+            opcode: req_packet.header.opcode,
             rcode: ResultCode::NoError,
-            qdcount: 1,
+            qdcount: req_packet.questions.len() as u16,
             ancount: 0,
             nscount: 0,
             arcount: 0,
@@ -33,34 +593,592 @@ pub fn handle_query(socket: &UdpSocket) -> Result<(), String> {
         answers: vec![],
         authority: vec![],
         additional: vec![],
+    }
+}
+
+/// Builds the response for a parsed request, dispatching on its opcode,
+/// `authenticated` tells whether the request carried a valid TSIG
+pub fn resolve(mut req_packet: DNSPacket, state: &ServerState, authenticated: bool) -> DNSPacket {
+    let mut resp_packet = empty_response(&req_packet, state);
+
+    match req_packet.header.opcode {
+        Opcode::Query => {},
+        Opcode::Update => {
+            resp_packet.header.rcode = match DNSUpdate::new(&req_packet) {
+                // Only signed updates may touch our zones
+                Ok(_) if ! authenticated => ResultCode::Refused,
+                Ok(update) => apply_update(&state.zones, &update),
+                Err(_) => ResultCode::FormatError,
+            };
+
+            return resp_packet;
+        },
+        _ => {
+            resp_packet.header.rcode = ResultCode::NotImplemented;
+
+            return resp_packet;
+        },
+    }
+
+    let Some(question) = req_packet.questions.pop() else {
+        resp_packet.header.rcode = ResultCode::FormatError;
+
+        return resp_packet;
+    };
+
+    // Our own zones come first, whatever the mode
+    if let Some(zone_answer) = state.zones.lookup(&question.name, question.rtype) {
+        resp_packet.header.aa = zone_answer.authoritative;
+        resp_packet.header.rcode = zone_answer.rcode;
+        resp_packet.answers = zone_answer.answers;
+        resp_packet.authority = zone_answer.authority;
+        resp_packet.additional = zone_answer.additional;
+        set_counts(&mut resp_packet);
+
+        return resp_packet;
+    }
+
+    if state.blocklist.blocks(&question.name) {
+        log!(Debug, "Blocked {} {}", question.name, question.rtype);
+        resp_packet.header.rcode = ResultCode::NameError;
+
+        return resp_packet;
+    }
+
+    if let Some(cached) = state.cache.answer(&question.name, question.rtype, question.class, Instant::now()) {
+        if cached.prefetch && state.config.cache.prefetch {
+            log!(Debug, "Prefetching {} {}", question.name, question.rtype);
+
+            // Nobody waits for it, it only refreshes the cache
+            let _ = lookup_in_background(state, &question, req_packet.header.cd);
+        }
+
+        resp_packet.header.rcode = cached.rcode;
+        resp_packet.answers = cached.answers;
+        resp_packet.authority = cached.authority;
+        set_counts(&mut resp_packet);
+
+        return resp_packet;
+    }
+
+    if state.config.mode == Mode::Authoritative {
+        resp_packet.header.rcode = ResultCode::Refused;
+
+        return resp_packet;
+    }
+
+    // With stale data to fall back on, clients only wait on upstream servers for so long
+    // while the lookup goes on to refresh the cache, and they aren't asked at all
+    // for a while after failing (RFC 8767 5)
+    let stale = state.cache.stale_answer(&question.name, question.rtype, question.class, Instant::now());
+
+    let result = match stale {
+        Some(_) if state.refreshes.recently_failed(&question.name, question.rtype, question.class, Instant::now()) =>
+            Err("Upstream servers failed recently".to_owned()),
+        Some(_) => match lookup_in_background(state, &question, req_packet.header.cd) {
+            Some(receiver) => receiver
+                .recv_timeout(state.config.cache.client_timeout)
+                .unwrap_or_else(|_| Err(format!("No answer within {:?}", state.config.cache.client_timeout))),
+            None => Err("Too many lookups running in the background".to_owned()),
+        },
+        None => lookup_upstream(&state.config, &state.cache, &question, req_packet.header.cd),
     };
 
-    if let Some(question) = req_packet.questions.pop() {
-        match lookup_recursively(&question.name, question.rtype) {
-            Ok(DNSPacket { header, questions: _, answers, authority, additional }) => {
-                resp_packet.header.tc = header.tc;
-                resp_packet.header.ancount = header.ancount;
-                resp_packet.header.nscount = header.nscount;
-                resp_packet.header.arcount = header.arcount;
-                resp_packet.answers = answers;
-                resp_packet.authority = authority;
-                resp_packet.additional = additional;
-                println!("Debug {:?}", resp_packet);
-
-                socket.send_to(&resp_packet.serialize()?, src)
-                    .expect("Should send response");
-            }, 
-            Err(err) => {
-                resp_packet.header.rcode = ResultCode::ServerFailure;
-                socket.send_to(&resp_packet.serialize()?, src)
-                    .expect("Should send response");
+    // Upstream servers failing is what stale data is there for, not them saying the name doesn't exist
+    let failed = refresh::failed(&result);
+
+    match (result, stale) {
+        (result, Some(stale)) if failed => {
+            log!(Debug, "Serving stale {} {}, {}", question.name, question.rtype, result.map_or_else(|e| e, |resp| format!("upstream answered {}", resp.header.rcode)));
+
+            resp_packet.header.rcode = stale.rcode;
+            resp_packet.answers = stale.answers;
+            resp_packet.authority = stale.authority;
+            set_counts(&mut resp_packet);
+        },
+        (Ok(DNSPacket { header, questions: _, answers, authority, additional }), _) => {
+            resp_packet.header.tc = header.tc;
+            // Answers are never DNSSEC validated on our side, so AD
+            // stays cleared no matter what the upstream claims (RFC 4035 3.2.3)
+            resp_packet.header.rcode = header.rcode;
+            resp_packet.answers = answers;
+            resp_packet.authority = authority;
+            resp_packet.additional = additional;
+            set_counts(&mut resp_packet);
+            log!(Debug, "{}", resp_packet);
+        },
+        (Err(e), _) => {
+            log!(Debug, "Failed to resolve {} {}, {}", question.name, question.rtype, e);
+            resp_packet.header.rcode = ResultCode::ServerFailure;
+        },
+    }
+
+    resp_packet
+}
+
+/// Resolves `question` the way the mode says, caching whatever comes back
+fn lookup_upstream(config: &Config, cache: &Cache, question: &DNSQuestion, cd: bool) -> Result<DNSPacket, String> {
+    match config.mode {
+        Mode::Recursive => lookup_recursively(&config.root_hints, cache, &question.name, question.rtype, cd),
+        Mode::Forwarding => forward(&config.forwarders, &question.name, question.rtype, cd)
+            .inspect(|resp| cache.insert_response(resp, "", Instant::now())),
+        Mode::Authoritative => Err("Only our own zones are served".to_owned()),
+    }
+}
+
+/// `lookup_upstream` in the background, joining the lookup already running
+/// for `question` if there's one. The result is sent over the returned channel
+/// and also lands in the cache whether or not anyone still waits. None when
+/// too many lookups are running already.
+fn lookup_in_background(state: &ServerState, question: &DNSQuestion, cd: bool) -> Option<Receiver<RefreshResult>> {
+    let config = state.config.clone();
+    let cache = Arc::clone(&state.cache);
+    let owned = question.clone();
+
+    state.refreshes.start(&question.name, question.rtype, question.class, move || lookup_upstream(&config, &cache, &owned, cd))
+}
+
+fn set_counts(packet: &mut DNSPacket) {
+    packet.header.ancount = packet.answers.len() as u16;
+    packet.header.nscount = packet.authority.len() as u16;
+    packet.header.arcount = packet.additional.len() as u16;
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{parser::{header::{DNSHeader, DNSHeaderType, Opcode, ResultCode}, packet::{DNSPacket, DNSPacketParser}, question::DNSQuestion, record::{DNSARecord, DNSCNameRecord, DNSRecord, DNSRecordData, DNSSOARecord, TSIGError}, types::{RecordClass, RecordType}, view::DNSPacketView}, server::tsig::{self, TSIGAlgorithm, TSIGKey, TSIGSigner}};
+
+    use std::{io::{Read, Write}, net::{TcpListener, TcpStream}, sync::Arc, thread, time::{Duration, Instant}};
+
+    use crate::server::{cache::{Cache, Trust}, config::{Config, Mode}, pool::{PoolOptions, WorkerPool}, zone::Zone};
+
+    use super::{answer, handle_connection, handle_packet, resolve, serve_tcp, ServerState, SharedState, TcpOptions};
+
+    fn request(opcode: Opcode) -> DNSPacket {
+        DNSPacket {
+            header: DNSHeader {
+                id: 1234,
+                qr: DNSHeaderType::Query,
+                opcode,
+                aa: false,
+                tc: false,
+                rd: false,
+                ra: false,
+                z: 0,
+                ad: false,
+                cd: false,
+                rcode: ResultCode::NoError,
+                qdcount: 1,
+                ancount: 0,
+                nscount: 0,
+                arcount: 0,
             },
+            questions: vec![
+                DNSQuestion {
+                    name: "example.com.".to_owned(),
+                    rtype: RecordType::SOA,
+                    class: RecordClass::IN,
+                },
+            ],
+            answers: vec![],
+            authority: vec![],
+            additional: vec![],
         }
-    } else {
-        resp_packet.header.rcode = ResultCode::FormatError;
-        socket.send_to(&resp_packet.serialize()?, src)
-            .expect("Should send response");
     }
 
-    Ok(())
+    #[test]
+    fn answers_not_implemented_for_unsupported_opcodes() {
+        for opcode in [Opcode::IQuery, Opcode::Status, Opcode::Notify, Opcode::Unknown(9)] {
+            let resp_packet = resolve(request(opcode), &ServerState::default(), false);
+
+            assert_eq!(resp_packet.header.id, 1234);
+            assert_eq!(resp_packet.header.qr, DNSHeaderType::Response);
+            assert_eq!(resp_packet.header.opcode, opcode);
+            assert_eq!(resp_packet.header.rcode, ResultCode::NotImplemented);
+            assert_eq!(resp_packet.questions.len(), 1);
+        }
+    }
+
+    #[test]
+    fn answers_format_error_to_malformed_requests() {
+        let mut req_data = request(Opcode::Query).serialize().unwrap();
+        req_data.truncate(20);
+
+        let resp_data = handle_packet(&req_data, &ServerState::default()).unwrap();
+        let resp_packet = DNSPacketParser::new(&resp_data).parse().unwrap();
+
+        assert_eq!(resp_packet.header.id, 1234);
+        assert_eq!(resp_packet.header.qr, DNSHeaderType::Response);
+        assert_eq!(resp_packet.header.rcode, ResultCode::FormatError);
+        assert!(resp_packet.questions.is_empty());
+
+        // Malformed responses are dropped rather than answered
+        let mut resp_data = resp_data;
+        resp_data.extend_from_slice(&[0xc0]);
+        resp_data[5] = 1;
+        assert!(handle_packet(&resp_data, &ServerState::default()).is_err());
+    }
+
+    #[test]
+    fn answers_format_error_to_looping_and_stray_name_pointers() {
+        // A question for "a." and an additional CNAME whose target points at itself
+        let mut req_data = vec![
+            0x04, 0xd2, 0x01, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01,
+            0x01, b'a', 0x00, 0x00, 0x01, 0x00, 0x01,
+            0x00, 0x00, 0x05, 0x00, 0x01, 0x00, 0x00, 0x0e, 0x10, 0x00, 0x02, 0xc0, 0x1e,
+        ];
+        assert_eq!(req_data.len(), 32);
+
+        for pointer in [0x1e, 0xff] {
+            req_data[31] = pointer;
+
+            let resp_data = handle_packet(&req_data, &ServerState::default()).unwrap();
+            let resp_packet = DNSPacketParser::new(&resp_data).parse().unwrap();
+
+            assert_eq!(resp_packet.header.rcode, ResultCode::FormatError);
+        }
+
+        // Pointing back at the question is fine
+        req_data[31] = 0x0c;
+        let packet = DNSPacketView::new(&req_data).unwrap().to_packet().unwrap();
+        assert_eq!(packet.additional[0].record, DNSRecordData::CNAME(DNSCNameRecord { cname: "a.".to_owned() }));
+    }
+
+    #[test]
+    fn requires_tsig_for_updates() {
+        let key = TSIGKey {
+            name: "update-key.".to_owned(),
+            algorithm: TSIGAlgorithm::HmacSha256,
+            secret: b"secret".to_vec(),
+        };
+
+        let mut state = ServerState::default();
+        state.keyring.insert(key.clone());
+
+        let req_data = request(Opcode::Update).serialize().unwrap();
+        let resp_packet = DNSPacketParser::new(&handle_packet(&req_data, &state).unwrap()).parse().unwrap();
+        assert_eq!(resp_packet.header.rcode, ResultCode::Refused);
+
+        // Signed with a key we don't know about
+        let mut req_packet = request(Opcode::Update);
+        TSIGSigner::new(&TSIGKey { name: "other-key.".to_owned(), ..key.clone() })
+            .sign(&mut req_packet, tsig::now())
+            .unwrap();
+        let req_data = req_packet.serialize().unwrap();
+        let resp_packet = DNSPacketParser::new(&handle_packet(&req_data, &state).unwrap()).parse().unwrap();
+        assert_eq!(resp_packet.header.rcode, ResultCode::NotAuth);
+        assert!(matches!(
+            resp_packet.additional.last().map(|record| &record.record),
+            Some(DNSRecordData::TSIG(tsig)) if tsig.error == TSIGError::BadKey && tsig.mac.is_empty(),
+        ));
+
+        // Properly signed, but we don't host the zone, the response is signed as well
+        let mut req_packet = request(Opcode::Update);
+        TSIGSigner::new(&key).sign(&mut req_packet, tsig::now()).unwrap();
+        let req_data = req_packet.serialize().unwrap();
+        let resp_packet = DNSPacketParser::new(&handle_packet(&req_data, &state).unwrap()).parse().unwrap();
+        assert_eq!(resp_packet.header.rcode, ResultCode::NotAuth);
+        assert!(matches!(
+            resp_packet.additional.last().map(|record| &record.record),
+            Some(DNSRecordData::TSIG(tsig)) if tsig.error == TSIGError::NoError && tsig.mac.len() == 32,
+        ));
+    }
+
+    #[test]
+    fn truncates_responses_that_are_too_large() {
+        let req_data = request(Opcode::Notify).serialize().unwrap();
+        let resp_data = answer(&req_data, &ServerState::default(), 512).unwrap();
+        assert!(! DNSPacketParser::new(&resp_data).parse().unwrap().header.tc);
+
+        let resp_data = answer(&req_data, &ServerState::default(), 12).unwrap();
+        let resp_packet = DNSPacketParser::new(&resp_data).parse().unwrap();
+
+        assert!(resp_packet.header.tc);
+        assert_eq!(resp_packet.header.rcode, ResultCode::NotImplemented);
+        assert_eq!(resp_packet.questions, request(Opcode::Notify).questions);
+    }
+
+    /// Reads one length prefixed message, `None` once the server closed the connection
+    fn read_framed(stream: &mut TcpStream) -> Option<DNSPacket> {
+        let mut len = [0u8; 2];
+        stream.read_exact(&mut len).ok()?;
+
+        let mut data = vec![0u8; u16::from_be_bytes(len) as usize];
+        stream.read_exact(&mut data).unwrap();
+
+        Some(DNSPacketParser::new(&data).parse().unwrap())
+    }
+
+    /// Writes length prefixed `Status` requests with the given ids in one go
+    fn send_pipelined(stream: &mut TcpStream, ids: &[u16]) {
+        let mut data = vec![];
+
+        for id in ids {
+            let mut req_packet = request(Opcode::Status);
+            req_packet.header.id = *id;
+            let req_data = req_packet.serialize().unwrap();
+
+            data.extend_from_slice(&(req_data.len() as u16).to_be_bytes());
+            data.extend_from_slice(&req_data);
+        }
+
+        stream.write_all(&data).unwrap();
+    }
+
+    #[test]
+    fn answers_pipelined_queries_over_tcp() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (server, _) = listener.accept().unwrap();
+
+        let pool = WorkerPool::new(PoolOptions { workers: 2, max_in_flight: 8 });
+
+        // Both queries go out before reading anything back
+        send_pipelined(&mut client, &[1, 2]);
+
+        thread::scope(|scope| {
+            let handle = scope.spawn(|| handle_connection(server, &SharedState::default(), Duration::from_millis(200), &pool));
+
+            // Responses go out as soon as they're ready
+            let mut ids = (0..2)
+                .map(|_| read_framed(&mut client).unwrap())
+                .inspect(|resp_packet| assert_eq!(resp_packet.header.rcode, ResultCode::NotImplemented))
+                .map(|resp_packet| resp_packet.header.id)
+                .collect::<Vec<_>>();
+            ids.sort();
+            assert_eq!(ids, vec![1, 2]);
+
+            // The server hangs up once the connection goes idle
+            assert_eq!(handle.join().unwrap(), Ok(()));
+            assert!(read_framed(&mut client).is_none());
+        });
+    }
+
+    #[test]
+    fn limits_tcp_connections() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let options = TcpOptions { idle_timeout: Duration::from_secs(5), max_connections: 1 };
+
+        // The listener never returns, it's left running until the tests are done
+        thread::spawn(move || serve_tcp(&listener, &SharedState::default(), options, &WorkerPool::new(PoolOptions::default())));
+
+        let req_data = request(Opcode::Status).serialize().unwrap();
+        let mut framed = (req_data.len() as u16).to_be_bytes().to_vec();
+        framed.extend_from_slice(&req_data);
+
+        let mut first = TcpStream::connect(addr).unwrap();
+        first.write_all(&framed).unwrap();
+        assert!(read_framed(&mut first).is_some());
+
+        let mut second = TcpStream::connect(addr).unwrap();
+        second.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        let _ = second.write_all(&framed);
+        assert!(read_framed(&mut second).is_none());
+
+        // Once the first one is gone there's room again
+        drop(first);
+        thread::sleep(Duration::from_millis(100));
+
+        let mut third = TcpStream::connect(addr).unwrap();
+        third.write_all(&framed).unwrap();
+        assert!(read_framed(&mut third).is_some());
+    }
+
+    #[test]
+    fn turns_away_queries_when_overloaded() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (server, _) = listener.accept().unwrap();
+
+        // Nothing may be in flight, every query is answered on the spot
+        let pool = WorkerPool::new(PoolOptions { workers: 1, max_in_flight: 0 });
+
+        send_pipelined(&mut client, &[7]);
+        handle_connection(server, &SharedState::default(), Duration::from_millis(100), &pool).unwrap();
+
+        let resp_packet = read_framed(&mut client).unwrap();
+
+        assert_eq!(resp_packet.header.id, 7);
+        assert_eq!(resp_packet.header.qr, DNSHeaderType::Response);
+        assert_eq!(resp_packet.header.rcode, ResultCode::ServerFailure);
+        assert!(read_framed(&mut client).is_none());
+    }
+
+    #[test]
+    fn answers_from_zones_and_refuses_the_rest_when_authoritative() {
+        let soa = DNSSOARecord {
+            mname: "ns1.example.com.".to_owned(),
+            rname: "hostmaster.example.com.".to_owned(),
+            serial: 1,
+            refresh: 3600,
+            retry: 600,
+            expire: 86400,
+            minimum: 300,
+        };
+
+        let state = ServerState {
+            config: Config { mode: Mode::Authoritative, ..Config::default() },
+            ..ServerState::default()
+        };
+        state.zones.insert(Zone::new("example.com.", vec![DNSRecord {
+            name: "example.com.".to_owned(),
+            rtype: RecordType::SOA,
+            class: RecordClass::IN,
+            ttl: 3600,
+            len: 0,
+            record: DNSRecordData::SOA(soa),
+        }]).unwrap());
+
+        let resp_packet = resolve(request(Opcode::Query), &state, false);
+        assert_eq!(resp_packet.header.rcode, ResultCode::NoError);
+        assert!(resp_packet.header.aa);
+        assert!(! resp_packet.header.ra);
+        assert_eq!(resp_packet.header.ancount, 1);
+
+        let mut req_packet = request(Opcode::Query);
+        req_packet.questions[0].name = "example.org.".to_owned();
+        let resp_packet = resolve(req_packet, &state, false);
+        assert_eq!(resp_packet.header.rcode, ResultCode::Refused);
+        assert!(resp_packet.answers.is_empty());
+    }
+
+    #[test]
+    fn refuses_clients_outside_the_acl() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (server, _) = listener.accept().unwrap();
+
+        let state = ServerState {
+            config: Config { allow: vec!["10.0.0.0/8".parse().unwrap()], ..Config::default() },
+            ..ServerState::default()
+        };
+
+        send_pipelined(&mut client, &[3]);
+        handle_connection(server, &SharedState::from(state), Duration::from_millis(100), &WorkerPool::new(PoolOptions::default())).unwrap();
+
+        let resp_packet = read_framed(&mut client).unwrap();
+
+        assert_eq!(resp_packet.header.id, 3);
+        assert_eq!(resp_packet.header.rcode, ResultCode::Refused);
+    }
+
+    #[test]
+    fn stops_serving_tcp_when_stopping() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let shared = SharedState::default();
+        let pool = WorkerPool::new(PoolOptions::default());
+        let options = TcpOptions { idle_timeout: Duration::from_secs(30), max_connections: 8 };
+
+        thread::scope(|scope| {
+            let handle = scope.spawn(|| serve_tcp(&listener, &shared, options, &pool));
+
+            let mut client = TcpStream::connect(addr).unwrap();
+            send_pipelined(&mut client, &[5]);
+            assert_eq!(read_framed(&mut client).unwrap().header.id, 5);
+
+            // The connection would otherwise stay open for the whole idle timeout
+            shared.stop();
+            handle.join().unwrap();
+
+            assert!(read_framed(&mut client).is_none());
+        });
+    }
+
+    #[test]
+    fn answers_from_the_cache() {
+        let state = ServerState {
+            cache: Arc::new(Cache::new(1 << 20)),
+            // Nothing listens there, resolving anything would fail
+            config: Config { root_hints: vec!["127.0.0.1:9".parse().unwrap()], ..Config::default() },
+            ..ServerState::default()
+        };
+
+        let record = DNSRecord {
+            name: "example.com.".to_owned(),
+            rtype: RecordType::A,
+            class: RecordClass::IN,
+            ttl: 300,
+            len: 0,
+            record: DNSRecordData::A(DNSARecord { ip: [192, 0, 2, 1] }),
+        };
+        state.cache.insert(vec![record.clone()], Trust::Answer, Instant::now());
+
+        let mut req_packet = request(Opcode::Query);
+        req_packet.questions[0].rtype = RecordType::A;
+        let resp_packet = resolve(req_packet, &state, false);
+
+        assert_eq!(resp_packet.header.rcode, ResultCode::NoError);
+        assert!(! resp_packet.header.aa);
+        assert_eq!(resp_packet.header.ancount, 1);
+        assert_eq!(resp_packet.answers[0].record, record.record);
+        assert!(resp_packet.answers[0].ttl <= 300);
+
+        // Names known not to exist come back as such, with the SOA saying so
+        let soa = DNSRecord {
+            name: "example.com.".to_owned(),
+            rtype: RecordType::SOA,
+            class: RecordClass::IN,
+            ttl: 3600,
+            len: 0,
+            record: DNSRecordData::SOA(DNSSOARecord {
+                mname: "ns1.example.com.".to_owned(),
+                rname: "hostmaster.example.com.".to_owned(),
+                serial: 1,
+                refresh: 7200,
+                retry: 3600,
+                expire: 1209600,
+                minimum: 300,
+            }),
+        };
+        state.cache.insert_negative("nope.example.com.", RecordType::A, ResultCode::NameError, soa, Trust::AuthoritativeAnswer, Instant::now());
+
+        let mut req_packet = request(Opcode::Query);
+        req_packet.questions[0].name = "nope.example.com.".to_owned();
+        let resp_packet = resolve(req_packet, &state, false);
+
+        assert_eq!(resp_packet.header.rcode, ResultCode::NameError);
+        assert_eq!(resp_packet.header.ancount, 0);
+        assert_eq!(resp_packet.header.nscount, 1);
+        assert!(resp_packet.authority[0].ttl <= 300);
+    }
+
+    #[test]
+    fn serves_stale_answers_when_upstream_fails() {
+        let state = ServerState {
+            cache: Arc::new(Cache::new(1 << 20)),
+            // Nothing listens there, resolving anything would fail
+            config: Config { root_hints: vec!["127.0.0.1:9".parse().unwrap()], ..Config::default() },
+            ..ServerState::default()
+        };
+        state.cache.set_max_stale(Duration::from_secs(3600));
+
+        let record = DNSRecord {
+            name: "example.com.".to_owned(),
+            rtype: RecordType::A,
+            class: RecordClass::IN,
+            ttl: 60,
+            len: 0,
+            record: DNSRecordData::A(DNSARecord { ip: [192, 0, 2, 1] }),
+        };
+        state.cache.insert(vec![record.clone()], Trust::Answer, Instant::now() - Duration::from_secs(120));
+
+        let mut req_packet = request(Opcode::Query);
+        req_packet.questions[0].rtype = RecordType::A;
+        let resp_packet = resolve(req_packet.clone(), &state, false);
+
+        assert_eq!(resp_packet.header.rcode, ResultCode::NoError);
+        assert_eq!(resp_packet.answers[0].record, record.record);
+        assert_eq!(resp_packet.answers[0].ttl, 30);
+
+        // Upstream servers aren't asked again for a while after failing
+        assert!(state.refreshes.recently_failed("example.com.", RecordType::A, RecordClass::IN, Instant::now()));
+        assert_eq!(resolve(req_packet.clone(), &state, false).answers[0].record, record.record);
+        assert!(state.refreshes.is_empty());
+
+        // Without it, the failure goes through
+        state.cache.set_max_stale(Duration::ZERO);
+        assert_eq!(resolve(req_packet, &state, false).header.rcode, ResultCode::ServerFailure);
+    }
 }
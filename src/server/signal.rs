@@ -0,0 +1,106 @@
+use std::sync::atomic::{AtomicU32, Ordering};
+
+/// Signals the server reacts to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Signal {
+    /// SIGHUP, reload the configuration
+    Hangup,
+
+    /// SIGINT, shut down
+    Interrupt,
+
+    /// SIGTERM, shut down
+    Terminate,
+}
+
+impl Signal {
+    /// Same numbers on Linux, the BSDs and macOS
+    fn number(self) -> i32 {
+        match self {
+            Self::Hangup => 1,
+            Self::Interrupt => 2,
+            Self::Terminate => 15,
+        }
+    }
+}
+
+/// Bit `n` is set once signal `n` arrived, until it's taken
+static PENDING: AtomicU32 = AtomicU32::new(0);
+
+#[cfg(unix)]
+mod unix {
+    use std::sync::atomic::Ordering;
+
+    use super::PENDING;
+
+    type Handler = extern "C" fn(i32);
+
+    extern "C" {
+        fn signal(signum: i32, handler: Handler) -> usize;
+    }
+
+    /// Only touches an atomic, which is all a signal handler may safely do
+    extern "C" fn on_signal(signum: i32) {
+        PENDING.fetch_or(1 << signum, Ordering::SeqCst);
+    }
+
+    pub fn install(signum: i32) -> Result<(), String> {
+        // SAFETY: `on_signal` is async signal safe and lives for the whole program
+        let previous = unsafe { signal(signum, on_signal) };
+
+        // SIG_ERR
+        if previous == usize::MAX {
+            return Err(format!("Failed to install handler for signal {}", signum));
+        }
+
+        Ok(())
+    }
+}
+
+/// Starts catching `signals`, they're then picked up with `take` instead of
+/// killing the process. Does nothing outside of Unix.
+pub fn catch(signals: &[Signal]) -> Result<(), String> {
+    #[cfg(unix)]
+    for signal in signals {
+        unix::install(signal.number())?;
+    }
+
+    #[cfg(not(unix))]
+    let _ = signals;
+
+    Ok(())
+}
+
+/// Whether `signal` arrived since the last call, a signal sent several times
+/// in between is only reported once
+pub fn take(signal: Signal) -> bool {
+    let bit = 1 << signal.number();
+
+    PENDING.fetch_and(! bit, Ordering::SeqCst) & bit != 0
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::{process::{self, Command}, thread, time::{Duration, Instant}};
+
+    use super::{catch, take, Signal};
+
+    #[test]
+    fn catches_signals() {
+        catch(&[Signal::Hangup]).unwrap();
+        assert!(! take(Signal::Hangup));
+
+        let status = Command::new("kill").args(["-HUP", &process::id().to_string()]).status().unwrap();
+        assert!(status.success());
+
+        // Delivered by the time kill returns, but not necessarily handled
+        let deadline = Instant::now() + Duration::from_secs(5);
+
+        while ! take(Signal::Hangup) {
+            assert!(Instant::now() < deadline, "SIGHUP never arrived");
+            thread::yield_now();
+        }
+
+        assert!(! take(Signal::Hangup));
+    }
+}
@@ -0,0 +1,362 @@
+use std::collections::BTreeMap;
+
+/// The subset of TOML used by the configuration file: tables, quoted and
+/// bare keys, strings, integers, booleans and arrays
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Value {
+    String(String),
+    Integer(i64),
+    Boolean(bool),
+    Array(Vec<Value>),
+    Table(Table),
+}
+
+pub type Table = BTreeMap<String, Value>;
+
+impl Value {
+    /// Name of the value's type, for error messages
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Self::String(_) => "string",
+            Self::Integer(_) => "integer",
+            Self::Boolean(_) => "boolean",
+            Self::Array(_) => "array",
+            Self::Table(_) => "table",
+        }
+    }
+}
+
+/// Parses a TOML document into its root table, errors point at the line
+pub fn parse(text: &str) -> Result<Table, String> {
+    let mut parser = Parser { chars: text.chars().collect(), pos: 0, line: 1 };
+
+    parser.parse_document().map_err(|e| format!("line {}: {}", parser.line, e))
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+    line: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += 1;
+
+        if c == '\n' {
+            self.line += 1;
+        }
+
+        Some(c)
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        match self.next() {
+            Some(c) if c == expected => Ok(()),
+            Some(c) => Err(format!("Expected {:?}, got {:?}", expected, c)),
+            None => Err(format!("Expected {:?}, got the end of the file", expected)),
+        }
+    }
+
+    /// Skips spaces and tabs, and newlines and comments too with `newlines`
+    fn skip_whitespace(&mut self, newlines: bool) {
+        while let Some(c) = self.peek() {
+            match c {
+                ' ' | '\t' | '\r' => {},
+                '\n' if newlines => {},
+                '#' => {
+                    while self.peek().is_some_and(|c| c != '\n') {
+                        self.next();
+                    }
+
+                    continue;
+                },
+                _ => return,
+            }
+
+            self.next();
+        }
+    }
+
+    /// Nothing but a comment may follow a key/value pair or a table header
+    fn end_of_line(&mut self) -> Result<(), String> {
+        self.skip_whitespace(false);
+
+        match self.next() {
+            None | Some('\n') => Ok(()),
+            Some(c) => Err(format!("Unexpected {:?} at the end of the line", c)),
+        }
+    }
+
+    fn parse_document(&mut self) -> Result<Table, String> {
+        let mut root = Table::new();
+        let mut current: Vec<String> = vec![];
+        let mut headers: Vec<Vec<String>> = vec![];
+
+        loop {
+            self.skip_whitespace(true);
+
+            match self.peek() {
+                None => return Ok(root),
+                Some('[') => {
+                    self.next();
+                    let path = self.parse_key_path(']')?;
+                    self.expect(']')?;
+                    self.end_of_line()?;
+
+                    table_at(&mut root, &path)?;
+
+                    if headers.contains(&path) {
+                        return Err(format!("Table [{}] is defined twice", path.join(".")));
+                    }
+
+                    headers.push(path.clone());
+                    current = path;
+                },
+                Some(_) => {
+                    let path = self.parse_key_path('=')?;
+                    self.expect('=')?;
+                    self.skip_whitespace(false);
+                    let value = self.parse_value()?;
+                    self.end_of_line()?;
+
+                    let (key, parents) = path.split_last().expect("Key paths are never empty");
+                    let table = table_at(&mut root, &[current.as_slice(), parents].concat())?;
+
+                    if table.insert(key.clone(), value).is_some() {
+                        return Err(format!("Key {} is defined twice", path.join(".")));
+                    }
+                },
+            }
+        }
+    }
+
+    /// Dotted key, bare or quoted, up to `end`
+    fn parse_key_path(&mut self, end: char) -> Result<Vec<String>, String> {
+        let mut path = vec![];
+
+        loop {
+            self.skip_whitespace(false);
+
+            let key = match self.peek() {
+                Some('"') => self.parse_basic_string()?,
+                Some('\'') => self.parse_literal_string()?,
+                _ => {
+                    let start = self.pos;
+
+                    while self.peek().is_some_and(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+                        self.next();
+                    }
+
+                    if start == self.pos {
+                        return Err(format!("Expected a key, got {:?}", self.peek().unwrap_or(end)));
+                    }
+
+                    self.chars[start..self.pos].iter().collect()
+                },
+            };
+
+            path.push(key);
+            self.skip_whitespace(false);
+
+            match self.peek() {
+                Some('.') => { self.next(); },
+                Some(c) if c == end => return Ok(path),
+                Some(c) => return Err(format!("Unexpected {:?} in key", c)),
+                None => return Err("Unexpected end of the file in key".to_owned()),
+            }
+        }
+    }
+
+    fn parse_value(&mut self) -> Result<Value, String> {
+        match self.peek() {
+            Some('"') => Ok(Value::String(self.parse_basic_string()?)),
+            Some('\'') => Ok(Value::String(self.parse_literal_string()?)),
+            Some('[') => self.parse_array(),
+            Some(c) if c == '-' || c == '+' || c.is_ascii_digit() => self.parse_integer(),
+            Some(c) if c.is_ascii_alphabetic() => {
+                let start = self.pos;
+
+                while self.peek().is_some_and(|c| c.is_ascii_alphabetic()) {
+                    self.next();
+                }
+
+                match self.chars[start..self.pos].iter().collect::<String>().as_str() {
+                    "true" => Ok(Value::Boolean(true)),
+                    "false" => Ok(Value::Boolean(false)),
+                    word => Err(format!("Invalid value {}, strings have to be quoted", word)),
+                }
+            },
+            Some(c) => Err(format!("Unexpected {:?}, expected a value", c)),
+            None => Err("Missing value".to_owned()),
+        }
+    }
+
+    fn parse_integer(&mut self) -> Result<Value, String> {
+        let start = self.pos;
+
+        while self.peek().is_some_and(|c| c == '-' || c == '+' || c == '_' || c.is_ascii_alphanumeric()) {
+            self.next();
+        }
+
+        let text = self.chars[start..self.pos].iter().filter(|c| **c != '_').collect::<String>();
+
+        text.parse::<i64>()
+            .map(Value::Integer)
+            .map_err(|_| format!("Invalid integer {}", text))
+    }
+
+    fn parse_array(&mut self) -> Result<Value, String> {
+        self.expect('[')?;
+        let mut values = vec![];
+
+        loop {
+            self.skip_whitespace(true);
+
+            if self.peek() == Some(']') {
+                self.next();
+                return Ok(Value::Array(values));
+            }
+
+            values.push(self.parse_value()?);
+            self.skip_whitespace(true);
+
+            match self.next() {
+                Some(',') => {},
+                Some(']') => return Ok(Value::Array(values)),
+                Some(c) => return Err(format!("Unexpected {:?} in array", c)),
+                None => return Err("Unterminated array".to_owned()),
+            }
+        }
+    }
+
+    fn parse_basic_string(&mut self) -> Result<String, String> {
+        self.expect('"')?;
+        let mut text = String::new();
+
+        loop {
+            // Left unconsumed so that the error points at the string's line
+            let c = match self.peek() {
+                Some('\n') | None => return Err("Unterminated string".to_owned()),
+                Some(c) => c,
+            };
+
+            self.next();
+
+            match c {
+                '"' => return Ok(text),
+                '\\' => {
+                    let c = match self.next() {
+                        Some('n') => '\n',
+                        Some('t') => '\t',
+                        Some('r') => '\r',
+                        Some('"') => '"',
+                        Some('\\') => '\\',
+                        Some('u') => {
+                            let hex = (0..4).filter_map(|_| self.next()).collect::<String>();
+
+                            u32::from_str_radix(&hex, 16)
+                                .ok()
+                                .and_then(char::from_u32)
+                                .ok_or_else(|| format!("Invalid unicode escape \\u{}", hex))?
+                        },
+                        Some(c) => return Err(format!("Invalid escape \\{}", c)),
+                        None => return Err("Unterminated string".to_owned()),
+                    };
+
+                    text.push(c);
+                },
+                c => text.push(c),
+            }
+        }
+    }
+
+    fn parse_literal_string(&mut self) -> Result<String, String> {
+        self.expect('\'')?;
+        let mut text = String::new();
+
+        loop {
+            let c = match self.peek() {
+                Some('\n') | None => return Err("Unterminated string".to_owned()),
+                Some(c) => c,
+            };
+
+            self.next();
+
+            if c == '\'' {
+                return Ok(text);
+            }
+
+            text.push(c);
+        }
+    }
+}
+
+/// The table at `path`, creating the missing ones along the way
+fn table_at<'a>(root: &'a mut Table, path: &[String]) -> Result<&'a mut Table, String> {
+    let mut table = root;
+
+    for (i, key) in path.iter().enumerate() {
+        let value = table.entry(key.clone()).or_insert_with(|| Value::Table(Table::new()));
+
+        table = match value {
+            Value::Table(table) => table,
+            _ => return Err(format!("{} is not a table", path[..=i].join("."))),
+        };
+    }
+
+    Ok(table)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse, Table, Value};
+
+    #[test]
+    fn parses_tables_and_values() {
+        let text = r#"
+# Top level keys come first
+mode = "recursive"   # trailing comment
+
+[listen]
+udp = [
+    "0.0.0.0:53",
+    '[::]:53',   # literal string
+]
+tcp_max_connections = 1_000
+
+[zones]
+"example.com." = "db.\"example\""
+
+[log.file]
+enabled = false
+"#;
+
+        let root = parse(text).unwrap();
+        let table = |entries: Vec<(&str, Value)>| Value::Table(entries.into_iter().map(|(k, v)| (k.to_owned(), v)).collect::<Table>());
+        let string = |s: &str| Value::String(s.to_owned());
+
+        assert_eq!(root.get("mode"), Some(&string("recursive")));
+        assert_eq!(root.get("listen"), Some(&table(vec![
+            ("udp", Value::Array(vec![string("0.0.0.0:53"), string("[::]:53")])),
+            ("tcp_max_connections", Value::Integer(1000)),
+        ])));
+        assert_eq!(root.get("zones"), Some(&table(vec![("example.com.", string("db.\"example\""))])));
+        assert_eq!(root.get("log"), Some(&table(vec![("file", table(vec![("enabled", Value::Boolean(false))]))])));
+    }
+
+    #[test]
+    fn reports_errors_with_their_line() {
+        assert_eq!(parse("a = 1\nb = yes"), Err("line 2: Invalid value yes, strings have to be quoted".to_owned()));
+        assert_eq!(parse("a = 1\na = 2"), Err("line 2: Key a is defined twice".to_owned()));
+        assert_eq!(parse("a = \"open\n"), Err("line 1: Unterminated string".to_owned()));
+        assert_eq!(parse("[a]\n[a]"), Err("line 2: Table [a] is defined twice".to_owned()));
+        assert_eq!(parse("a = 1\n[a]"), Err("line 2: a is not a table".to_owned()));
+        assert_eq!(parse("a = 1 2"), Err("line 1: Unexpected '2' at the end of the line".to_owned()));
+    }
+}
@@ -0,0 +1,718 @@
+use std::{collections::HashMap, fs, str::FromStr, time::{SystemTime, UNIX_EPOCH}};
+
+use crate::{crypto::{base64, hmac::{constant_time_eq, hmac_sha256, hmac_sha512}}, parser::{DomainNameLabel, header::Opcode, packet::DNSPacket, question::DNSQuestionParser, record::{DNSRecord, DNSRecordData, DNSRecordsParser, DNSTSIGRecord, TSIGError}, types::{RecordClass, RecordType}}};
+
+/// Seconds of clock skew we allow on signed messages by default (RFC 8945 10)
+pub const DEFAULT_FUDGE: u16 = 300;
+
+/// How many unsigned messages may follow each other in a TCP stream (RFC 8945 5.3.1)
+const MAX_UNSIGNED_MESSAGES: usize = 99;
+
+/// Seconds since the epoch, as used by the time signed field
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0)
+}
+
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum TSIGAlgorithm {
+    HmacSha256,
+    HmacSha512,
+}
+
+impl TSIGAlgorithm {
+    /// The algorithm name as it appears in TSIG records
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::HmacSha256 => "hmac-sha256.",
+            Self::HmacSha512 => "hmac-sha512.",
+        }
+    }
+
+    fn mac(&self, secret: &[u8], data: &[u8]) -> Vec<u8> {
+        match self {
+            Self::HmacSha256 => hmac_sha256(secret, data),
+            Self::HmacSha512 => hmac_sha512(secret, data),
+        }
+    }
+
+    /// Length of the untruncated MAC
+    fn mac_len(&self) -> usize {
+        match self {
+            Self::HmacSha256 => 32,
+            Self::HmacSha512 => 64,
+        }
+    }
+
+    /// Whether a MAC of `len` bytes is well formed, truncated ones have to
+    /// keep at least half of the hash and 10 bytes (RFC 8945 5.2.2.1)
+    fn valid_mac_len(&self, len: usize) -> bool {
+        len <= self.mac_len() && len >= (self.mac_len() / 2).max(10)
+    }
+}
+
+impl FromStr for TSIGAlgorithm {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim_end_matches('.').to_ascii_lowercase().as_str() {
+            "hmac-sha256" => Ok(Self::HmacSha256),
+            "hmac-sha512" => Ok(Self::HmacSha512),
+            _ => Err(format!("Unsupported TSIG algorithm {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct TSIGKey {
+    /// Name of the key, fully qualified (e.g. update-key.)
+    pub name: String,
+
+    pub algorithm: TSIGAlgorithm,
+
+    pub secret: Vec<u8>,
+}
+
+/// Shared secrets we accept, keyed by their lowercased name
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct TSIGKeyring {
+    keys: HashMap<String, TSIGKey>,
+}
+
+impl TSIGKeyring {
+    pub fn insert(&mut self, mut key: TSIGKey) {
+        if ! key.name.ends_with('.') {
+            key.name.push('.');
+        }
+
+        self.keys.insert(key.name.to_ascii_lowercase(), key);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&TSIGKey> {
+        self.keys.get(&name.to_ascii_lowercase())
+    }
+
+    pub fn load(path: &str) -> Result<Self, String> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read TSIG keys from {}, {}", path, e))?;
+
+        Self::parse(&text).map_err(|e| format!("{}: {}", path, e))
+    }
+
+    /// Parses key statements in the format used by BIND and nsupdate
+    /// ```text
+    /// key "update-key" {
+    ///     algorithm hmac-sha256;
+    ///     secret "c2VjcmV0";
+    /// };
+    /// ```
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut keyring = Self::default();
+        let mut tokens = tokenize_key_file(text).into_iter();
+
+        while let Some(token) = tokens.next() {
+            if token != "key" {
+                return Err(format!("Expected key statement, got {}", token));
+            }
+
+            let name = tokens.next().ok_or("Expected key name")?;
+            let mut algorithm = None;
+            let mut secret = None;
+
+            if tokens.next().as_deref() != Some("{") {
+                return Err(format!("Expected {{ after key {}", name));
+            }
+
+            loop {
+                let clause = tokens.next().ok_or_else(|| format!("Unterminated key {}", name))?;
+
+                if clause == "}" {
+                    break;
+                }
+
+                let value = tokens.next().ok_or_else(|| format!("Expected value for {} in key {}", clause, name))?;
+
+                match clause.as_str() {
+                    "algorithm" => algorithm = Some(value.parse::<TSIGAlgorithm>()?),
+                    "secret" => secret = Some(base64::decode(&value).map_err(|e| format!("Invalid secret in key {}, {}", name, e))?),
+                    _ => return Err(format!("Unknown clause {} in key {}", clause, name)),
+                }
+
+                if tokens.next().as_deref() != Some(";") {
+                    return Err(format!("Expected ; after {} in key {}", clause, name));
+                }
+            }
+
+            if tokens.next().as_deref() != Some(";") {
+                return Err(format!("Expected ; after key {}", name));
+            }
+
+            keyring.insert(TSIGKey {
+                algorithm: algorithm.ok_or_else(|| format!("Missing algorithm for key {}", name))?,
+                secret: secret.ok_or_else(|| format!("Missing secret for key {}", name))?,
+                name,
+            });
+        }
+
+        Ok(keyring)
+    }
+}
+
+fn tokenize_key_file(text: &str) -> Vec<String> {
+    let mut tokens = vec![];
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '{' | '}' | ';' => tokens.push(c.to_string()),
+            '"' => tokens.push(chars.by_ref().take_while(|c| *c != '"').collect()),
+            '#' => { chars.by_ref().find(|c| *c == '\n'); },
+            c if c.is_whitespace() => {},
+            c => {
+                let mut token = c.to_string();
+
+                while let Some(c) = chars.next_if(|c| ! c.is_whitespace() && ! "{};\"".contains(*c)) {
+                    token.push(c);
+                }
+
+                tokens.push(token);
+            },
+        }
+    }
+
+    tokens
+}
+
+/// Appends a MAC prefixed with its length, as used for request and prior MACs
+fn push_mac(data: &mut Vec<u8>, mac: &[u8]) {
+    data.extend_from_slice(&(mac.len() as u16).to_be_bytes());
+    data.extend_from_slice(mac);
+}
+
+/// Appends the TSIG variables (RFC 8945 4.3.3), names are in canonical form
+fn push_variables(data: &mut Vec<u8>, key_name: &str, tsig: &DNSTSIGRecord) -> Result<(), String> {
+    DomainNameLabel::serialize_into(&key_name.to_ascii_lowercase(), data, None)?;
+    data.extend_from_slice(&u16::from(RecordClass::ANY).to_be_bytes());
+    data.extend_from_slice(&0u32.to_be_bytes());
+    DomainNameLabel::serialize_into(&tsig.algorithm.to_ascii_lowercase(), data, None)?;
+    push_timers(data, tsig);
+    data.extend_from_slice(&u16::from(tsig.error).to_be_bytes());
+    data.extend_from_slice(&(tsig.other_data.len() as u16).to_be_bytes());
+    data.extend_from_slice(&tsig.other_data);
+
+    Ok(())
+}
+
+/// Appends the timer variables, which is all subsequent messages of a stream sign
+fn push_timers(data: &mut Vec<u8>, tsig: &DNSTSIGRecord) {
+    data.extend_from_slice(&tsig.time_signed.to_be_bytes()[2..]);
+    data.extend_from_slice(&tsig.fudge.to_be_bytes());
+}
+
+fn tsig_record(key_name: &str, tsig: DNSTSIGRecord) -> DNSRecord {
+    DNSRecord {
+        name: key_name.to_owned(),
+        rtype: RecordType::TSIG,
+        class: RecordClass::ANY,
+        ttl: 0,
+        len: 0,
+        record: DNSRecordData::TSIG(tsig),
+    }
+}
+
+/// Returns the TSIG record of a packet, if any
+fn find_tsig(packet: &DNSPacket) -> Result<Option<(&DNSRecord, &DNSTSIGRecord)>, String> {
+    let tsig_count = packet.additional
+        .iter()
+        .filter(|record| record.rtype == RecordType::TSIG)
+        .count();
+
+    match (tsig_count, packet.additional.last()) {
+        (0, _) => Ok(None),
+        (1, Some(record @ DNSRecord { record: DNSRecordData::TSIG(tsig), class: RecordClass::ANY, ttl: 0, .. })) => {
+            Ok(Some((record, tsig)))
+        },
+        // The TSIG record must be the very last one (RFC 8945 5.1)
+        _ => Err("TSIG record is malformed or not the last record".to_owned()),
+    }
+}
+
+/// Offset of the last additional record (the TSIG) within the raw message
+fn tsig_offset(raw: &[u8], packet: &DNSPacket) -> Result<usize, String> {
+    let mut ptr = 12;
+
+    let (_, len) = DNSQuestionParser::new(raw).parse(packet.questions.len(), ptr)?;
+    ptr += len;
+
+    let records_before = packet.answers.len() + packet.authority.len() + packet.additional.len() - 1;
+    let (_, len) = DNSRecordsParser::new(raw)
+        .with_empty_rdata(packet.header.opcode == Opcode::Update)
+        .parse(records_before, ptr)?;
+    ptr += len;
+
+    Ok(ptr)
+}
+
+/// The message as it was before the TSIG record was added to it
+fn strip_tsig(raw: &[u8], packet: &DNSPacket, original_id: u16) -> Result<Vec<u8>, String> {
+    let mut message = raw[0..tsig_offset(raw, packet)?].to_vec();
+    let arcount = u16::from_be_bytes([message[10], message[11]]) - 1;
+
+    message[0..2].copy_from_slice(&original_id.to_be_bytes());
+    message[10..12].copy_from_slice(&arcount.to_be_bytes());
+
+    Ok(message)
+}
+
+/// Signs outgoing messages. A signer is kept around for the whole TCP stream,
+/// every message after the first one only covers the prior MAC and the timers.
+pub struct TSIGSigner<'key> {
+    key: &'key TSIGKey,
+    prior_mac: Option<Vec<u8>>,
+    first: bool,
+    unsigned: Vec<u8>,
+}
+
+impl<'key> TSIGSigner<'key> {
+    /// Signer for requests
+    pub fn new(key: &'key TSIGKey) -> Self {
+        Self { key, prior_mac: None, first: true, unsigned: vec![] }
+    }
+
+    /// Signer for the responses to a request carrying `request_mac`
+    pub fn for_response(key: &'key TSIGKey, request_mac: &[u8]) -> Self {
+        Self { prior_mac: Some(request_mac.to_vec()), ..Self::new(key) }
+    }
+
+    /// Sends a message of the stream without signing it, it will be covered
+    /// by the signature of the next signed message
+    pub fn skip(&mut self, packet: &DNSPacket) -> Result<Vec<u8>, String> {
+        let data = packet.serialize()?;
+        self.unsigned.extend_from_slice(&data);
+
+        Ok(data)
+    }
+
+    /// The MAC of the last signed message
+    pub fn last_mac(&self) -> Option<&[u8]> {
+        self.prior_mac.as_deref()
+    }
+
+    pub fn sign(&mut self, packet: &mut DNSPacket, time_signed: u64) -> Result<(), String> {
+        self.sign_with_error(packet, time_signed, TSIGError::NoError, vec![])
+    }
+
+    /// Signs a message carrying a TSIG error, `other_data` holds the server's
+    /// time for BADTIME
+    pub fn sign_with_error(
+        &mut self,
+        packet: &mut DNSPacket,
+        time_signed: u64,
+        error: TSIGError,
+        other_data: Vec<u8>,
+    ) -> Result<(), String> {
+        let mut tsig = DNSTSIGRecord {
+            algorithm: self.key.algorithm.name().to_owned(),
+            time_signed,
+            fudge: DEFAULT_FUDGE,
+            mac: vec![],
+            original_id: packet.header.id,
+            error,
+            other_data,
+        };
+
+        let mut data = vec![];
+
+        if let Some(ref prior_mac) = self.prior_mac {
+            push_mac(&mut data, prior_mac);
+        }
+
+        data.append(&mut self.unsigned);
+        data.extend_from_slice(&packet.serialize()?);
+
+        if self.first {
+            push_variables(&mut data, &self.key.name, &tsig)?;
+        } else {
+            push_timers(&mut data, &tsig);
+        }
+
+        tsig.mac = self.key.algorithm.mac(&self.key.secret, &data);
+
+        self.prior_mac = Some(tsig.mac.clone());
+        self.first = false;
+
+        packet.additional.push(tsig_record(&self.key.name, tsig));
+        packet.header.arcount += 1;
+
+        Ok(())
+    }
+}
+
+/// Verifies incoming messages, like the signer a verifier follows a whole
+/// TCP stream and accepts up to 99 unsigned messages between signed ones
+pub struct TSIGVerifier<'key> {
+    key: &'key TSIGKey,
+    prior_mac: Option<Vec<u8>>,
+    first: bool,
+    unsigned: Vec<u8>,
+    unsigned_count: usize,
+}
+
+impl<'key> TSIGVerifier<'key> {
+    /// Verifier for requests
+    pub fn new(key: &'key TSIGKey) -> Self {
+        Self { key, prior_mac: None, first: true, unsigned: vec![], unsigned_count: 0 }
+    }
+
+    /// Verifier for the responses to a request we signed with `request_mac`
+    pub fn for_response(key: &'key TSIGKey, request_mac: &[u8]) -> Self {
+        Self { prior_mac: Some(request_mac.to_vec()), ..Self::new(key) }
+    }
+
+    /// The MAC of the last verified message
+    pub fn last_mac(&self) -> Option<&[u8]> {
+        self.prior_mac.as_deref()
+    }
+
+    pub fn verify(&mut self, raw: &[u8], packet: &DNSPacket, now: u64) -> Result<(), TSIGError> {
+        let (record, tsig) = match find_tsig(packet) {
+            Ok(Some(tsig)) => tsig,
+            Ok(None) if ! self.first && self.unsigned_count < MAX_UNSIGNED_MESSAGES => {
+                self.unsigned.extend_from_slice(raw);
+                self.unsigned_count += 1;
+
+                return Ok(());
+            },
+            _ => return Err(TSIGError::BadSig),
+        };
+
+        let algorithm = tsig.algorithm.parse::<TSIGAlgorithm>();
+
+        if ! record.name.eq_ignore_ascii_case(&self.key.name) || algorithm != Ok(self.key.algorithm) {
+            return Err(TSIGError::BadKey);
+        }
+
+        // Requests are answered with FORMERR for these before getting here
+        if ! self.key.algorithm.valid_mac_len(tsig.mac.len()) {
+            return Err(TSIGError::BadSig);
+        }
+
+        let mut data = vec![];
+
+        if let Some(ref prior_mac) = self.prior_mac {
+            push_mac(&mut data, prior_mac);
+        }
+
+        data.append(&mut self.unsigned);
+        data.extend_from_slice(&strip_tsig(raw, packet, tsig.original_id).map_err(|_| TSIGError::BadSig)?);
+
+        if self.first {
+            push_variables(&mut data, &self.key.name, tsig).map_err(|_| TSIGError::BadSig)?;
+        } else {
+            push_timers(&mut data, tsig);
+        }
+
+        let expected_mac = self.key.algorithm.mac(&self.key.secret, &data);
+
+        if ! constant_time_eq(&expected_mac[0..tsig.mac.len()], &tsig.mac) {
+            return Err(TSIGError::BadSig);
+        }
+
+        // We never truncate our MACs and, as local policy, don't take truncated ones either
+        if tsig.mac.len() < expected_mac.len() {
+            return Err(TSIGError::BadTrunc);
+        }
+
+        self.prior_mac = Some(tsig.mac.clone());
+        self.first = false;
+        self.unsigned_count = 0;
+
+        if now.abs_diff(tsig.time_signed) > tsig.fudge as u64 {
+            return Err(TSIGError::BadTime);
+        }
+
+        Ok(())
+    }
+}
+
+/// A request whose TSIG was verified, the response has to be signed with the same key
+#[derive(Debug, PartialEq, Eq)]
+pub struct SignedRequest {
+    pub key: TSIGKey,
+    pub mac: Vec<u8>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum TSIGVerifyError {
+    /// The TSIG record is malformed or misplaced, answered with FORMERR
+    FormatError,
+
+    /// The signature was rejected, answered with NOTAUTH and `error` in the TSIG record
+    Rejected {
+        error: TSIGError,
+        key_name: String,
+        request: Box<DNSTSIGRecord>,
+        key: Option<TSIGKey>,
+    },
+}
+
+/// Verifies the TSIG of a request (RFC 8945 5.2), `Ok(None)` means the request isn't signed
+pub fn verify_request(
+    raw: &[u8],
+    packet: &DNSPacket,
+    keyring: &TSIGKeyring,
+    now: u64,
+) -> Result<Option<SignedRequest>, TSIGVerifyError> {
+    let (record, tsig) = match find_tsig(packet) {
+        Ok(Some(tsig)) => tsig,
+        Ok(None) => return Ok(None),
+        Err(_) => return Err(TSIGVerifyError::FormatError),
+    };
+
+    let rejected = |error, key: Option<&TSIGKey>| TSIGVerifyError::Rejected {
+        error,
+        key_name: record.name.clone(),
+        request: Box::new(tsig.clone()),
+        key: key.cloned(),
+    };
+
+    let key = match keyring.get(&record.name) {
+        Some(key) if tsig.algorithm.parse::<TSIGAlgorithm>() == Ok(key.algorithm) => key,
+        _ => return Err(rejected(TSIGError::BadKey, None)),
+    };
+
+    // A MAC of the wrong length is malformed rather than a bad signature (RFC 8945 5.2.2.1)
+    if ! key.algorithm.valid_mac_len(tsig.mac.len()) {
+        return Err(TSIGVerifyError::FormatError);
+    }
+
+    match TSIGVerifier::new(key).verify(raw, packet, now) {
+        Ok(()) => Ok(Some(SignedRequest { key: key.clone(), mac: tsig.mac.clone() })),
+        // The MAC was valid, so the error response can be signed
+        Err(error @ (TSIGError::BadTime | TSIGError::BadTrunc)) => Err(rejected(error, Some(key))),
+        Err(error) => Err(rejected(error, None)),
+    }
+}
+
+/// Attaches the TSIG record of an error response (RFC 8945 5.3.2), BADTIME
+/// responses are signed and carry our time, others go out unsigned
+pub fn attach_error(packet: &mut DNSPacket, failure: TSIGVerifyError, now: u64) -> Result<(), String> {
+    let TSIGVerifyError::Rejected { error, key_name, request, key } = failure else {
+        return Ok(());
+    };
+
+    match key {
+        Some(key) => {
+            TSIGSigner::for_response(&key, &request.mac)
+                .sign_with_error(packet, request.time_signed, error, now.to_be_bytes()[2..].to_vec())
+        },
+        None => {
+            let tsig = DNSTSIGRecord {
+                mac: vec![],
+                original_id: packet.header.id,
+                error,
+                other_data: vec![],
+                ..*request
+            };
+
+            packet.additional.push(tsig_record(&key_name, tsig));
+            packet.header.arcount += 1;
+
+            Ok(())
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::{header::{DNSHeader, DNSHeaderType, Opcode, ResultCode}, packet::{DNSPacket, DNSPacketParser}, question::DNSQuestion, record::{DNSARecord, DNSRecord, DNSRecordData, TSIGError}, types::{RecordClass, RecordType}};
+
+    use super::{verify_request, TSIGAlgorithm, TSIGKey, TSIGKeyring, TSIGSigner, TSIGVerifier, TSIGVerifyError};
+
+    fn keyring() -> TSIGKeyring {
+        TSIGKeyring::parse(r#"
+            # Used by the DHCP server
+            key "update-key" {
+                algorithm hmac-sha256;
+                secret "c2VjcmV0LXNlY3JldC1zZWNyZXQ=";
+            };
+            key other-key { algorithm hmac-sha512; secret "b3RoZXI="; };
+        "#).unwrap()
+    }
+
+    fn packet(id: u16) -> DNSPacket {
+        DNSPacket {
+            header: DNSHeader {
+                id,
+                qr: DNSHeaderType::Query,
+                opcode: Opcode::Update,
+                aa: false,
+                tc: false,
+                rd: false,
+                ra: false,
+                z: 0,
+                ad: false,
+                cd: false,
+                rcode: ResultCode::NoError,
+                qdcount: 1,
+                ancount: 0,
+                nscount: 1,
+                arcount: 0,
+            },
+            questions: vec![DNSQuestion { name: "example.com.".to_owned(), rtype: RecordType::SOA, class: RecordClass::IN }],
+            answers: vec![],
+            authority: vec![DNSRecord {
+                name: "host.example.com.".to_owned(),
+                rtype: RecordType::A,
+                class: RecordClass::IN,
+                ttl: 300,
+                len: 4,
+                record: DNSRecordData::A(DNSARecord { ip: [10, 0, 0, 1] }),
+            }],
+            additional: vec![],
+        }
+    }
+
+    fn signed(key: &TSIGKey, time_signed: u64) -> Vec<u8> {
+        let mut packet = packet(42);
+        TSIGSigner::new(key).sign(&mut packet, time_signed).unwrap();
+
+        packet.serialize().unwrap()
+    }
+
+    #[test]
+    fn parses_key_files() {
+        let keyring = keyring();
+        let key = keyring.get("Update-Key.").unwrap();
+
+        assert_eq!(key.algorithm, TSIGAlgorithm::HmacSha256);
+        assert_eq!(key.secret, b"secret-secret-secret".to_vec());
+        assert_eq!(keyring.get("other-key.").unwrap().algorithm, TSIGAlgorithm::HmacSha512);
+        assert!(TSIGKeyring::parse("key \"broken\" { algorithm hmac-md5; };").is_err());
+    }
+
+    #[test]
+    fn verifies_signed_requests() {
+        let keyring = keyring();
+        let raw = signed(keyring.get("update-key.").unwrap(), 1_000);
+        let signed_packet = DNSPacketParser::new(&raw).parse().unwrap();
+
+        let request = verify_request(&raw, &signed_packet, &keyring, 1_100).unwrap().unwrap();
+        assert_eq!(request.key.name, "update-key.");
+        assert_eq!(request.mac.len(), 32);
+
+        let unsigned = packet(42);
+        let raw = unsigned.serialize().unwrap();
+        assert_eq!(verify_request(&raw, &unsigned, &keyring, 1_100), Ok(None));
+    }
+
+    #[test]
+    fn rejects_bad_signatures_keys_and_times() {
+        let keyring = keyring();
+        let key = keyring.get("update-key.").unwrap();
+
+        let mut raw = signed(key, 1_000);
+        // Flip a bit of the A record
+        let ip_pos = raw.windows(4).position(|w| w == [10, 0, 0, 1]).unwrap();
+        raw[ip_pos] = 11;
+        let packet = DNSPacketParser::new(&raw).parse().unwrap();
+        assert!(matches!(
+            verify_request(&raw, &packet, &keyring, 1_000),
+            Err(TSIGVerifyError::Rejected { error: TSIGError::BadSig, .. }),
+        ));
+
+        let unknown_key = TSIGKey { name: "nope.".to_owned(), ..key.clone() };
+        let raw = signed(&unknown_key, 1_000);
+        let packet = DNSPacketParser::new(&raw).parse().unwrap();
+        assert!(matches!(
+            verify_request(&raw, &packet, &keyring, 1_000),
+            Err(TSIGVerifyError::Rejected { error: TSIGError::BadKey, .. }),
+        ));
+
+        let raw = signed(key, 1_000);
+        let packet = DNSPacketParser::new(&raw).parse().unwrap();
+        assert!(matches!(
+            verify_request(&raw, &packet, &keyring, 2_000),
+            Err(TSIGVerifyError::Rejected { error: TSIGError::BadTime, key: Some(_), .. }),
+        ));
+    }
+
+    #[test]
+    fn rejects_truncated_and_malformed_macs() {
+        let keyring = keyring();
+        let key = keyring.get("update-key.").unwrap();
+
+        // The signed request with its MAC cut or padded to `len` bytes
+        let with_mac_len = |len: usize| {
+            let mut packet = DNSPacketParser::new(&signed(key, 1_000)).parse().unwrap();
+            let DNSRecordData::TSIG(ref mut tsig) = packet.additional[0].record else {
+                unreachable!();
+            };
+            tsig.mac.resize(len, 0);
+
+            let raw = packet.serialize().unwrap();
+            (verify_request(&raw, &DNSPacketParser::new(&raw).parse().unwrap(), &keyring, 1_000), raw)
+        };
+
+        // Well formed, but shorter than we take
+        assert!(matches!(
+            with_mac_len(16).0,
+            Err(TSIGVerifyError::Rejected { error: TSIGError::BadTrunc, key: Some(_), .. }),
+        ));
+
+        for len in [15, 10, 33] {
+            assert_eq!(with_mac_len(len).0, Err(TSIGVerifyError::FormatError));
+        }
+
+        // Truncation doesn't hide a bad signature
+        let (_, mut raw) = with_mac_len(16);
+        let ip_pos = raw.windows(4).position(|w| w == [10, 0, 0, 1]).unwrap();
+        raw[ip_pos] = 11;
+        let packet = DNSPacketParser::new(&raw).parse().unwrap();
+        assert!(matches!(
+            verify_request(&raw, &packet, &keyring, 1_000),
+            Err(TSIGVerifyError::Rejected { error: TSIGError::BadSig, key: None, .. }),
+        ));
+    }
+
+    #[test]
+    fn rejects_malformed_secrets() {
+        for secret in ["c2VjcmV0", "c2VjcmV0=", "c2VjcmV", "c2VjcmV0LX=="] {
+            let result = TSIGKeyring::parse(&format!("key k {{ algorithm hmac-sha256; secret \"{}\"; }};", secret));
+            assert_eq!(result.is_ok(), secret == "c2VjcmV0", "{}", secret);
+        }
+    }
+
+    #[test]
+    fn verifies_multi_message_streams() {
+        let keyring = keyring();
+        let key = keyring.get("other-key.").unwrap();
+        let request_mac = vec![7; 64];
+
+        let mut signer = TSIGSigner::for_response(key, &request_mac);
+        let mut verifier = TSIGVerifier::for_response(key, &request_mac);
+
+        let mut first = packet(1);
+        signer.sign(&mut first, 1_000).unwrap();
+
+        // Unsigned messages in the middle are covered by the next signature
+        let middle = signer.skip(&packet(1)).unwrap();
+
+        let mut last = packet(1);
+        signer.sign(&mut last, 1_001).unwrap();
+
+        for raw in [first.serialize().unwrap(), middle.clone(), last.serialize().unwrap()] {
+            let packet = DNSPacketParser::new(&raw).parse().unwrap();
+            assert_eq!(verifier.verify(&raw, &packet, 1_001), Ok(()));
+        }
+
+        assert_eq!(verifier.last_mac(), signer.last_mac());
+
+        // The stream must start with a signed message
+        let mut verifier = TSIGVerifier::for_response(key, &request_mac);
+        let packet = DNSPacketParser::new(&middle).parse().unwrap();
+        assert_eq!(verifier.verify(&middle, &packet, 1_001), Err(TSIGError::BadSig));
+    }
+}
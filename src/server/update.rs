@@ -0,0 +1,408 @@
+use crate::parser::{header::ResultCode, record::{DNSRecord, DNSRecordData}, types::{RecordClass, RecordType}, update::DNSUpdate};
+
+use super::zone::{names_eq, Zone, ZoneStore};
+
+/// Applies a dynamic update (RFC 2136 section 3) to one of the hosted zones
+///
+/// The prerequisites and the update section are checked as a whole before
+/// anything is touched, the zone is then swapped with its updated copy while
+/// holding the store's write lock, so other readers either see all of the
+/// changes or none of them.
+pub fn apply_update(zones: &ZoneStore, update: &DNSUpdate) -> ResultCode {
+    let mut zones = zones.write();
+    let key = update.zone.name.to_ascii_lowercase();
+
+    let zone = match zones.get(&key) {
+        Some(zone) if zone.class() == update.zone.class => zone,
+        _ => return ResultCode::NotAuth,
+    };
+
+    if let Err(rcode) = check_prerequisites(zone, update.prerequisites) {
+        return rcode;
+    }
+
+    if let Err(rcode) = prescan_updates(zone, update.updates) {
+        return rcode;
+    }
+
+    let mut updated_zone = zone.clone();
+    let soa_replaced = apply_updates(&mut updated_zone, update.updates);
+
+    if updated_zone != *zone {
+        if ! soa_replaced {
+            bump_serial(&mut updated_zone);
+        }
+
+        zones.insert(key, updated_zone);
+    }
+
+    ResultCode::NoError
+}
+
+/// Serial number comparison (RFC 1982 section 3.2)
+pub fn serial_gt(a: u32, b: u32) -> bool {
+    (a < b && b - a > (1 << 31)) || (a > b && a - b < (1 << 31))
+}
+
+fn is_meta_type(rtype: RecordType) -> bool {
+    matches!(rtype, RecordType::AXFR | RecordType::IXFR | RecordType::MAILA | RecordType::MAILB)
+}
+
+/// RFC 2136 section 3.2
+fn check_prerequisites(zone: &Zone, prerequisites: &[DNSRecord]) -> Result<(), ResultCode> {
+    let mut rrsets: Vec<(&str, RecordType, Vec<&DNSRecordData>)> = vec![];
+
+    for rr in prerequisites {
+        if rr.ttl != 0 {
+            return Err(ResultCode::FormatError);
+        }
+
+        if ! zone.contains(&rr.name) {
+            return Err(ResultCode::NotZone);
+        }
+
+        match rr.class {
+            RecordClass::ANY => {
+                if rr.record != DNSRecordData::Empty {
+                    return Err(ResultCode::FormatError);
+                }
+
+                if rr.rtype == RecordType::ANY {
+                    if ! zone.name_exists(&rr.name) {
+                        return Err(ResultCode::NameError);
+                    }
+                } else if zone.rrset(&rr.name, rr.rtype).next().is_none() {
+                    return Err(ResultCode::NXRRSet);
+                }
+            },
+            RecordClass::NONE => {
+                if rr.record != DNSRecordData::Empty {
+                    return Err(ResultCode::FormatError);
+                }
+
+                if rr.rtype == RecordType::ANY {
+                    if zone.name_exists(&rr.name) {
+                        return Err(ResultCode::YXDomain);
+                    }
+                } else if zone.rrset(&rr.name, rr.rtype).next().is_some() {
+                    return Err(ResultCode::YXRRSet);
+                }
+            },
+            class if class == zone.class() => {
+                match rrsets.iter_mut().find(|(name, rtype, _)| *rtype == rr.rtype && names_eq(name, &rr.name)) {
+                    Some((_, _, rdatas)) => rdatas.push(&rr.record),
+                    None => rrsets.push((&rr.name, rr.rtype, vec![&rr.record])),
+                }
+            },
+            _ => return Err(ResultCode::FormatError),
+        }
+    }
+
+    // Value dependent prerequisites must match the whole RRset exactly
+    for (name, rtype, rdatas) in rrsets {
+        let zone_rdatas = zone.rrset(name, rtype)
+            .map(|record| &record.record)
+            .collect::<Vec<_>>();
+
+        let matches = zone_rdatas.iter().all(|rdata| rdatas.contains(rdata))
+            && rdatas.iter().all(|rdata| zone_rdatas.contains(rdata));
+
+        if ! matches {
+            return Err(ResultCode::NXRRSet);
+        }
+    }
+
+    Ok(())
+}
+
+/// RFC 2136 section 3.4.1
+fn prescan_updates(zone: &Zone, updates: &[DNSRecord]) -> Result<(), ResultCode> {
+    for rr in updates {
+        if ! zone.contains(&rr.name) {
+            return Err(ResultCode::NotZone);
+        }
+
+        let valid = match rr.class {
+            RecordClass::ANY => {
+                rr.ttl == 0
+                    && rr.record == DNSRecordData::Empty
+                    && ! is_meta_type(rr.rtype)
+            },
+            RecordClass::NONE => {
+                rr.ttl == 0
+                    && rr.rtype != RecordType::ANY
+                    && ! is_meta_type(rr.rtype)
+            },
+            class if class == zone.class() => {
+                rr.record != DNSRecordData::Empty
+                    && rr.rtype != RecordType::ANY
+                    && ! is_meta_type(rr.rtype)
+            },
+            _ => false,
+        };
+
+        if ! valid {
+            return Err(ResultCode::FormatError);
+        }
+    }
+
+    Ok(())
+}
+
+/// RFC 2136 section 3.4.2, returns whether the SOA was replaced by the update
+fn apply_updates(zone: &mut Zone, updates: &[DNSRecord]) -> bool {
+    let origin = zone.origin().to_owned();
+    let zone_class = zone.class();
+    let mut soa_replaced = false;
+
+    for rr in updates {
+        let at_apex = names_eq(&rr.name, &origin);
+
+        match rr.class {
+            class if class == zone_class => {
+                let records = zone.records_mut();
+                let at_name = || records.iter().filter(|record| names_eq(&record.name, &rr.name));
+
+                // CNAMEs can't coexist with other data
+                if rr.rtype == RecordType::CNAME {
+                    if at_name().any(|record| record.rtype != RecordType::CNAME) {
+                        continue;
+                    }
+                } else if at_name().any(|record| record.rtype == RecordType::CNAME) {
+                    continue;
+                }
+
+                if rr.rtype == RecordType::SOA {
+                    let (DNSRecordData::SOA(new_soa), true) = (&rr.record, at_apex) else {
+                        continue;
+                    };
+
+                    let soa = records
+                        .iter_mut()
+                        .find(|record| record.rtype == RecordType::SOA)
+                        .expect("Zone should always have an SOA record");
+
+                    if let DNSRecordData::SOA(ref current_soa) = soa.record {
+                        if ! serial_gt(new_soa.serial, current_soa.serial) {
+                            continue;
+                        }
+                    }
+
+                    *soa = rr.clone();
+                    soa_replaced = true;
+
+                    continue;
+                }
+
+                if rr.rtype == RecordType::CNAME {
+                    records.retain(|record| ! (record.rtype == RecordType::CNAME && names_eq(&record.name, &rr.name)));
+                }
+
+                let existing = records
+                    .iter_mut()
+                    .find(|record| {
+                        record.rtype == rr.rtype
+                            && record.record == rr.record
+                            && names_eq(&record.name, &rr.name)
+                    });
+
+                match existing {
+                    Some(record) => record.ttl = rr.ttl,
+                    None => records.push(rr.clone()),
+                }
+            },
+            RecordClass::ANY => {
+                let records = zone.records_mut();
+
+                if rr.rtype == RecordType::ANY {
+                    records.retain(|record| {
+                        ! names_eq(&record.name, &rr.name)
+                            || (at_apex && matches!(record.rtype, RecordType::SOA | RecordType::NS))
+                    });
+                } else if ! (at_apex && matches!(rr.rtype, RecordType::SOA | RecordType::NS)) {
+                    records.retain(|record| ! (record.rtype == rr.rtype && names_eq(&record.name, &rr.name)));
+                }
+            },
+            RecordClass::NONE => {
+                if rr.rtype == RecordType::SOA {
+                    continue;
+                }
+
+                let records = zone.records_mut();
+
+                // The zone must keep at least one NS at its apex
+                if at_apex && rr.rtype == RecordType::NS {
+                    let ns_count = records
+                        .iter()
+                        .filter(|record| record.rtype == RecordType::NS && names_eq(&record.name, &rr.name))
+                        .count();
+
+                    if ns_count <= 1 {
+                        continue;
+                    }
+                }
+
+                records.retain(|record| {
+                    ! (record.rtype == rr.rtype
+                        && record.record == rr.record
+                        && names_eq(&record.name, &rr.name))
+                });
+            },
+            _ => {},
+        }
+    }
+
+    soa_replaced
+}
+
+fn bump_serial(zone: &mut Zone) {
+    let soa = zone.records_mut()
+        .iter_mut()
+        .find_map(|record| match record.record {
+            DNSRecordData::SOA(ref mut soa) => Some(soa),
+            _ => None,
+        })
+        .expect("Zone should always have an SOA record");
+
+    soa.serial = soa.serial.wrapping_add(1);
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::{header::{DNSHeader, DNSHeaderType, Opcode, ResultCode}, packet::DNSPacket, question::DNSQuestion, record::{DNSARecord, DNSNSRecord, DNSRecord, DNSRecordData, DNSSOARecord}, types::{RecordClass, RecordType}, update::DNSUpdate};
+    use crate::server::zone::{Zone, ZoneStore};
+
+    use super::{apply_update, serial_gt};
+
+    fn record(name: &str, rtype: RecordType, class: RecordClass, ttl: u32, record: DNSRecordData) -> DNSRecord {
+        DNSRecord { name: name.to_owned(), rtype, class, ttl, len: 0, record }
+    }
+
+    fn a(name: &str, class: RecordClass, ttl: u32, ip: [u8; 4]) -> DNSRecord {
+        record(name, RecordType::A, class, ttl, DNSRecordData::A(DNSARecord { ip }))
+    }
+
+    fn store() -> ZoneStore {
+        let soa = DNSSOARecord {
+            mname: "ns1.example.com.".to_owned(),
+            rname: "hostmaster.example.com.".to_owned(),
+            serial: 10,
+            refresh: 3600,
+            retry: 600,
+            expire: 86400,
+            minimum: 300,
+        };
+
+        let zone = Zone::new("example.com.", vec![
+            record("example.com.", RecordType::SOA, RecordClass::IN, 3600, DNSRecordData::SOA(soa)),
+            record("example.com.", RecordType::NS, RecordClass::IN, 3600, DNSRecordData::NS(DNSNSRecord { nsdname: "ns1.example.com.".to_owned() })),
+            a("ns1.example.com.", RecordClass::IN, 3600, [10, 0, 0, 53]),
+        ]).unwrap();
+
+        let store = ZoneStore::default();
+        store.insert(zone);
+
+        store
+    }
+
+    fn update_packet(zone: &str, prerequisites: Vec<DNSRecord>, updates: Vec<DNSRecord>) -> DNSPacket {
+        DNSPacket {
+            header: DNSHeader {
+                id: 1,
+                qr: DNSHeaderType::Query,
+                opcode: Opcode::Update,
+                aa: false,
+                tc: false,
+                rd: false,
+                ra: false,
+                z: 0,
+                ad: false,
+                cd: false,
+                rcode: ResultCode::NoError,
+                qdcount: 1,
+                ancount: prerequisites.len() as u16,
+                nscount: updates.len() as u16,
+                arcount: 0,
+            },
+            questions: vec![DNSQuestion { name: zone.to_owned(), rtype: RecordType::SOA, class: RecordClass::IN }],
+            answers: prerequisites,
+            authority: updates,
+            additional: vec![],
+        }
+    }
+
+    fn serial(store: &ZoneStore) -> u32 {
+        store.get("example.com.").unwrap().soa().serial
+    }
+
+    #[test]
+    fn adds_records_and_bumps_serial() {
+        let store = store();
+        let packet = update_packet(
+            "example.com.",
+            vec![record("host.example.com.", RecordType::A, RecordClass::NONE, 0, DNSRecordData::Empty)],
+            vec![a("host.example.com.", RecordClass::IN, 300, [10, 0, 0, 1])],
+        );
+
+        assert_eq!(apply_update(&store, &DNSUpdate::new(&packet).unwrap()), ResultCode::NoError);
+        assert_eq!(store.get("example.com.").unwrap().rrset("host.example.com.", RecordType::A).count(), 1);
+        assert_eq!(serial(&store), 11);
+
+        // The prerequisite doesn't hold anymore
+        assert_eq!(apply_update(&store, &DNSUpdate::new(&packet).unwrap()), ResultCode::YXRRSet);
+        assert_eq!(serial(&store), 11);
+    }
+
+    #[test]
+    fn leaves_zone_untouched_when_any_update_is_invalid() {
+        let store = store();
+        let packet = update_packet(
+            "example.com.",
+            vec![],
+            vec![
+                a("host.example.com.", RecordClass::IN, 300, [10, 0, 0, 1]),
+                a("host.example.org.", RecordClass::IN, 300, [10, 0, 0, 2]),
+            ],
+        );
+
+        assert_eq!(apply_update(&store, &DNSUpdate::new(&packet).unwrap()), ResultCode::NotZone);
+        assert_eq!(store.get("example.com.").unwrap().rrset("host.example.com.", RecordType::A).count(), 0);
+        assert_eq!(serial(&store), 10);
+    }
+
+    #[test]
+    fn deletes_rrsets_but_keeps_apex_ns() {
+        let store = store();
+        let packet = update_packet(
+            "example.com.",
+            vec![record("ns1.example.com.", RecordType::A, RecordClass::ANY, 0, DNSRecordData::Empty)],
+            vec![
+                record("ns1.example.com.", RecordType::A, RecordClass::ANY, 0, DNSRecordData::Empty),
+                record("example.com.", RecordType::ANY, RecordClass::ANY, 0, DNSRecordData::Empty),
+            ],
+        );
+
+        assert_eq!(apply_update(&store, &DNSUpdate::new(&packet).unwrap()), ResultCode::NoError);
+
+        let zone = store.get("example.com.").unwrap();
+        assert_eq!(zone.rrset("ns1.example.com.", RecordType::A).count(), 0);
+        assert_eq!(zone.rrset("example.com.", RecordType::NS).count(), 1);
+        assert_eq!(zone.soa().serial, 11);
+    }
+
+    #[test]
+    fn refuses_updates_for_zones_not_hosted() {
+        let store = store();
+        let packet = update_packet("example.org.", vec![], vec![]);
+
+        assert_eq!(apply_update(&store, &DNSUpdate::new(&packet).unwrap()), ResultCode::NotAuth);
+    }
+
+    #[test]
+    fn compares_serials() {
+        assert!(serial_gt(11, 10));
+        assert!(serial_gt(0, u32::MAX));
+        assert!(! serial_gt(10, 10));
+        assert!(! serial_gt(10, 11));
+    }
+}
@@ -0,0 +1,394 @@
+use std::{collections::HashMap, sync::{RwLock, RwLockReadGuard, RwLockWriteGuard}};
+
+use crate::{parser::{header::ResultCode, record::{DNSRecord, DNSRecordData, DNSSOARecord}, types::{RecordClass, RecordType}, zone_file::{normalize_origin, ZoneFileParser, ZoneFileWriter}}, server::{log::log, update::serial_gt}};
+
+/// Longest CNAME chain followed inside a zone before giving up on it
+const MAX_CNAME_CHAIN: usize = 8;
+
+/// Compares two domain names, case insensitively (RFC 4343)
+pub fn names_eq(a: &str, b: &str) -> bool {
+    a.eq_ignore_ascii_case(b)
+}
+
+/// Whether `name` is equal to or a subdomain of `parent`
+pub fn is_subdomain(name: &str, parent: &str) -> bool {
+    if parent.is_empty() {
+        // The root contains everything
+        return true;
+    }
+
+    if name.len() < parent.len() {
+        return false;
+    }
+
+    let (prefix, suffix) = name.split_at(name.len() - parent.len());
+
+    names_eq(suffix, parent) && (prefix.is_empty() || prefix.ends_with('.'))
+}
+
+/// What a zone has to say about a query, laid out like the sections of the
+/// response it goes into
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ZoneAnswer {
+    pub rcode: ResultCode,
+
+    /// Referrals to a delegated child zone aren't authoritative
+    pub authoritative: bool,
+    pub answers: Vec<DNSRecord>,
+    pub authority: Vec<DNSRecord>,
+    pub additional: Vec<DNSRecord>,
+}
+
+/// A locally hosted zone
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Zone {
+    /// Name of the zone apex
+    origin: String,
+
+    /// Class of the zone, taken from its SOA record
+    class: RecordClass,
+
+    /// Every record in the zone, including the SOA
+    records: Vec<DNSRecord>,
+}
+
+impl Zone {
+    pub fn new(origin: &str, records: Vec<DNSRecord>) -> Result<Self, String> {
+        if let Some(record) = records.iter().find(|record| ! is_subdomain(&record.name, origin)) {
+            return Err(format!("Zone {}: Record {} is outside of the zone", origin, record.name));
+        }
+
+        let soas = records
+            .iter()
+            .filter(|record| record.rtype == RecordType::SOA)
+            .collect::<Vec<_>>();
+
+        let class = match soas.as_slice() {
+            [soa] if names_eq(&soa.name, origin) => soa.class,
+            [soa] => return Err(format!("Zone {}: SOA record {} is not at the zone apex", origin, soa.name)),
+            soas => return Err(format!("Zone {}: Expected exactly 1 SOA record, got {}", origin, soas.len())),
+        };
+
+        Ok(Self {
+            origin: origin.to_owned(),
+            class,
+            records,
+        })
+    }
+
+    /// Loads a zone from its master file
+    pub fn load(origin: &str, path: &str) -> Result<Self, String> {
+        let origin = normalize_origin(origin);
+        let records = ZoneFileParser::new(&origin).parse_file(path)?;
+
+        Self::new(&origin, records)
+    }
+
+    /// Writes the zone out as a canonical master file
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        ZoneFileWriter::new(&self.origin).write_file(path, &self.records)
+    }
+
+    pub fn origin(&self) -> &str {
+        &self.origin
+    }
+
+    pub fn class(&self) -> RecordClass {
+        self.class
+    }
+
+    pub fn records(&self) -> &[DNSRecord] {
+        &self.records
+    }
+
+    pub(crate) fn records_mut(&mut self) -> &mut Vec<DNSRecord> {
+        &mut self.records
+    }
+
+    pub fn soa(&self) -> &DNSSOARecord {
+        self.records
+            .iter()
+            .find_map(|record| match record.record {
+                DNSRecordData::SOA(ref soa) => Some(soa),
+                _ => None,
+            })
+            .expect("Zone should always have an SOA record")
+    }
+
+    /// Whether `name` falls under this zone's apex
+    pub fn contains(&self, name: &str) -> bool {
+        is_subdomain(name, &self.origin)
+    }
+
+    /// Whether any record is owned by `name`
+    pub fn name_exists(&self, name: &str) -> bool {
+        self.records.iter().any(|record| names_eq(&record.name, name))
+    }
+
+    /// All records owned by `name` of the given type
+    pub fn rrset<'a>(&'a self, name: &'a str, rtype: RecordType) -> impl Iterator<Item = &'a DNSRecord> + 'a {
+        self.records
+            .iter()
+            .filter(move |record| record.rtype == rtype && names_eq(&record.name, name))
+    }
+
+    /// Answers `qname`/`qtype` from the zone's records (RFC 1034 4.3.2),
+    /// following CNAMEs as long as they stay inside the zone
+    pub fn lookup(&self, qname: &str, qtype: RecordType) -> ZoneAnswer {
+        let mut answers = vec![];
+        let mut name = qname.to_owned();
+
+        for _ in 0..MAX_CNAME_CHAIN {
+            if let Some(cut) = self.delegation(&name) {
+                // The rest of the chain is the child zone's business
+                if ! answers.is_empty() {
+                    break;
+                }
+
+                return self.referral(&cut);
+            }
+
+            let rrset = self.rrset(&name, qtype).cloned().collect::<Vec<_>>();
+
+            if ! rrset.is_empty() {
+                answers.extend(rrset);
+                break;
+            }
+
+            let cname = self.rrset(&name, RecordType::CNAME).find_map(|record| match record.record {
+                DNSRecordData::CNAME(ref cname) => Some((record.clone(), cname.cname.clone())),
+                _ => None,
+            });
+
+            match cname {
+                Some((record, target)) if qtype != RecordType::CNAME => {
+                    answers.push(record);
+
+                    // Whoever asked has to chase targets outside of the zone
+                    if ! self.contains(&target) {
+                        break;
+                    }
+
+                    name = target;
+                },
+                _ => {
+                    // Empty non terminals exist, they just don't own anything (RFC 8020)
+                    let exists = self.records.iter().any(|record| is_subdomain(&record.name, &name));
+
+                    return self.negative(answers, if exists { ResultCode::NoError } else { ResultCode::NameError });
+                },
+            }
+        }
+
+        ZoneAnswer {
+            rcode: ResultCode::NoError,
+            authoritative: true,
+            answers,
+            authority: vec![],
+            additional: vec![],
+        }
+    }
+
+    /// The topmost delegation below the apex that `name` falls under
+    fn delegation(&self, name: &str) -> Option<String> {
+        self.records
+            .iter()
+            .filter(|record| record.rtype == RecordType::NS && ! names_eq(&record.name, &self.origin) && is_subdomain(name, &record.name))
+            .min_by_key(|record| record.name.len())
+            .map(|record| record.name.clone())
+    }
+
+    /// Points at the child zone's name servers, along with the addresses of
+    /// the ones that live inside of it
+    fn referral(&self, cut: &str) -> ZoneAnswer {
+        let authority = self.rrset(cut, RecordType::NS).cloned().collect::<Vec<_>>();
+
+        let additional = authority
+            .iter()
+            .filter_map(|record| match record.record {
+                DNSRecordData::NS(ref ns) => Some(ns.nsdname.as_str()),
+                _ => None,
+            })
+            .flat_map(|ns| self.rrset(ns, RecordType::A).chain(self.rrset(ns, RecordType::AAAA)))
+            .cloned()
+            .collect();
+
+        ZoneAnswer {
+            rcode: ResultCode::NoError,
+            authoritative: false,
+            answers: vec![],
+            authority,
+            additional,
+        }
+    }
+
+    /// NODATA or NXDOMAIN, with the SOA so that resolvers know how long they
+    /// may cache it (RFC 2308 3)
+    fn negative(&self, answers: Vec<DNSRecord>, rcode: ResultCode) -> ZoneAnswer {
+        let mut soa = self.rrset(&self.origin, RecordType::SOA)
+            .next()
+            .cloned()
+            .expect("Zone should always have an SOA record");
+        soa.ttl = soa.ttl.min(self.soa().minimum);
+
+        ZoneAnswer {
+            rcode,
+            authoritative: true,
+            answers,
+            authority: vec![soa],
+            additional: vec![],
+        }
+    }
+}
+
+/// The set of zones this server is authoritative for, keyed by their
+/// lowercased origin
+#[derive(Debug, Default)]
+pub struct ZoneStore {
+    zones: RwLock<HashMap<String, Zone>>,
+}
+
+impl ZoneStore {
+    pub fn insert(&self, zone: Zone) {
+        self.write().insert(zone.origin.to_ascii_lowercase(), zone);
+    }
+
+    pub fn get(&self, origin: &str) -> Option<Zone> {
+        self.read().get(&origin.to_ascii_lowercase()).cloned()
+    }
+
+    /// Swaps in freshly loaded zones, dropping the ones that are gone. A zone
+    /// whose serial isn't newer than the one being served is kept as is, so
+    /// dynamic updates survive reloads until the master file is bumped.
+    pub fn replace(&self, zones: Vec<Zone>) {
+        let mut current = self.write();
+        let mut replaced = HashMap::with_capacity(zones.len());
+
+        for zone in zones {
+            let key = zone.origin.to_ascii_lowercase();
+
+            let zone = match current.remove(&key) {
+                Some(old) if ! serial_gt(zone.soa().serial, old.soa().serial) => {
+                    if old != zone {
+                        log!(Info, "Keeping zone {}, serial {} isn't newer than {}", zone.origin, zone.soa().serial, old.soa().serial);
+                    }
+
+                    old
+                },
+                _ => zone,
+            };
+
+            replaced.insert(key, zone);
+        }
+
+        *current = replaced;
+    }
+
+    /// Answers from the closest zone enclosing `qname`, `None` when we don't
+    /// host any
+    pub fn lookup(&self, qname: &str, qtype: RecordType) -> Option<ZoneAnswer> {
+        self.read()
+            .values()
+            .filter(|zone| zone.contains(qname))
+            .max_by_key(|zone| zone.origin.len())
+            .map(|zone| zone.lookup(qname, qtype))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.read().is_empty()
+    }
+
+    fn read(&self) -> RwLockReadGuard<'_, HashMap<String, Zone>> {
+        self.zones.read().expect("Zone store lock should not be poisoned")
+    }
+
+    pub(crate) fn write(&self) -> RwLockWriteGuard<'_, HashMap<String, Zone>> {
+        self.zones.write().expect("Zone store lock should not be poisoned")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::{header::ResultCode, record::DNSRecord, types::RecordType, zone_file::ZoneFileParser};
+
+    use super::{is_subdomain, Zone, ZoneStore};
+
+    const ZONE: &str = "\
+$TTL 3600
+@           IN SOA   ns1 hostmaster 1 3600 600 86400 300
+@           IN NS    ns1
+ns1         IN A     192.0.2.53
+www         IN CNAME web
+web         IN A     192.0.2.80
+ext         IN CNAME www.example.net.
+a.b         IN A     192.0.2.1
+sub         IN NS    ns1.sub
+ns1.sub     IN A     192.0.2.54
+";
+
+    #[test]
+    fn checks_subdomains() {
+        assert!(is_subdomain("www.example.com.", "example.com."));
+        assert!(is_subdomain("Example.COM.", "example.com."));
+        assert!(is_subdomain("example.com.", ""));
+        assert!(! is_subdomain("badexample.com.", "example.com."));
+        assert!(! is_subdomain("com.", "example.com."));
+    }
+
+    fn names(records: &[DNSRecord]) -> Vec<String> {
+        records.iter().map(|record| format!("{} {}", record.name, record.rtype)).collect()
+    }
+
+    #[test]
+    fn answers_from_the_closest_zone() {
+        let store = ZoneStore::default();
+        let records = ZoneFileParser::new("example.com.").parse_str(ZONE, "db.example").unwrap();
+        store.insert(Zone::new("example.com.", records).unwrap());
+
+        assert!(store.lookup("example.org.", RecordType::A).is_none());
+
+        let answer = store.lookup("WWW.example.com.", RecordType::A).unwrap();
+        assert_eq!((answer.rcode, answer.authoritative), (ResultCode::NoError, true));
+        assert_eq!(names(&answer.answers), vec!["www.example.com. CNAME", "web.example.com. A"]);
+
+        // Chains leaving the zone stop at its edge
+        let answer = store.lookup("ext.example.com.", RecordType::A).unwrap();
+        assert_eq!(names(&answer.answers), vec!["ext.example.com. CNAME"]);
+
+        let answer = store.lookup("host.sub.example.com.", RecordType::A).unwrap();
+        assert!(! answer.authoritative);
+        assert_eq!(names(&answer.authority), vec!["sub.example.com. NS"]);
+        assert_eq!(names(&answer.additional), vec!["ns1.sub.example.com. A"]);
+
+        let answer = store.lookup("web.example.com.", RecordType::AAAA).unwrap();
+        assert_eq!(answer.rcode, ResultCode::NoError);
+        assert_eq!(names(&answer.authority), vec!["example.com. SOA"]);
+        assert_eq!(answer.authority[0].ttl, 300);
+
+        assert_eq!(store.lookup("b.example.com.", RecordType::A).unwrap().rcode, ResultCode::NoError);
+        assert_eq!(store.lookup("nope.example.com.", RecordType::A).unwrap().rcode, ResultCode::NameError);
+    }
+
+    #[test]
+    fn keeps_zones_whose_serial_did_not_move_on_replace() {
+        let load = |text: &str| Zone::new("example.com.", ZoneFileParser::new("example.com.").parse_str(text, "db.example").unwrap()).unwrap();
+        let with_serial = |serial: &str| ZONE.replacen(" 1 3600", &format!(" {} 3600", serial), 1);
+
+        let store = ZoneStore::default();
+        store.replace(vec![load(ZONE)]);
+
+        // Stands in for a dynamic update, which bumps the serial in memory only
+        let updated = load(&(with_serial("2") + "new IN A 192.0.2.2\n"));
+        store.insert(updated.clone());
+
+        store.replace(vec![load(&with_serial("2"))]);
+        assert_eq!(store.get("example.com."), Some(updated));
+
+        store.replace(vec![load(&with_serial("3"))]);
+        assert_eq!(store.get("example.com.").unwrap().soa().serial, 3);
+        assert!(store.lookup("new.example.com.", RecordType::A).is_some_and(|answer| answer.answers.is_empty()));
+
+        store.replace(vec![]);
+        assert!(store.is_empty());
+    }
+}